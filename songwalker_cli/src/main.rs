@@ -1,57 +1,340 @@
 //! SongWalker CLI — Compile and render .sw files to WAV.
 //!
 //! Usage:
-//!   songwalker_cli <input.sw> [output.wav]
-//!   songwalker_cli --check <input.sw>
-//!   songwalker_cli --ast <input.sw>
+//!   songwalker_cli render <input.sw> [output.wav|.flac|.raw|-] [--format wav|flac|raw]
+//!                  [--sample-rate HZ] [--bit-depth 16|24|32f] [--dither] [--channels 1|2] [--end-mode gate|release|tail] [--start BEAT] [--end BEAT]
+//!                  [--gain DB | --normalize [DBFS] | --loudness LUFS] [--limit [DBTP]]
+//!                  [--stems [track|instrument]] [--watch]
+//!   songwalker_cli check <input.sw>...
+//!   songwalker_cli ast <input.sw>
+//!   songwalker_cli events <input.sw> [--format json|csv|table] [--track NAME]... [--start BEAT] [--end BEAT]
+//!   songwalker_cli info <input.sw> [--sample-rate HZ]
+//!   songwalker_cli fmt [--check] <input.sw>...
+//!   songwalker_cli midi <input.sw> [output.mid] [--library <index.json>]
+//!   songwalker_cli import <input.mid> [output.sw] [--quantize N] [--strength S]
+//!   songwalker_cli live [input.sw] [--instrument NAME] [--midi-in <device>] [--sample-rate HZ]
+//!
+//! `songwalker_cli <input.sw> [output.wav]` is shorthand for `render`.
 
-use songwalker_core::{compiler, dsp, parse};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use songwalker_core::compiler::{EndMode, Event, EventKind, EventList};
+use songwalker_core::dsp::encode::{EncodeSpec, FileFormat, SampleFormat};
+use songwalker_core::dsp::engine::StemGrouping;
+use songwalker_core::tempo::{DEFAULT_BPM, TempoMap};
+use songwalker_core::{compiler, dsp, format, midi, parse, parse_recovering, preset};
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
 use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Sample rate used when none is given.
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Parser)]
+#[command(name = "songwalker_cli", version, about = "Compile and render SongWalker (.sw) songs")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render a song to WAV, FLAC or raw PCM
+    Render(RenderArgs),
+    /// Check syntax and compile without rendering
+    Check {
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Print the parsed AST
+    Ast { input: String },
+    /// Print the compiled event list as JSON, CSV or a table of notes
+    Events {
+        input: String,
+        #[arg(long, value_enum, default_value = "json")]
+        format: EventsFormat,
+        /// Only notes played by this track, directly or through a call
+        #[arg(long)]
+        track: Vec<String>,
+        /// Only events at or after this beat
+        #[arg(long)]
+        start: Option<f64>,
+        /// Only events before this beat
+        #[arg(long)]
+        end: Option<f64>,
+    },
+    /// Show tempo, length, tracks and presets
+    Info {
+        input: String,
+        /// Sample rate the render length is measured at
+        #[arg(long, default_value_t = DEFAULT_SAMPLE_RATE, value_parser = clap::value_parser!(u32).range(1..))]
+        sample_rate: u32,
+    },
+    /// Format source files in place
+    Fmt {
+        /// List files that would change and exit 1, without writing
+        #[arg(long)]
+        check: bool,
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Export to a Standard MIDI File
+    Midi {
+        input: String,
+        output: Option<String>,
+        /// Preset library index.json, for GM program changes
+        #[arg(long)]
+        library: Option<String>,
+    },
+    /// Convert a MIDI file to .sw source
+    Import {
+        input: String,
+        output: Option<String>,
+        /// Quantize grid in subdivisions per beat (4 = sixteenths)
+        #[arg(long)]
+        quantize: Option<u32>,
+        /// Quantize strength from 0 to 1
+        #[arg(long, default_value_t = 1.0)]
+        strength: f64,
+    },
+    /// Play raw MIDI input as mono f32 PCM on stdout
+    Live {
+        /// Song to take the instrument from
+        input: Option<String>,
+        /// Name of the instrument const to play
+        #[arg(long, requires = "input")]
+        instrument: Option<String>,
+        /// MIDI device or file to read (default stdin)
+        #[arg(long)]
+        midi_in: Option<String>,
+        #[arg(long, default_value_t = DEFAULT_SAMPLE_RATE, value_parser = clap::value_parser!(u32).range(1..))]
+        sample_rate: u32,
+    },
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    input: String,
+    /// Output file, or `-` for stdout
+    output: Option<String>,
+    /// File format (default from the output extension, else WAV)
+    #[arg(long, value_enum)]
+    format: Option<FormatArg>,
+    #[arg(long, default_value_t = DEFAULT_SAMPLE_RATE, value_parser = clap::value_parser!(u32).range(1..))]
+    sample_rate: u32,
+    #[arg(long, value_enum, default_value = "16")]
+    bit_depth: BitDepth,
+    /// Add TPDF dither when writing integer samples
+    #[arg(long)]
+    dither: bool,
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..=2))]
+    channels: u16,
+    /// Override the song's `song.endMode`
+    #[arg(long, value_enum)]
+    end_mode: Option<EndModeArg>,
+    /// Start rendering at this beat
+    #[arg(long)]
+    start: Option<f64>,
+    /// Stop rendering at this beat
+    #[arg(long)]
+    end: Option<f64>,
+    /// Output gain in dB
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    gain: f64,
+    /// Scale so the peak sample hits this level in dBFS
+    #[arg(long, value_name = "DBFS", num_args = 0..=1, default_missing_value = "-1",
+          allow_negative_numbers = true, conflicts_with = "gain")]
+    normalize: Option<f64>,
+    /// Scale to this integrated loudness in LUFS (EBU R128)
+    #[arg(long, value_name = "LUFS", allow_negative_numbers = true,
+          conflicts_with_all = ["gain", "normalize"])]
+    loudness: Option<f64>,
+    /// Limit true peaks to this ceiling in dBTP, in place of soft clipping
+    #[arg(long, value_name = "DBTP", num_args = 0..=1, default_missing_value = "-1",
+          allow_negative_numbers = true)]
+    limit: Option<f64>,
+    /// Also write one file per track or instrument next to the output
+    #[arg(long, value_enum, value_name = "GROUPING", num_args = 0..=1, default_missing_value = "track")]
+    stems: Option<StemArg>,
+    /// Re-render whenever the input file changes
+    #[arg(long)]
+    watch: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EventsFormat {
+    Json,
+    Csv,
+    Table,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Wav,
+    Flac,
+    Raw,
+}
+
+impl From<FormatArg> for FileFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Wav => FileFormat::Wav,
+            FormatArg::Flac => FileFormat::Flac,
+            FormatArg::Raw => FileFormat::Raw,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BitDepth {
+    #[value(name = "16")]
+    Int16,
+    #[value(name = "24")]
+    Int24,
+    #[value(name = "32f")]
+    Float32,
+}
+
+impl From<BitDepth> for SampleFormat {
+    fn from(depth: BitDepth) -> Self {
+        match depth {
+            BitDepth::Int16 => SampleFormat::Int16,
+            BitDepth::Int24 => SampleFormat::Int24,
+            BitDepth::Float32 => SampleFormat::Float32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StemArg {
+    Track,
+    Instrument,
+}
+
+impl From<StemArg> for StemGrouping {
+    fn from(grouping: StemArg) -> Self {
+        match grouping {
+            StemArg::Track => StemGrouping::Track,
+            StemArg::Instrument => StemGrouping::Instrument,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EndModeArg {
+    Gate,
+    Release,
+    Tail,
+}
+
+impl From<EndModeArg> for EndMode {
+    fn from(mode: EndModeArg) -> Self {
+        match mode {
+            EndModeArg::Gate => EndMode::Gate,
+            EndModeArg::Release => EndMode::Release,
+            EndModeArg::Tail => EndMode::Tail,
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        eprintln!("SongWalker CLI v{}", env!("CARGO_PKG_VERSION"));
-        eprintln!();
-        eprintln!("Usage:");
-        eprintln!("  {} <input.sw> [output.wav]   Render to WAV", args[0]);
-        eprintln!("  {} --check <input.sw>        Check syntax only", args[0]);
-        eprintln!("  {} --ast <input.sw>          Print AST", args[0]);
-        process::exit(1);
+    let mut args: Vec<String> = env::args().collect();
+    // `songwalker_cli song.sw [out.wav]` renders, as before subcommands.
+    if args.get(1).is_some_and(|a| a.ends_with(".sw")) {
+        args.insert(1, "render".to_string());
     }
 
-    match args[1].as_str() {
-        "--check" => {
-            if args.len() < 3 {
-                eprintln!("Error: --check requires a file argument");
-                process::exit(1);
+    match Cli::parse_from(args).command {
+        Command::Render(args) => {
+            let extension = match args.format {
+                Some(FormatArg::Flac) => "flac",
+                Some(FormatArg::Raw) => "raw",
+                _ => "wav",
+            };
+            let output = args.output.clone().unwrap_or_else(|| replace_extension(&args.input, extension));
+            if args.watch {
+                cmd_watch(&args, &output);
+            } else {
+                cmd_render(&args, &output);
             }
-            cmd_check(&args[2]);
         }
-        "--ast" => {
-            if args.len() < 3 {
-                eprintln!("Error: --ast requires a file argument");
+        Command::Check { files } => {
+            let mut failed = false;
+            for path in &files {
+                failed |= !cmd_check(path);
+            }
+            if failed {
                 process::exit(1);
             }
-            cmd_ast(&args[2]);
         }
-        _ => {
-            let input = &args[1];
-            let output = if args.len() >= 3 {
-                args[2].clone()
-            } else {
-                // Replace .sw extension with .wav, or append .wav
-                if input.ends_with(".sw") {
-                    format!("{}.wav", &input[..input.len() - 3])
-                } else {
-                    format!("{input}.wav")
-                }
-            };
-            cmd_render(input, &output);
+        Command::Ast { input } => cmd_ast(&input),
+        Command::Events {
+            input,
+            format,
+            track,
+            start,
+            end,
+        } => cmd_events(&input, format, &track, start, end),
+        Command::Info { input, sample_rate } => cmd_info(&input, sample_rate),
+        Command::Fmt { check, files } => cmd_fmt(&files, check),
+        Command::Midi {
+            input,
+            output,
+            library,
+        } => {
+            let output = output.unwrap_or_else(|| replace_extension(&input, "mid"));
+            cmd_midi(&input, &output, library.as_deref());
         }
+        Command::Import {
+            input,
+            output,
+            quantize,
+            strength,
+        } => {
+            let output = output.unwrap_or_else(|| match input.strip_suffix(".mid") {
+                Some(stem) => format!("{stem}.sw"),
+                None => format!("{input}.sw"),
+            });
+            cmd_import(&input, &output, &midi::ImportOptions { quantize, strength });
+        }
+        Command::Live {
+            input,
+            instrument,
+            midi_in,
+            sample_rate,
+        } => cmd_live(input.as_deref(), instrument.as_deref(), midi_in.as_deref(), sample_rate),
+    }
+}
+
+/// Replace a `.sw` extension with `ext`, or append `ext`.
+fn replace_extension(input: &str, ext: &str) -> String {
+    match input.strip_suffix(".sw") {
+        Some(stem) => format!("{stem}.{ext}"),
+        None => format!("{input}.{ext}"),
+    }
+}
+
+/// A level in dB units, or `-inf` for silence.
+fn format_level(db: f64, unit: &str) -> String {
+    if db.is_finite() { format!("{db:.1} {unit}") } else { format!("-inf {unit}") }
+}
+
+/// `out/song.wav` + bus `bass` → `out/song.bass.wav`. Characters that
+/// don't belong in a file name become `_`.
+fn stem_path(output: &str, bus: &str) -> String {
+    let bus: String = bus
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let bus = if bus.is_empty() { "song".to_string() } else { bus };
+    let file_start = output.rfind('/').map_or(0, |i| i + 1);
+    match output.rfind('.').filter(|&i| i > file_start) {
+        Some(dot) => format!("{}.{bus}{}", &output[..dot], &output[dot..]),
+        None => format!("{output}.{bus}"),
     }
 }
 
@@ -65,28 +348,37 @@ fn read_source(path: &str) -> String {
     }
 }
 
-fn cmd_check(path: &str) {
-    let source = read_source(path);
+/// Parse and compile one file, reporting problems. Returns success.
+fn cmd_check(path: &str) -> bool {
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error reading '{path}': {e}");
+            return false;
+        }
+    };
 
-    match parse(&source) {
-        Ok(program) => {
-            match compiler::compile(&program) {
-                Ok(event_list) => {
-                    println!(
-                        "✓ {path}: {} events, {:.1} beats",
-                        event_list.events.len(),
-                        event_list.total_beats,
-                    );
-                }
-                Err(e) => {
-                    eprintln!("Compile error in '{path}': {e}");
-                    process::exit(1);
-                }
-            }
+    // Recover from syntax errors so every problem in the file is reported.
+    let (program, errors) = parse_recovering(&source);
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("Parse error in '{path}': {e}");
+        }
+        return false;
+    }
+
+    match compiler::compile(&program) {
+        Ok(event_list) => {
+            println!(
+                "✓ {path}: {} events, {:.1} beats",
+                event_list.events.len(),
+                event_list.total_beats,
+            );
+            true
         }
         Err(e) => {
-            eprintln!("Parse error in '{path}': {e}");
-            process::exit(1);
+            eprintln!("Compile error in '{path}': {e}");
+            false
         }
     }
 }
@@ -105,47 +397,617 @@ fn cmd_ast(path: &str) {
     }
 }
 
-fn cmd_render(input: &str, output: &str) {
-    let source = read_source(input);
+/// Print the event list, or one row per note, filtered by track and beat
+/// range. A track filter keeps only notes.
+fn cmd_events(path: &str, format: EventsFormat, tracks: &[String], start: Option<f64>, end: Option<f64>) {
+    let mut event_list = compile_or_exit(path);
+    let tempo = TempoMap::from_events(&event_list, DEFAULT_BPM);
+    event_list.events.retain(|e| keep_event(e, tracks, start, end));
+
+    if let EventsFormat::Json = format {
+        match serde_json::to_string_pretty(&event_list) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let header = ["beat", "seconds", "track", "pitch", "midi", "velocity", "gate", "instrument"];
+    let rows: Vec<[String; 8]> = event_list
+        .events
+        .iter()
+        .filter_map(|e| note_row(e, &tempo))
+        .collect();
+
+    if let EventsFormat::Csv = format {
+        println!("{}", header.join(","));
+        for row in &rows {
+            let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+            println!("{}", fields.join(","));
+        }
+        return;
+    }
+
+    // Aligned table: numbers right-aligned, text left-aligned.
+    let numeric = [true, true, false, false, true, true, true, false];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (w, field) in widths.iter_mut().zip(row) {
+            *w = (*w).max(field.chars().count());
+        }
+    }
+    let line = |fields: &[&str]| {
+        let cells: Vec<String> = fields
+            .iter()
+            .zip(widths.iter().zip(numeric))
+            .map(|(f, (&w, right))| if right { format!("{f:>w$}") } else { format!("{f:<w$}") })
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    };
+    line(&header);
+    for row in &rows {
+        line(&row.each_ref().map(String::as_str));
+    }
+}
+
+/// Whether `cmd_events` lists an event: it starts within `start..end`
+/// and, when tracks are given, is a note played by one of them.
+fn keep_event(e: &Event, tracks: &[String], start: Option<f64>, end: Option<f64>) -> bool {
+    let in_range = start.is_none_or(|s| e.time >= s) && end.is_none_or(|end| e.time < end);
+    let in_track = tracks.is_empty()
+        || matches!(&e.kind, EventKind::Note { track, top_track, .. }
+            if tracks.contains(track) || tracks.contains(top_track));
+    in_range && in_track
+}
+
+/// The table row for a note event. Drum names get their GM key.
+fn note_row(e: &Event, tempo: &TempoMap) -> Option<[String; 8]> {
+    match &e.kind {
+        EventKind::Note {
+            pitch,
+            midi,
+            velocity,
+            gate,
+            instrument,
+            track,
+            ..
+        } => Some([
+            format_number(e.time),
+            format!("{:.3}", tempo.seconds_at(e.time)),
+            track.clone(),
+            pitch.clone(),
+            midi.or_else(|| midi::drum_key(pitch)).map_or(String::new(), |m| m.to_string()),
+            format_number(*velocity),
+            format_number(*gate),
+            instrument.label().to_string(),
+        ]),
+        _ => None,
+    }
+}
+
+/// A number without trailing zeros, to three decimals at most.
+fn format_number(n: f64) -> String {
+    let s = format!("{n:.3}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Summarize a song: tempo, length, tracks and presets.
+fn cmd_info(path: &str, sample_rate: u32) {
+    let event_list = compile_or_exit(path);
+    let tempo = TempoMap::from_events(&event_list, DEFAULT_BPM);
+
+    let mut tracks: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut presets: Vec<&str> = Vec::new();
+    let mut time_signature = None;
+    for event in &event_list.events {
+        match &event.kind {
+            EventKind::Note { track, .. } => {
+                *tracks.entry(if track.is_empty() { "(song)" } else { track }).or_default() += 1;
+            }
+            EventKind::PresetRef { name } if !presets.contains(&name.as_str()) => {
+                presets.push(name);
+            }
+            EventKind::SetProperty { target, value, .. }
+                if target == "track.timeSignature" && time_signature.is_none() =>
+            {
+                time_signature = Some(value.as_str());
+            }
+            _ => {}
+        }
+    }
+
+    let bpms: Vec<String> = tempo.changes().iter().map(|c| format_bpm(c.bpm)).collect();
+    let changes = match bpms.len() {
+        1 => String::new(),
+        n => format!(" ({} changes)", n - 1),
+    };
+    let renderer = dsp::engine::AudioEngine::new(sample_rate as f64).renderer(&event_list);
+    let end_mode = match event_list.end_mode {
+        EndMode::Gate => "gate",
+        EndMode::Release => "release",
+        EndMode::Tail => "tail",
+    };
+
+    println!("{path}");
+    println!("  Tempo:    {} BPM{changes}", bpms.join(" → "));
+    if let Some(ts) = time_signature {
+        println!("  Time:     {ts}");
+    }
+    println!(
+        "  Length:   {:.2} beats, {}",
+        event_list.total_beats,
+        format_seconds(tempo.seconds_at(event_list.total_beats)),
+    );
+    println!(
+        "  Render:   {} with end mode '{end_mode}'",
+        format_seconds(renderer.total_samples() as f64 / sample_rate as f64),
+    );
+    println!(
+        "  Events:   {} ({} notes)",
+        event_list.events.len(),
+        tracks.values().sum::<usize>(),
+    );
+    if !tracks.is_empty() {
+        let list: Vec<String> = tracks.iter().map(|(t, n)| format!("{t} ({n})")).collect();
+        println!("  Tracks:   {}", list.join(", "));
+    }
+    if !presets.is_empty() {
+        println!("  Presets:  {}", presets.join(", "));
+    }
+}
+
+fn format_bpm(bpm: f64) -> String {
+    if bpm.fract() == 0.0 {
+        format!("{bpm}")
+    } else {
+        format!("{bpm:.2}")
+    }
+}
+
+/// `m:ss.ss`
+fn format_seconds(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:05.2}", minutes, seconds - minutes * 60.0)
+}
+
+fn compile_file(path: &str) -> Result<EventList, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Error reading '{path}': {e}"))?;
+    let program = parse(&source).map_err(|e| format!("{path}: {e}"))?;
+    compiler::compile(&program).map_err(|e| format!("Compile error in '{path}': {e}"))
+}
+
+fn compile_or_exit(path: &str) -> EventList {
+    compile_file(path).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    })
+}
+
+fn cmd_midi(input: &str, output: &str, library_path: Option<&str>) {
+    let event_list = compile_or_exit(input);
+
+    let library: Option<preset::LibraryIndex> = library_path.map(|path| {
+        match serde_json::from_str(&read_source(path)) {
+            Ok(index) => index,
+            Err(e) => {
+                eprintln!("Error reading library index '{path}': {e}");
+                process::exit(1);
+            }
+        }
+    });
 
-    // Parse
-    let program = match parse(&source) {
+    let smf = midi::export_smf(&event_list, library.as_ref());
+    match fs::write(output, &smf) {
+        Ok(()) => println!("✓ Exported '{input}' → '{output}' ({} bytes)", smf.len()),
+        Err(e) => {
+            eprintln!("Error writing '{output}': {e}");
+            process::exit(1);
+        }
+    }
+}
+
+fn cmd_import(input: &str, output: &str, options: &midi::ImportOptions) {
+    let bytes = match fs::read(input) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Error reading '{input}': {e}");
+            process::exit(1);
+        }
+    };
+    let program = match midi::import_smf(&bytes, options) {
         Ok(p) => p,
         Err(e) => {
-            eprintln!("Parse error: {e}");
+            eprintln!("Import error: {e}");
             process::exit(1);
         }
     };
-
-    // Compile
-    let event_list = match compiler::compile(&program) {
-        Ok(el) => el,
+    let source = format::format_program(&program);
+    match fs::write(output, &source) {
+        Ok(()) => println!("✓ Imported '{input}' → '{output}'"),
         Err(e) => {
-            eprintln!("Compile error: {e}");
+            eprintln!("Error writing '{output}': {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Play raw MIDI bytes from a device (or stdin) through the live engine,
+/// writing mono f32 little-endian samples to stdout, e.g.
+/// `songwalker_cli live --midi-in /dev/snd/midiC1D0 | aplay -f FLOAT_LE -r 44100`.
+/// Stdout backpressure paces rendering; exits once input ends and the
+/// last notes have rung out.
+fn cmd_live(input: Option<&str>, instrument: Option<&str>, device: Option<&str>, rate: u32) {
+    let mut engine = dsp::live::LiveEngine::new(rate as f64);
+    if input.is_some() || instrument.is_some() {
+        let Some(input) = input else {
+            eprintln!("Error: --instrument requires an input .sw file");
             process::exit(1);
+        };
+        let source = read_source(input);
+        let program = match parse(&source) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("Parse error in '{input}': {e}");
+                process::exit(1);
+            }
+        };
+        match dsp::live::instrument_from_program(&program, instrument) {
+            Ok(config) => engine.set_instrument(config),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
         }
+    }
+
+    let mut reader: Box<dyn Read + Send> = match device {
+        Some(path) => match fs::File::open(path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("Error opening '{path}': {e}");
+                process::exit(1);
+            }
+        },
+        None => Box::new(io::stdin()),
     };
 
-    let sample_rate = 44100;
-    let total_beats = event_list.total_beats;
-    let num_events = event_list.events.len();
+    // Reads block, so they happen on their own thread; the render loop
+    // drains whatever has arrived before each block.
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let mut buf = [0u8; 256];
+        while let Ok(n) = reader.read(&mut buf) {
+            if n == 0 || tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
 
-    // Render to WAV
-    let wav_data = dsp::renderer::render_wav(&event_list, sample_rate);
+    eprintln!("♪ Live at {rate} Hz — mono f32le PCM on stdout");
+    let mut stdout = io::stdout().lock();
+    let mut block = [0.0f32; 128];
+    let mut bytes = Vec::with_capacity(block.len() * 4);
+    let mut input_open = true;
+    while input_open || engine.active_voices() > 0 {
+        loop {
+            match rx.try_recv() {
+                Ok(chunk) => engine.feed(&chunk),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    if input_open {
+                        input_open = false;
+                        engine.all_notes_off();
+                    }
+                    break;
+                }
+            }
+        }
+        engine.process(&mut block);
+        bytes.clear();
+        for s in block {
+            bytes.extend_from_slice(&s.to_le_bytes());
+        }
+        if stdout.write_all(&bytes).is_err() {
+            break; // downstream player closed
+        }
+    }
+}
 
-    // Write output
-    match fs::write(output, &wav_data) {
-        Ok(()) => {
-            let duration_sec = total_beats * 60.0 / 120.0; // default BPM for display
-            let size_kb = wav_data.len() / 1024;
-            println!("✓ Rendered '{input}' → '{output}'");
-            println!(
-                "  {num_events} events, {total_beats:.1} beats, ~{duration_sec:.1}s, {size_kb} KB",
-            );
+/// Format files in place. With `check`, only report files that would change
+/// and exit non-zero if there are any.
+fn cmd_fmt(paths: &[String], check: bool) {
+    let mut failed = false;
+    for path in paths {
+        let source = read_source(path);
+        let formatted = match format::format_source(&source) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Parse error in '{path}': {e}");
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
         }
-        Err(e) => {
-            eprintln!("Error writing '{output}': {e}");
-            process::exit(1);
+        if check {
+            println!("Would reformat '{path}'");
+            failed = true;
+        } else if let Err(e) = fs::write(path, &formatted) {
+            eprintln!("Error writing '{path}': {e}");
+            failed = true;
+        } else {
+            println!("✓ Formatted '{path}'");
         }
     }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn cmd_render(args: &RenderArgs, output: &str) {
+    if let Err(e) = render_file(args, output) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+/// Re-render whenever the input file changes, until interrupted. Errors
+/// are reported and the previous output is left in place.
+fn cmd_watch(args: &RenderArgs, output: &str) {
+    let input = &args.input;
+    let modified = || fs::metadata(input).and_then(|m| m.modified()).ok();
+    let mut last = None;
+    println!("Watching '{input}' (Ctrl+C to stop)");
+    loop {
+        let current = modified();
+        if current.is_some() && current != last {
+            last = current;
+            if let Err(e) = render_file(args, output) {
+                eprintln!("{e}");
+            }
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Reject `--start`/`--end` ranges that would render nothing.
+fn check_render_range(start: Option<f64>, end: Option<f64>, total_beats: f64) -> Result<(), String> {
+    if let (Some(start), Some(end)) = (start, end)
+        && start >= end
+    {
+        return Err(format!("--start ({start}) must come before --end ({end})"));
+    }
+    if let Some(start) = start
+        && start >= total_beats
+    {
+        return Err(format!("--start ({start}) is past the end of the song ({total_beats} beats)"));
+    }
+    Ok(())
+}
+
+fn render_file(args: &RenderArgs, output: &str) -> Result<(), String> {
+    let input = &args.input;
+    let mut event_list = compile_file(input)?;
+    check_render_range(args.start, args.end, event_list.total_beats)?;
+    if let Some(mode) = args.end_mode {
+        event_list.end_mode = mode.into();
+    }
+    let sample_rate = args.sample_rate;
+    let engine = dsp::engine::AudioEngine::new(sample_rate as f64);
+
+    if args.stems.is_some() && output == "-" {
+        return Err("Stems can't be written to stdout".to_string());
+    }
+
+    // A fresh renderer positioned on the requested beat range.
+    let renderer = || {
+        let mut renderer = match args.stems {
+            Some(grouping) => engine.stem_renderer(&event_list, grouping.into()),
+            None => engine.renderer(&event_list),
+        };
+        if let Some(end) = args.end {
+            renderer.set_end(renderer.beat_to_sample(end));
+        }
+        if let Some(start) = args.start {
+            renderer.seek(renderer.beat_to_sample(start));
+        }
+        renderer.set_soft_clip(args.limit.is_none());
+        renderer
+    };
+
+    // Normalizing renders twice: once to measure, then for real.
+    // Rendering is deterministic, so memory use stays constant.
+    let gain_db = if let Some(target) = args.normalize {
+        let peak = dsp::renderer::scan_peak(&mut renderer());
+        if peak > 0.0 { target - 20.0 * peak.log10() } else { 0.0 }
+    } else if let Some(target) = args.loudness {
+        let measured = dsp::renderer::scan_loudness(&mut renderer(), args.channels).integrated;
+        if measured.is_finite() { target - measured } else { 0.0 }
+    } else {
+        args.gain
+    };
+    let format = args
+        .format
+        .map(FileFormat::from)
+        .or_else(|| FileFormat::from_path(output))
+        .unwrap_or(FileFormat::Wav);
+    let spec = EncodeSpec {
+        sample_rate,
+        channels: args.channels,
+        sample_format: args.bit_depth.into(),
+        gain: 10f64.powf(gain_db / 20.0),
+        dither: args.dither,
+        limiter: args.limit,
+    };
+
+    // Stream block by block, so memory use doesn't grow with length
+    let mut renderer = renderer();
+    let frames = renderer.total_samples() - renderer.position();
+    let stem_paths: Vec<String> = renderer
+        .buses()
+        .iter()
+        .map(|bus| stem_path(output, bus))
+        .collect();
+    let written = if args.stems.is_some() {
+        let create = |path: &String| fs::File::create(path).map(io::BufWriter::new);
+        fs::File::create(output).map(io::BufWriter::new).and_then(|master| {
+            let stems = stem_paths.iter().map(create).collect::<io::Result<Vec<_>>>()?;
+            dsp::encode::encode_stems(&mut renderer, &spec, format, master, stems)
+        })
+    } else if output == "-" {
+        dsp::encode::encode(&mut renderer, &spec, format, io::BufWriter::new(io::stdout().lock()))
+    } else {
+        fs::File::create(output).and_then(|file| {
+            dsp::encode::encode(&mut renderer, &spec, format, io::BufWriter::new(file))
+        })
+    };
+    let loudness = written.map_err(|e| format!("Error writing '{output}': {e}"))?;
+
+    // Progress goes to stderr when the audio itself is on stdout
+    let duration = frames as f64 / sample_rate as f64;
+    let mut summary = format!(
+        "✓ Rendered '{input}' → '{output}'\n  {} events, {:.1} beats, {}",
+        event_list.events.len(),
+        event_list.total_beats,
+        format_seconds(duration),
+    );
+    if output != "-"
+        && let Ok(meta) = fs::metadata(output)
+    {
+        summary += &format!(", {} KB", meta.len() / 1024);
+    }
+    if gain_db != 0.0 {
+        summary += &format!(", gain {gain_db:+.1} dB");
+    }
+    summary += &format!(
+        "\n  loudness {}, true peak {}",
+        format_level(loudness.integrated, "LUFS"),
+        format_level(loudness.true_peak, "dBTP"),
+    );
+    for path in &stem_paths {
+        summary += &format!("\n  stem → '{path}'");
+    }
+    if output == "-" {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_zero_sample_rate() {
+        for command in ["render", "info", "live"] {
+            let args = ["songwalker_cli", command, "song.sw", "--sample-rate", "0"];
+            assert!(Cli::try_parse_from(args).is_err(), "{command}");
+        }
+        let args = ["songwalker_cli", "render", "song.sw", "--sample-rate", "48000"];
+        assert!(Cli::try_parse_from(args).is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_render_ranges() {
+        assert_eq!(check_render_range(Some(2.0), Some(6.0), 16.0), Ok(()));
+        assert_eq!(check_render_range(None, Some(6.0), 16.0), Ok(()));
+        assert_eq!(
+            check_render_range(Some(8.0), Some(4.0), 16.0),
+            Err("--start (8) must come before --end (4)".to_string())
+        );
+        assert!(check_render_range(Some(4.0), Some(4.0), 16.0).is_err());
+        assert_eq!(
+            check_render_range(Some(20.0), None, 16.0),
+            Err("--start (20) is past the end of the song (16 beats)".to_string())
+        );
+    }
+
+    fn compile_source(source: &str) -> EventList {
+        compiler::compile(&parse(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("lead"), "lead");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\r\nlines"), "\"two\r\nlines\"");
+    }
+
+    #[test]
+    fn formats_numbers_without_trailing_zeros() {
+        assert_eq!(format_number(2.0), "2");
+        assert_eq!(format_number(0.5), "0.5");
+        assert_eq!(format_number(1.0 / 3.0), "0.333");
+        assert_eq!(format_number(0.0), "0");
+    }
+
+    #[test]
+    fn filters_events_by_track_and_range() {
+        let events = compile_source(
+            r#"
+bass();
+drums();
+
+track bass() {
+    C2 /2
+    G2 /2
+    C3 /2
+}
+
+track drums() {
+    Kick /2
+    Snare /2
+}
+"#,
+        );
+        let kept = |tracks: &[&str], start, end| -> Vec<(f64, String)> {
+            let tracks: Vec<String> = tracks.iter().map(|t| t.to_string()).collect();
+            events
+                .events
+                .iter()
+                .filter(|e| keep_event(e, &tracks, start, end))
+                .filter_map(|e| match &e.kind {
+                    EventKind::Note { pitch, .. } => Some((e.time, pitch.clone())),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(kept(&["drums"], None, None), [(1.5, "Kick".into()), (2.0, "Snare".into())]);
+        assert_eq!(kept(&["bass"], Some(0.5), Some(1.0)), [(0.5, "G2".into())]);
+        assert_eq!(kept(&[], Some(1.0), Some(2.0)), [(1.0, "C3".into()), (1.5, "Kick".into())]);
+
+        let others = events.events.iter().filter(|e| !matches!(e.kind, EventKind::Note { .. }));
+        let tracks = ["bass".to_string()];
+        assert!(others.clone().all(|e| !keep_event(e, &tracks, None, None)));
+    }
+
+    #[test]
+    fn drum_rows_show_their_key() {
+        let events = compile_source("drums();\n\ntrack drums() {\n    Kick /2\n    C4 /2\n}\n");
+        let tempo = TempoMap::from_events(&events, DEFAULT_BPM);
+        let midi: Vec<String> = events
+            .events
+            .iter()
+            .filter_map(|e| note_row(e, &tempo))
+            .map(|row| row[4].clone())
+            .collect();
+        assert_eq!(midi, ["36", "60"]);
+    }
 }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::token::Span;
+
 /// A complete SongWalker program.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
@@ -12,31 +14,107 @@ pub enum Statement {
     /// `track name(params) { body }`
     TrackDef {
         name: String,
-        params: Vec<String>,
+        params: Vec<Param>,
         body: Vec<TrackStatement>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
     },
-    /// `name*vel@dur(args) step;`
+    /// `name+semitones*vel@dur(args) step;`
     TrackCall {
         name: String,
+        /// `+7` or `-12`: semitones to transpose the track by.
+        transpose: Option<Expr>,
         velocity: Option<f64>,
         play_duration: Option<DurationExpr>,
+        /// `@~dur`: repeat the track to fill `play_duration`.
+        looped: bool,
         args: Vec<Expr>,
         step: Option<DurationExpr>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
     },
     /// `const name = expr;`
-    ConstDecl { name: String, value: Expr },
+    ConstDecl {
+        name: String,
+        value: Expr,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
     /// `target = value;`
-    Assignment { target: String, value: Expr },
+    Assignment {
+        target: String,
+        value: Expr,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `scale name = [0, 1, 4, 5, 7, 8, 10];` — a scale for `track.key`,
+    /// as semitone steps above the tonic.
+    ScaleDecl {
+        name: String,
+        value: Expr,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `groove mpc = {unit: 1/4, timing: [0, 0.1], velocity: [0, -10]};`
+    /// — a groove template for `track.groove`.
+    GrooveDecl {
+        name: String,
+        value: Expr,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `parallel { a(); b(); }` or `a() & b();` — members start together
+    /// and the block lasts as long as its longest member.
+    Parallel {
+        body: Vec<Statement>,
+        /// Written as an `&` join rather than a `parallel` block.
+        joined: bool,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `at 16: body` — places `body` at a beat counted from the start of
+    /// the enclosing `parallel` or `at` block (the song start at top level).
+    At {
+        beat: DurationExpr,
+        body: Box<Statement>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
     /// `// text`
-    Comment(String),
+    Comment {
+        text: String,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
 }
 
 /// A statement inside a track body.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TrackStatement {
-    /// `C3*vel@audible /step`
+    /// `C3+semitones*vel@audible /step`. Scale degrees keep their source
+    /// text as the pitch: `^3`, `^-1` or `^name`.
     NoteEvent {
         pitch: String,
+        /// `+7` or `-12`: semitones added to the pitch.
+        transpose: Option<Expr>,
         velocity: Option<f64>,
         audible_duration: Option<DurationExpr>,
         step_duration: Option<DurationExpr>,
@@ -55,34 +133,316 @@ pub enum TrackStatement {
         /// Source byte offset (end).
         span_end: usize,
     },
+    /// `'Cmaj7/E'*vel@audible /step` — a chord symbol, voiced by the
+    /// track's chord settings.
+    ChordSymbol {
+        symbol: String,
+        velocity: Option<f64>,
+        audible_duration: Option<DurationExpr>,
+        step_duration: Option<DurationExpr>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
     /// Standalone number = rest for N beats.
-    Rest(DurationExpr),
+    Rest {
+        duration: DurationExpr,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
     /// `target = value;`
-    Assignment { target: String, value: Expr },
-    /// `for (init; cond; update) { body }`
+    Assignment {
+        target: String,
+        value: Expr,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `for (let i = 0; i < 4; i++) { body }`
     ForLoop {
-        init: String,
-        condition: String,
-        update: String,
+        /// The loop variable.
+        variable: String,
+        /// Its value on the first pass.
+        start: Expr,
+        /// Checked before each pass; the loop ends once it is false (0).
+        condition: Expr,
+        update: LoopUpdate,
+        body: Vec<TrackStatement>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `repeat 2 as pass { body } ending 1 { ... } ending 2 { ... }`
+    Repeat {
+        count: u32,
+        /// Variable bound to the pass number (from 1), if named.
+        index: Option<String>,
         body: Vec<TrackStatement>,
+        endings: Vec<Ending>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+    /// `if (cond) { then_body } else { else_body }` — `else if` nests
+    /// another `If` as the only else statement.
+    If {
+        condition: Expr,
+        then_body: Vec<TrackStatement>,
+        else_body: Vec<TrackStatement>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
     },
     /// A track call inside another track.
     TrackCall {
         name: String,
+        /// `+7` or `-12`: semitones to transpose the track by.
+        transpose: Option<Expr>,
         velocity: Option<f64>,
         play_duration: Option<DurationExpr>,
+        /// `@~dur`: repeat the track to fill `play_duration`.
+        looped: bool,
         args: Vec<Expr>,
         step: Option<DurationExpr>,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
     },
     /// `// text`
-    Comment(String),
+    Comment {
+        text: String,
+        /// Source byte offset (start).
+        span_start: usize,
+        /// Source byte offset (end).
+        span_end: usize,
+    },
+}
+
+impl Statement {
+    /// Source range of the whole statement.
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::TrackDef {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::TrackCall {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::ConstDecl {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::ScaleDecl {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::GrooveDecl {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::Assignment {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::Parallel {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::At {
+                span_start,
+                span_end,
+                ..
+            }
+            | Statement::Comment {
+                span_start,
+                span_end,
+                ..
+            } => Span {
+                start: *span_start,
+                end: *span_end,
+            },
+        }
+    }
+}
+
+impl TrackStatement {
+    /// Source range of the whole statement.
+    pub fn span(&self) -> Span {
+        match self {
+            TrackStatement::NoteEvent {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::Chord {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::ChordSymbol {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::Rest {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::Assignment {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::ForLoop {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::Repeat {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::If {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::TrackCall {
+                span_start,
+                span_end,
+                ..
+            }
+            | TrackStatement::Comment {
+                span_start,
+                span_end,
+                ..
+            } => Span {
+                start: *span_start,
+                end: *span_end,
+            },
+        }
+    }
 }
 
 /// A note within a chord.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChordNote {
     pub pitch: String,
+    /// `+7` or `-12`: semitones added to the pitch.
+    pub transpose: Option<Expr>,
     pub audible_duration: Option<DurationExpr>,
+    /// Source byte offset (start).
+    pub span_start: usize,
+    /// Source byte offset (end).
+    pub span_end: usize,
+}
+
+/// A track parameter: `name` or `name: type`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: String,
+    /// Declared type; untyped parameters take the type of their argument.
+    pub ty: Option<ParamType>,
+}
+
+impl Param {
+    pub fn new(name: impl Into<String>) -> Self {
+        Param {
+            name: name.into(),
+            ty: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ty {
+            Some(ty) => write!(f, "{}: {}", self.name, ty.name()),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// The type of a track parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParamType {
+    Instrument,
+    Number,
+    Duration,
+    String,
+    Pitch,
+    Array,
+}
+
+impl ParamType {
+    pub const ALL: [ParamType; 6] = [
+        ParamType::Instrument,
+        ParamType::Number,
+        ParamType::Duration,
+        ParamType::String,
+        ParamType::Pitch,
+        ParamType::Array,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParamType::Instrument => "instrument",
+            ParamType::Number => "number",
+            ParamType::Duration => "duration",
+            ParamType::String => "string",
+            ParamType::Pitch => "pitch",
+            ParamType::Array => "array",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+}
+
+/// How a `for` loop moves its variable after each pass.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LoopUpdate {
+    /// `i++`
+    Increment,
+    /// `i--`
+    Decrement,
+    /// `i += n`
+    Add(Expr),
+    /// `i -= n`
+    Sub(Expr),
+    /// `i = n`
+    Set(Expr),
+}
+
+/// `ending 1, 3 { body }` — played after the repeat body on the listed passes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ending {
+    pub passes: Vec<u32>,
+    pub body: Vec<TrackStatement>,
+    /// Source byte offset (start).
+    pub span_start: usize,
+    /// Source byte offset (end).
+    pub span_end: usize,
 }
 
 /// A duration expression.
@@ -98,9 +458,29 @@ pub enum DurationExpr {
     Dots(usize),
 }
 
-/// A general expression (simplified for Phase 1).
+/// A general expression with its source range.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expr {
+    pub kind: ExprKind,
+    /// Source byte offset (start).
+    pub span_start: usize,
+    /// Source byte offset (end).
+    pub span_end: usize,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span_start: usize, span_end: usize) -> Self {
+        Expr {
+            kind,
+            span_start,
+            span_end,
+        }
+    }
+}
+
+/// The kinds of expression (simplified for Phase 1).
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Expr {
+pub enum ExprKind {
     Number(f64),
     StringLit(String),
     RegexLit(String),
@@ -117,4 +497,70 @@ pub enum Expr {
         property: String,
     },
     DurationLit(DurationExpr),
+    /// `pass % 2 == 0`, `fill && !intro` — only in conditions.
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    /// `!fill` or `-n` — only in conditions.
+    Unary { op: UnaryOp, operand: Box<Expr> },
+    /// `hits[i]` — an item of a list, counting from 0 and wrapping around.
+    Index { target: Box<Expr>, index: Box<Expr> },
+}
+
+/// Binary operators, loosest-binding first.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        }
+    }
+
+    /// Binding strength; higher binds tighter.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Ne => 3,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UnaryOp {
+    Not,
+    Neg,
 }
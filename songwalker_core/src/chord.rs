@@ -0,0 +1,317 @@
+//! Chord symbols — `'Cmaj7/E'`, `'Dm9'`, `'G7b9'` — and their voicings.
+//!
+//! A symbol is a root, a quality from `QUALITIES`, any alterations
+//! (`b5 #5 b9 #9 #11 b13 add9 add11 add13`) and an optional slash bass.
+//! `ChordStyle` holds the track settings that turn it into notes: the
+//! root's octave, inversion, voicing and arpeggio pattern.
+
+/// Chord qualities as semitones above the root. Longer names are matched
+/// first, so `m7b5` wins over `m7` followed by `b5`.
+const QUALITIES: &[(&str, &[i32])] = &[
+    ("", &[0, 4, 7]),
+    ("maj", &[0, 4, 7]),
+    ("M", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("min", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("sus2", &[0, 2, 7]),
+    ("sus", &[0, 5, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("5", &[0, 7]),
+    ("6", &[0, 4, 7, 9]),
+    ("m6", &[0, 3, 7, 9]),
+    ("7", &[0, 4, 7, 10]),
+    ("7sus4", &[0, 5, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("M7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("min7", &[0, 3, 7, 10]),
+    ("mMaj7", &[0, 3, 7, 11]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("aug7", &[0, 4, 8, 10]),
+    ("9", &[0, 4, 7, 10, 14]),
+    ("maj9", &[0, 4, 7, 11, 14]),
+    ("m9", &[0, 3, 7, 10, 14]),
+    ("11", &[0, 4, 7, 10, 14, 17]),
+    ("m11", &[0, 3, 7, 10, 14, 17]),
+    ("13", &[0, 4, 7, 10, 14, 21]),
+    ("maj13", &[0, 4, 7, 11, 14, 21]),
+    ("m13", &[0, 3, 7, 10, 14, 21]),
+];
+
+/// Alterations: a replaced fifth, or an added tone.
+const ALTERATIONS: &[(&str, i32)] = &[
+    ("b5", 6),
+    ("#5", 8),
+    ("b9", 13),
+    ("#9", 15),
+    ("#11", 18),
+    ("b13", 20),
+    ("add9", 14),
+    ("add11", 17),
+    ("add13", 21),
+];
+
+/// A parsed chord symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordSymbol {
+    /// Pitch class of the root (C = 0).
+    pub root: i32,
+    /// Semitones above the root, ascending.
+    pub intervals: Vec<i32>,
+    /// Pitch class of a slash bass.
+    pub bass: Option<i32>,
+}
+
+/// Pitch class of `C`, `Eb`, `F#`, ... with the rest of `text`.
+fn pitch_class(text: &str) -> Option<(i32, &str)> {
+    let base = match text.chars().next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = &text[1..];
+    Some(match rest.chars().next() {
+        Some('b') => ((base + 11) % 12, &rest[1..]),
+        Some('#') => ((base + 1) % 12, &rest[1..]),
+        _ => (base, rest),
+    })
+}
+
+impl ChordSymbol {
+    pub fn parse(text: &str) -> Option<ChordSymbol> {
+        let (symbol, bass) = match text.split_once('/') {
+            Some((symbol, bass)) => match pitch_class(bass)? {
+                (pc, "") => (symbol, Some(pc)),
+                _ => return None,
+            },
+            None => (text, None),
+        };
+        let (root, rest) = pitch_class(symbol)?;
+        let (quality, intervals) = QUALITIES
+            .iter()
+            .filter(|(name, _)| rest.starts_with(name))
+            .max_by_key(|(name, _)| name.len())?;
+
+        let mut intervals = intervals.to_vec();
+        let mut rest = &rest[quality.len()..];
+        while !rest.is_empty() {
+            let (name, tone) = ALTERATIONS.iter().find(|(name, _)| rest.starts_with(name))?;
+            if matches!(*tone, 6 | 8) {
+                intervals.retain(|&i| i != 7);
+            }
+            intervals.push(*tone);
+            rest = &rest[name.len()..];
+        }
+        intervals.sort_unstable();
+        intervals.dedup();
+        Some(ChordSymbol {
+            root,
+            intervals,
+            bass,
+        })
+    }
+
+    /// MIDI keys of the chord, lowest first, with the root in `style.octave`.
+    ///
+    /// Tones up to the seventh sit in close position within the octave
+    /// above the root, and 9ths, 11ths and 13ths in the octave above that.
+    /// Each inversion then moves the lowest tone up an octave;
+    /// drop-2 lowers the second-highest tone an octave and spread raises
+    /// every second tone from the bottom. A slash bass goes below it all.
+    pub fn voice(&self, style: &ChordStyle) -> Vec<i32> {
+        let root = 12 * (style.octave + 1) + self.root;
+        let mut notes: Vec<i32> = self.intervals.iter().map(|i| root + i).collect();
+        notes.sort_unstable();
+        notes.dedup();
+
+        // Every full round of inversions raises the whole chord an octave.
+        let size = notes.len() as u32;
+        let octaves = i32::try_from(style.inversion / size).unwrap_or(i32::MAX);
+        notes.rotate_left((style.inversion % size) as usize);
+        let raised = notes.len() - (style.inversion % size) as usize;
+        for (i, note) in notes.iter_mut().enumerate() {
+            let octaves = if i >= raised { octaves.saturating_add(1) } else { octaves };
+            *note = note.saturating_add(octaves.saturating_mul(12));
+        }
+        match style.voicing {
+            Voicing::Close => {}
+            Voicing::Drop2 => {
+                if notes.len() >= 2 {
+                    let second = notes.len() - 2;
+                    notes[second] -= 12;
+                }
+            }
+            Voicing::Spread => {
+                for note in notes.iter_mut().skip(1).step_by(2) {
+                    *note += 12;
+                }
+            }
+        }
+        notes.sort_unstable();
+
+        if let Some(bass) = self.bass {
+            let below = notes[0] - 1;
+            notes.insert(0, below - (below - bass).rem_euclid(12));
+        }
+        notes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Voicing {
+    #[default]
+    Close,
+    Drop2,
+    Spread,
+}
+
+impl Voicing {
+    pub fn from_name(name: &str) -> Option<Voicing> {
+        match name {
+            "close" => Some(Voicing::Close),
+            "drop2" => Some(Voicing::Drop2),
+            "spread" => Some(Voicing::Spread),
+            _ => None,
+        }
+    }
+}
+
+/// Order in which an arpeggiated chord plays its tones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arpeggio {
+    Up,
+    Down,
+    /// Up, then back down without repeating the top or bottom tone.
+    UpDown,
+    /// Every tone once, in a seeded random order.
+    Random,
+}
+
+impl Arpeggio {
+    pub fn from_name(name: &str) -> Option<Arpeggio> {
+        match name {
+            "up" => Some(Arpeggio::Up),
+            "down" => Some(Arpeggio::Down),
+            "updown" => Some(Arpeggio::UpDown),
+            "random" => Some(Arpeggio::Random),
+            _ => None,
+        }
+    }
+
+    /// Indices into `count` tones (lowest first) in playing order.
+    /// `random` returns values in [0, 1).
+    pub fn order(self, count: usize, mut random: impl FnMut() -> f64) -> Vec<usize> {
+        match self {
+            Arpeggio::Up => (0..count).collect(),
+            Arpeggio::Down => (0..count).rev().collect(),
+            Arpeggio::UpDown => (0..count).chain((1..count.saturating_sub(1)).rev()).collect(),
+            Arpeggio::Random => {
+                let mut order: Vec<usize> = (0..count).collect();
+                for i in (1..count).rev() {
+                    let j = (random() * (i + 1) as f64) as usize;
+                    order.swap(i, j);
+                }
+                order
+            }
+        }
+    }
+}
+
+/// Track settings for chord symbols.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordStyle {
+    /// Octave of the root (`track.chordOctave`).
+    pub octave: i32,
+    /// `track.inversion`.
+    pub inversion: u32,
+    /// `track.voicing`.
+    pub voicing: Voicing,
+    /// `track.arpeggio`; `None` plays the tones together.
+    pub arpeggio: Option<Arpeggio>,
+}
+
+impl Default for ChordStyle {
+    fn default() -> Self {
+        ChordStyle {
+            octave: 4,
+            inversion: 0,
+            voicing: Voicing::Close,
+            arpeggio: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(symbol: &str, style: &ChordStyle) -> Vec<i32> {
+        ChordSymbol::parse(symbol).unwrap().voice(style)
+    }
+
+    #[test]
+    fn parses_symbols() {
+        let chord = |s: &str| ChordSymbol::parse(s).map(|c| (c.root, c.intervals, c.bass));
+        assert_eq!(chord("C"), Some((0, vec![0, 4, 7], None)));
+        assert_eq!(chord("Cmaj7/E"), Some((0, vec![0, 4, 7, 11], Some(4))));
+        assert_eq!(chord("Dm9"), Some((2, vec![0, 3, 7, 10, 14], None)));
+        assert_eq!(chord("Bbm7b5"), Some((10, vec![0, 3, 6, 10], None)));
+        assert_eq!(chord("F#7#9"), Some((6, vec![0, 4, 7, 10, 15], None)));
+        assert_eq!(chord("G7b5"), Some((7, vec![0, 4, 6, 10], None)));
+        assert_eq!(chord("Amadd9"), Some((9, vec![0, 3, 7, 14], None)));
+
+        for bad in ["", "H7", "Cfoo", "C/", "C/E4", "Cmaj7b"] {
+            assert_eq!(chord(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn voicings() {
+        let style = ChordStyle::default();
+        assert_eq!(voice("Dm9", &style), [62, 65, 69, 72, 76]);
+        assert_eq!(voice("Cmaj7/E", &style), [52, 60, 64, 67, 71]);
+
+        // Extensions stay above the seventh instead of folding into a cluster.
+        assert_eq!(voice("C9", &style), [60, 64, 67, 70, 74]);
+        assert_eq!(voice("C11", &style), [60, 64, 67, 70, 74, 77]);
+        assert_eq!(voice("C13", &style), [60, 64, 67, 70, 74, 81]);
+        assert_eq!(voice("C7b9", &style), [60, 64, 67, 70, 73]);
+
+        let first = ChordStyle { inversion: 1, ..style.clone() };
+        assert_eq!(voice("C", &first), [64, 67, 72]);
+
+        // Inversions past the chord size carry into the next octave.
+        let fourth = ChordStyle { inversion: 4, ..style.clone() };
+        assert_eq!(voice("C", &fourth), [76, 79, 84]);
+        let huge = ChordStyle { inversion: u32::MAX, ..style.clone() };
+        assert!(voice("C", &huge).iter().all(|&k| k > 127));
+
+        let drop2 = ChordStyle { voicing: Voicing::Drop2, ..style.clone() };
+        assert_eq!(voice("Cmaj7", &drop2), [55, 60, 64, 71]);
+
+        let spread = ChordStyle { voicing: Voicing::Spread, octave: 3, ..style };
+        assert_eq!(voice("Cmaj7", &spread), [48, 55, 64, 71]);
+    }
+
+    #[test]
+    fn arpeggio_orders() {
+        let never = || unreachable!();
+        assert_eq!(Arpeggio::Up.order(3, never), [0, 1, 2]);
+        assert_eq!(Arpeggio::Down.order(3, never), [2, 1, 0]);
+        assert_eq!(Arpeggio::UpDown.order(4, never), [0, 1, 2, 3, 2, 1]);
+        assert_eq!(Arpeggio::UpDown.order(1, never), [0]);
+
+        let mut order = Arpeggio::Random.order(4, || 0.0);
+        assert_eq!(order, [1, 2, 3, 0]);
+        order.sort_unstable();
+        assert_eq!(order, [0, 1, 2, 3]);
+    }
+}
@@ -1,27 +1,27 @@
+use std::cell::Cell;
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 use crate::ast::*;
+use crate::chord::{Arpeggio, ChordStyle, ChordSymbol, Voicing};
+use crate::groove::{Feel, Groove};
+use crate::scale::{self, Key};
+use crate::token::Span;
 
 // ── Song End Mode ───────────────────────────────────────────
 
 /// Controls how the engine determines the total output length.
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub enum EndMode {
     /// Hard cut when the last note's gate ends (note-off).
     Gate,
     /// Wait for all envelope releases to finish.
     Release,
     /// Wait for all notes and effects to finish (default).
+    #[default]
     Tail,
 }
 
-impl Default for EndMode {
-    fn default() -> Self {
-        EndMode::Tail
-    }
-}
-
 // ── Instrument Configuration ────────────────────────────────
 
 /// Built-in instrument configuration resolved at compile time.
@@ -65,6 +65,21 @@ impl Default for InstrumentConfig {
     }
 }
 
+impl InstrumentConfig {
+    /// An oscillator with default settings: 'triangle', 'square', etc.
+    pub fn waveform(waveform: impl Into<String>) -> Self {
+        InstrumentConfig {
+            waveform: waveform.into(),
+            ..InstrumentConfig::default()
+        }
+    }
+
+    /// Short display name: the preset name, else the waveform.
+    pub fn label(&self) -> &str {
+        self.preset_ref.as_deref().unwrap_or(&self.waveform)
+    }
+}
+
 // ── Event List (Compiler Output) ────────────────────────────
 
 /// The compiled output: a flat list of timed events.
@@ -91,11 +106,18 @@ pub enum EventKind {
     /// Play a note.
     Note {
         pitch: String,
+        /// MIDI key of a pitched note after transposition (`None` for
+        /// drum names).
+        midi: Option<u8>,
         velocity: f64,
         /// Audible gate time in beats (how long the note sounds).
         gate: f64,
         /// Instrument configuration for this note.
         instrument: InstrumentConfig,
+        /// Name of the track whose body played the note (innermost call).
+        track: String,
+        /// Name of the top-level track call the note belongs to.
+        top_track: String,
         /// Source byte offset (for editor highlighting).
         source_start: usize,
         /// Source byte end offset.
@@ -107,9 +129,20 @@ pub enum EventKind {
         velocity: Option<f64>,
         play_duration: Option<f64>,
         args: Vec<String>,
+        /// Source byte offset of the track call (start).
+        source_start: usize,
+        /// Source byte end offset.
+        source_end: usize,
     },
     /// Set a property.
-    SetProperty { target: String, value: String },
+    SetProperty {
+        target: String,
+        value: String,
+        /// Source byte offset of the assignment (start).
+        source_start: usize,
+        /// Source byte end offset.
+        source_end: usize,
+    },
     /// Preset reference (for compile-time extraction / preloading).
     PresetRef { name: String },
 }
@@ -117,6 +150,7 @@ pub enum EventKind {
 // ── Compiler ────────────────────────────────────────────────
 
 /// Compile context: tracks state during compilation.
+#[derive(Clone)]
 struct CompileCtx {
     /// Default note length in beats (e.g., 1/4 = 0.25).
     default_note_length: f64,
@@ -124,43 +158,330 @@ struct CompileCtx {
     end_mode: EndMode,
     /// Current instrument configuration (default = Triangle).
     current_instrument: InstrumentConfig,
+    /// Semitones added to every pitched note (`track.transpose`).
+    transpose: i32,
+    /// Key for degree notes and diatonic transposition (`track.key`).
+    key: Option<Key>,
+    /// Scale steps every pitched note moves within the key
+    /// (`track.diatonicTranspose`).
+    diatonic_transpose: i32,
+    /// Octave, inversion, voicing and arpeggio for chord symbols.
+    chord_style: ChordStyle,
+    /// Swing, groove and humanize applied to emitted notes.
+    feel: Feel,
+    /// Random source for seeded choices (`song.seed`).
+    rng: Rng,
     /// Current cursor position in beats.
     cursor: f64,
+    /// Furthest beat the cursor has reached before an `at` moved it back.
+    end: f64,
+    /// Start of the enclosing `parallel` or `at` block; `at` positions
+    /// are measured from here.
+    origin: f64,
     /// Collected events.
     events: Vec<Event>,
+    /// The beat each event was emitted at, before the feel moved it;
+    /// parallel to `events`.
+    beats: Vec<f64>,
     /// Track definitions available for lookup.
     track_defs: Vec<TrackDef>,
     /// Song-level const bindings: `const name = Oscillator({...})`.
     consts: HashMap<String, InstrumentConfig>,
-    /// Active parameter bindings during track body compilation.
-    param_bindings: HashMap<String, InstrumentConfig>,
+    /// Song-level scales: `scale name = [0, 2, 3, ...]`.
+    scales: HashMap<String, Vec<i32>>,
+    /// Song-level groove templates: `groove name = {...}`.
+    grooves: HashMap<String, Groove>,
+    /// Track parameters and loop variables in scope.
+    variables: HashMap<String, Value>,
+    /// Name of the track currently being inlined.
+    current_track: String,
+    /// Name of the top-level track call being inlined.
+    top_track: String,
+    /// Span of the innermost statement that failed, set as its error
+    /// propagates.
+    error_span: Option<Span>,
+}
+
+/// A `CompileCtx` saved without its `Shared` data, plus how many events
+/// it had.
+struct Checkpoint {
+    ctx: CompileCtx,
+    events: usize,
+}
+
+/// The song-wide parts of a `CompileCtx`. A statement only appends events
+/// and only adds definitions once it has compiled, so checkpoints leave
+/// these in place instead of copying them.
+struct Shared {
+    events: Vec<Event>,
+    beats: Vec<f64>,
+    track_defs: Vec<TrackDef>,
+    consts: HashMap<String, InstrumentConfig>,
+    scales: HashMap<String, Vec<i32>>,
+    grooves: HashMap<String, Groove>,
+}
+
+/// Seeded xorshift generator, so random choices come out the same on
+/// every compile of a song. The state is a `Cell` so expressions can draw
+/// from it while evaluating against a shared `CompileCtx`.
+#[derive(Clone)]
+struct Rng(Cell<u64>);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Splitmix the seed so neighbouring seeds diverge; xorshift needs
+        // a non-zero state.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng(Cell::new((z ^ (z >> 31)).max(1)))
+    }
+
+    /// Next value in [0, 1).
+    fn random(&self) -> f64 {
+        let mut x = self.0.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
 }
 
+#[derive(Clone)]
 struct TrackDef {
     name: String,
-    params: Vec<String>,
+    params: Vec<Param>,
     body: Vec<TrackStatement>,
 }
 
+/// A value bound to a track parameter or variable.
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    /// A duration in beats.
+    Duration(f64),
+    String(String),
+    Pitch(String),
+    Instrument(InstrumentConfig),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Duration(_) => "a duration",
+            Value::String(_) => "a string",
+            Value::Pitch(_) => "a pitch",
+            Value::Instrument(_) => "an instrument",
+            Value::Array(_) => "an array",
+        }
+    }
+
+    /// Convert to a parameter's declared type, if compatible.
+    fn coerce(self, ty: ParamType) -> Option<Value> {
+        match (ty, self) {
+            (ParamType::Number, v @ Value::Number(_))
+            | (ParamType::Duration, v @ Value::Duration(_))
+            | (ParamType::String, v @ Value::String(_))
+            | (ParamType::Pitch, v @ Value::Pitch(_))
+            | (ParamType::Instrument, v @ Value::Instrument(_))
+            | (ParamType::Array, v @ Value::Array(_)) => Some(v),
+            (ParamType::Duration, Value::Number(n)) => Some(Value::Duration(n)),
+            (ParamType::Pitch, Value::String(s)) if is_pitch(&s) => Some(Value::Pitch(s)),
+            (ParamType::Instrument, Value::String(s)) => {
+                Some(Value::Instrument(InstrumentConfig::waveform(s)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Evaluate `expr` as a whole number: a transpose amount or a property
+/// such as `track.inversion`. `what` names it in the error.
+fn whole_number(ctx: &CompileCtx, what: &str, expr: &Expr) -> Result<i32, String> {
+    let n = evaluate_number(ctx, expr)?;
+    if n.fract() != 0.0 {
+        return Err(format!("{what} must be a whole number, got {n}."));
+    }
+    Ok(n as i32)
+}
+
+/// The number of a degree note written `^{text}`: a whole number or a
+/// bound number, optionally negated.
+fn degree_number(ctx: &CompileCtx, text: &str) -> Result<i32, String> {
+    let (sign, body) = match text.strip_prefix('-') {
+        Some(body) => (-1, body),
+        None => (1, text),
+    };
+    let n = match body.parse::<f64>() {
+        Ok(n) => n,
+        Err(_) => match ctx.variables.get(body) {
+            Some(Value::Number(n)) => *n,
+            _ => return Err(format!("Unknown scale degree '^{text}'.")),
+        },
+    };
+    if n.fract() != 0.0 {
+        return Err(format!("Scale degree '^{text}' must be a whole number, got {n}."));
+    }
+    Ok(sign * n as i32)
+}
+
+/// Resolve `pitch` to the played pitch and its MIDI key.
+///
+/// Degree notes are looked up in `track.key`, and their `interval` counts
+/// scale steps. Named pitches move by `track.diatonicTranspose` steps
+/// within the key, then by `track.transpose` and their `interval` in
+/// semitones. Drum names are left alone and cannot take an interval.
+fn resolve_pitch(
+    ctx: &CompileCtx,
+    pitch: String,
+    interval: Option<i32>,
+) -> Result<(String, Option<u8>), String> {
+    let interval = interval.unwrap_or(0);
+    let written = crate::dsp::engine::note_to_midi(&pitch);
+    let key = if let Some(degree) = pitch.strip_prefix('^') {
+        let scale = ctx
+            .key
+            .as_ref()
+            .ok_or_else(|| format!("Degree note '{pitch}' needs track.key to be set."))?;
+        let degree = degree_number(ctx, degree)? + interval + ctx.diatonic_transpose;
+        scale.degree(degree) + ctx.transpose
+    } else if let Some(key) = written {
+        let key = match (&ctx.key, ctx.diatonic_transpose) {
+            (_, 0) => key,
+            (Some(scale), steps) => scale.shift(key, steps),
+            (None, _) => return Err("track.diatonicTranspose needs track.key to be set.".into()),
+        };
+        key + ctx.transpose + interval
+    } else if interval != 0 {
+        return Err(format!("Cannot transpose '{pitch}': it is not a pitch."));
+    } else {
+        return Ok((pitch, None));
+    };
+
+    let midi = u8::try_from(key).ok().filter(|k| *k <= 127);
+    if written == Some(key) {
+        return Ok((pitch, midi));
+    }
+    match midi {
+        Some(k) => Ok((crate::midi::midi_to_note(k), Some(k))),
+        None => Err(format!("'{pitch}' falls outside the MIDI range.")),
+    }
+}
+
+/// The pitches a note statement naming `name` plays: a pitch, or every
+/// pitch of an array.
+fn value_pitches(name: &str, value: &Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Pitch(p) => Ok(vec![p.clone()]),
+        Value::String(s) if is_pitch(s) => Ok(vec![s.clone()]),
+        Value::Array(items) => {
+            let mut pitches = Vec::new();
+            for item in items {
+                pitches.extend(value_pitches(name, item)?);
+            }
+            Ok(pitches)
+        }
+        other => Err(format!("'{name}' is {}, not a pitch.", other.type_name())),
+    }
+}
+
+/// A note name (`C4`, `F#3`), scale degree (`^3`) or drum name (`Kick`).
+fn is_pitch(name: &str) -> bool {
+    name.starts_with('^')
+        || crate::dsp::engine::note_to_midi(name).is_some()
+        || crate::midi::drum_key(name).is_some()
+}
+
 impl CompileCtx {
     fn new(_strict: bool) -> Self {
         CompileCtx {
             default_note_length: 1.0, // default: 1 beat
             end_mode: EndMode::Tail,
             current_instrument: InstrumentConfig::default(),
+            transpose: 0,
+            key: None,
+            diatonic_transpose: 0,
+            chord_style: ChordStyle::default(),
+            feel: Feel::default(),
+            rng: Rng::new(0),
             cursor: 0.0,
+            end: 0.0,
+            origin: 0.0,
             events: Vec::new(),
+            beats: Vec::new(),
             track_defs: Vec::new(),
             consts: HashMap::new(),
-            param_bindings: HashMap::new(),
+            scales: HashMap::new(),
+            grooves: HashMap::new(),
+            variables: HashMap::new(),
+            current_track: String::new(),
+            top_track: String::new(),
+            error_span: None,
         }
     }
 
-    fn emit(&mut self, kind: EventKind) {
-        self.events.push(Event {
-            time: self.cursor,
-            kind,
-        });
+    /// Push an event at the cursor. Notes are moved off the grid by the
+    /// track's feel.
+    fn emit(&mut self, mut kind: EventKind) {
+        let mut time = self.cursor;
+        if let EventKind::Note { velocity, .. } = &mut kind {
+            (time, *velocity) = self.feel.place(time, *velocity, || self.rng.random());
+        }
+        self.push(self.cursor, Event { time, kind });
+    }
+
+    /// Push an event that was placed at grid position `beat`.
+    fn push(&mut self, beat: f64, event: Event) {
+        self.events.push(event);
+        self.beats.push(beat);
+    }
+
+    /// Save the per-statement state, so a failing statement can be rolled
+    /// back with `restore`.
+    fn checkpoint(&mut self) -> Checkpoint {
+        let shared = self.take_shared();
+        let ctx = self.clone();
+        let events = shared.events.len();
+        self.put_shared(shared);
+        Checkpoint { ctx, events }
+    }
+
+    /// Return to `checkpoint`, dropping the events emitted since.
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        let mut shared = self.take_shared();
+        shared.events.truncate(checkpoint.events);
+        shared.beats.truncate(checkpoint.events);
+        *self = checkpoint.ctx;
+        self.put_shared(shared);
+    }
+
+    /// Record `span` as where an error came from, unless a statement
+    /// nested inside it already has.
+    fn fail_at(&mut self, span: Span) {
+        self.error_span.get_or_insert(span);
+    }
+
+    fn take_shared(&mut self) -> Shared {
+        Shared {
+            events: std::mem::take(&mut self.events),
+            beats: std::mem::take(&mut self.beats),
+            track_defs: std::mem::take(&mut self.track_defs),
+            consts: std::mem::take(&mut self.consts),
+            scales: std::mem::take(&mut self.scales),
+            grooves: std::mem::take(&mut self.grooves),
+        }
+    }
+
+    fn put_shared(&mut self, shared: Shared) {
+        self.events = shared.events;
+        self.beats = shared.beats;
+        self.track_defs = shared.track_defs;
+        self.consts = shared.consts;
+        self.scales = shared.scales;
+        self.grooves = shared.grooves;
     }
 
     fn resolve_duration(&self, dur: &Option<DurationExpr>) -> f64 {
@@ -182,13 +503,14 @@ fn duration_to_beats(dur: &DurationExpr, default: f64) -> f64 {
 }
 
 fn expr_to_string(expr: &Expr) -> String {
-    match expr {
-        Expr::Identifier(s) => s.clone(),
-        Expr::StringLit(s) => s.clone(),
-        Expr::Number(n) => format!("{n}"),
-        Expr::RegexLit(s) => s.clone(),
-        Expr::FunctionCall { function, .. } => format!("{function}(...)"),
-        _ => format!("{expr:?}"),
+    match &expr.kind {
+        ExprKind::Identifier(s) => s.clone(),
+        ExprKind::StringLit(s) => s.clone(),
+        ExprKind::Number(n) => format!("{n}"),
+        ExprKind::RegexLit(s) => s.clone(),
+        ExprKind::FunctionCall { function, .. } => format!("{function}(...)"),
+        ExprKind::DurationLit(d) => crate::format::format_duration(d),
+        _ => crate::format::format_expr(expr),
     }
 }
 
@@ -208,12 +530,66 @@ pub fn compile_strict(program: &Program) -> Result<EventList, String> {
     compile_inner(program, true)
 }
 
+/// Compile a possibly partial Program (e.g. from `crate::parse_recovering`),
+/// skipping top-level statements that fail to compile.
+///
+/// A failing statement is rolled back — its events and scope changes are
+/// discarded — so the valid tracks still produce a playable EventList.
+/// Returns the events together with every compile error, in source order,
+/// each paired with the span of the innermost statement that failed.
+pub fn compile_recovering(program: &Program) -> (EventList, Vec<(String, Span)>) {
+    let mut ctx = CompileCtx::new(false);
+    collect_track_defs(&mut ctx, program);
+
+    let mut errors = Vec::new();
+    for stmt in &program.statements {
+        let checkpoint = ctx.checkpoint();
+        if let Err(e) = compile_statement(&mut ctx, stmt) {
+            let span = ctx.error_span.take().unwrap_or(stmt.span());
+            ctx.restore(checkpoint);
+            errors.push((e, span));
+        }
+    }
+
+    (finish(ctx), errors)
+}
+
+/// Resolve every song-level `const` to its instrument configuration.
+///
+/// Consts that fail to resolve are skipped. Used by editor tooling to
+/// describe a const without compiling the whole song.
+pub fn resolve_consts(program: &Program) -> HashMap<String, InstrumentConfig> {
+    let mut ctx = CompileCtx::new(false);
+    for stmt in &program.statements {
+        if let Statement::ConstDecl { name, value, .. } = stmt
+            && let Ok(config) = evaluate_instrument_expr(&ctx, value)
+        {
+            ctx.consts.insert(name.clone(), config);
+        }
+    }
+    ctx.consts
+}
+
 fn compile_inner(program: &Program, strict: bool) -> Result<EventList, String> {
     let mut ctx = CompileCtx::new(strict);
 
     // First pass: collect track definitions.
+    collect_track_defs(&mut ctx, program);
+
+    // Second pass: compile top-level statements.
+    for stmt in &program.statements {
+        compile_statement(&mut ctx, stmt)?;
+    }
+
+    Ok(finish(ctx))
+}
+
+fn collect_track_defs(ctx: &mut CompileCtx, program: &Program) {
     for stmt in &program.statements {
-        if let Statement::TrackDef { name, params, body } = stmt {
+        if let Statement::TrackDef {
+            name, params, body, ..
+        } = stmt
+        {
             ctx.track_defs.push(TrackDef {
                 name: name.clone(),
                 params: params.clone(),
@@ -221,19 +597,17 @@ fn compile_inner(program: &Program, strict: bool) -> Result<EventList, String> {
             });
         }
     }
+}
 
-    // Second pass: compile top-level statements.
-    for stmt in &program.statements {
-        compile_statement(&mut ctx, stmt)?;
-    }
-
+/// Sort the collected events and package them as an EventList.
+fn finish(mut ctx: CompileCtx) -> EventList {
     ctx.events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
 
-    Ok(EventList {
-        total_beats: ctx.cursor,
+    EventList {
+        total_beats: ctx.cursor.max(ctx.end),
         events: ctx.events,
         end_mode: ctx.end_mode,
-    })
+    }
 }
 
 fn compile_statement(ctx: &mut CompileCtx, stmt: &Statement) -> Result<(), String> {
@@ -244,78 +618,204 @@ fn compile_statement(ctx: &mut CompileCtx, stmt: &Statement) -> Result<(), Strin
         }
         Statement::TrackCall {
             name,
+            transpose,
             velocity,
             play_duration,
+            looped,
             args,
             step,
-        } => {
-            inline_track_call(ctx, name, velocity, play_duration, args, step)
-        }
-        Statement::ConstDecl { name, value } => {
+            span_start,
+            span_end,
+        } => inline_track_call(
+            ctx,
+            Call {
+                name,
+                transpose,
+                velocity,
+                play_duration,
+                looped: *looped,
+                args,
+                step,
+                span: Span {
+                    start: *span_start,
+                    end: *span_end,
+                },
+            },
+        ),
+        Statement::ConstDecl { name, value, .. } => {
             // Resolve the expression to an InstrumentConfig and store it.
             let config = evaluate_instrument_expr(ctx, value)?;
             // Emit a PresetRef event if this references an external preset.
             if let Some(ref preset_name) = config.preset_ref {
-                ctx.events.push(Event {
-                    time: 0.0,
-                    kind: EventKind::PresetRef {
-                        name: preset_name.clone(),
+                ctx.push(
+                    0.0,
+                    Event {
+                        time: 0.0,
+                        kind: EventKind::PresetRef {
+                            name: preset_name.clone(),
+                        },
                     },
-                });
+                );
             }
             ctx.consts.insert(name.clone(), config);
             Ok(())
         }
-        Statement::Assignment { target, value } => {
-            compile_assignment(ctx, target, value)
+        Statement::ScaleDecl { name, value, .. } => {
+            let steps = match evaluate_value(ctx, value)? {
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::Number(n) if n.fract() == 0.0 => Some(*n as i32),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>(),
+                _ => None,
+            };
+            match steps {
+                Some(steps) if scale::is_scale(&steps) => {
+                    ctx.scales.insert(name.clone(), steps);
+                    Ok(())
+                }
+                _ => Err(format!(
+                    "Scale '{name}' must list semitone steps from 0 to 11 in rising order, \
+                     e.g. [0, 2, 3, 5, 7, 8, 10]."
+                )),
+            }
+        }
+        Statement::GrooveDecl { name, value, .. } => {
+            let groove = evaluate_groove(ctx, name, value)?;
+            ctx.grooves.insert(name.clone(), groove);
+            Ok(())
+        }
+        Statement::Assignment {
+            target,
+            value,
+            span_start,
+            span_end,
+        } => compile_assignment(
+            ctx,
+            target,
+            value,
+            Span {
+                start: *span_start,
+                end: *span_end,
+            },
+        ),
+        Statement::Parallel { body, .. } => {
+            // Every member starts at the block start; the block lasts as
+            // long as its longest member.
+            let start = ctx.cursor;
+            let outer = std::mem::replace(&mut ctx.origin, start);
+            let mut length = 0.0_f64;
+            for member in body {
+                ctx.cursor = start;
+                compile_statement(ctx, member).inspect_err(|_| ctx.fail_at(member.span()))?;
+                length = length.max(ctx.cursor - start);
+                ctx.end = ctx.end.max(ctx.cursor);
+            }
+            ctx.origin = outer;
+            ctx.cursor = start + length;
+            Ok(())
+        }
+        Statement::At { beat, body, .. } => {
+            // Beats count from the enclosing block, so a positioned section
+            // moves with everything placed inside it.
+            ctx.end = ctx.end.max(ctx.cursor);
+            ctx.cursor = ctx.origin + duration_to_beats(beat, ctx.default_note_length);
+            let outer = std::mem::replace(&mut ctx.origin, ctx.cursor);
+            let result = compile_statement(ctx, body).inspect_err(|_| ctx.fail_at(body.span()));
+            ctx.origin = outer;
+            result
+        }
+        Statement::Comment { .. } => Ok(()),
+    }
+}
+
+/// Evaluate `{unit: 1/4, timing: [...], velocity: [...]}` to a groove.
+fn evaluate_groove(ctx: &CompileCtx, name: &str, expr: &Expr) -> Result<Groove, String> {
+    let ExprKind::ObjectLit(pairs) = &expr.kind else {
+        return Err(format!(
+            "Groove '{name}' must be an object such as {{unit: 1/4, timing: [0, 0.1], velocity: [0, -10]}}."
+        ));
+    };
+    let numbers = |key: &str, value: &Expr| match evaluate_value(ctx, value)? {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Number(n) => Some(*n),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("Groove '{name}' {key} must be a list of numbers.")),
+        _ => Err(format!("Groove '{name}' {key} must be a list of numbers.")),
+    };
+
+    let mut groove = Groove {
+        unit: 0.25,
+        timing: Vec::new(),
+        velocity: Vec::new(),
+    };
+    for (key, value) in pairs {
+        match key.as_str() {
+            "unit" => groove.unit = evaluate_number(ctx, value)?,
+            "timing" => groove.timing = numbers(key, value)?,
+            "velocity" => groove.velocity = numbers(key, value)?,
+            _ => {
+                return Err(format!(
+                    "Groove '{name}' has no setting '{key}'. Expected unit, timing or velocity."
+                ));
+            }
         }
-        Statement::Comment(_) => Ok(()),
     }
+    if groove.unit <= 0.0 {
+        return Err(format!("Groove '{name}' unit must be longer than 0."));
+    }
+    Ok(groove)
 }
 
 /// Evaluate an expression to an InstrumentConfig.
 fn evaluate_instrument_expr(ctx: &CompileCtx, expr: &Expr) -> Result<InstrumentConfig, String> {
-    match expr {
-        Expr::FunctionCall { function, args } => {
+    match &expr.kind {
+        ExprKind::FunctionCall { function, args } => {
             match function.as_str() {
                 "Oscillator" => {
                     let mut config = InstrumentConfig::default();
                     // First arg should be an ObjectLit with config keys.
-                    if let Some(Expr::ObjectLit(pairs)) = args.first() {
+                    if let Some(ExprKind::ObjectLit(pairs)) = args.first().map(|e| &e.kind) {
                         for (key, value) in pairs {
                             match key.as_str() {
                                 "type" => {
-                                    if let Expr::StringLit(s) = value {
+                                    if let ExprKind::StringLit(s) = &value.kind {
                                         config.waveform = s.clone();
                                     }
                                 }
                                 "attack" => {
-                                    if let Expr::Number(n) = value {
+                                    if let ExprKind::Number(n) = &value.kind {
                                         config.attack = Some(*n);
                                     }
                                 }
                                 "decay" => {
-                                    if let Expr::Number(n) = value {
+                                    if let ExprKind::Number(n) = &value.kind {
                                         config.decay = Some(*n);
                                     }
                                 }
                                 "sustain" => {
-                                    if let Expr::Number(n) = value {
+                                    if let ExprKind::Number(n) = &value.kind {
                                         config.sustain = Some(*n);
                                     }
                                 }
                                 "release" => {
-                                    if let Expr::Number(n) = value {
+                                    if let ExprKind::Number(n) = &value.kind {
                                         config.release = Some(*n);
                                     }
                                 }
                                 "detune" => {
-                                    if let Expr::Number(n) = value {
+                                    if let ExprKind::Number(n) = &value.kind {
                                         config.detune = Some(*n);
                                     }
                                 }
                                 "mixer" => {
-                                    if let Expr::Number(n) = value {
+                                    if let ExprKind::Number(n) = &value.kind {
                                         config.mixer = Some(*n);
                                     }
                                 }
@@ -330,46 +830,46 @@ fn evaluate_instrument_expr(ctx: &CompileCtx, expr: &Expr) -> Result<InstrumentC
                     // Currently produces a default config; runtime preloading
                     // uses extract_preset_refs() to discover references.
                     let mut config = InstrumentConfig::default();
-                    if let Some(Expr::StringLit(preset_name)) = args.first() {
+                    if let Some(ExprKind::StringLit(preset_name)) = args.first().map(|e| &e.kind) {
                         config.preset_ref = Some(preset_name.clone());
                         // If the preset name looks like an oscillator type, use it
                         match preset_name.as_str() {
                             "Oscillator" => {
-                                if let Some(Expr::ObjectLit(pairs)) = args.get(1) {
+                                if let Some(ExprKind::ObjectLit(pairs)) = args.get(1).map(|e| &e.kind) {
                                     for (key, value) in pairs {
                                         match key.as_str() {
                                             "type" => {
-                                                if let Expr::StringLit(s) = value {
+                                                if let ExprKind::StringLit(s) = &value.kind {
                                                     config.waveform = s.clone();
                                                 }
                                             }
                                             "attack" => {
-                                                if let Expr::Number(n) = value {
+                                                if let ExprKind::Number(n) = &value.kind {
                                                     config.attack = Some(*n);
                                                 }
                                             }
                                             "decay" => {
-                                                if let Expr::Number(n) = value {
+                                                if let ExprKind::Number(n) = &value.kind {
                                                     config.decay = Some(*n);
                                                 }
                                             }
                                             "sustain" => {
-                                                if let Expr::Number(n) = value {
+                                                if let ExprKind::Number(n) = &value.kind {
                                                     config.sustain = Some(*n);
                                                 }
                                             }
                                             "release" => {
-                                                if let Expr::Number(n) = value {
+                                                if let ExprKind::Number(n) = &value.kind {
                                                     config.release = Some(*n);
                                                 }
                                             }
                                             "detune" => {
-                                                if let Expr::Number(n) = value {
+                                                if let ExprKind::Number(n) = &value.kind {
                                                     config.detune = Some(*n);
                                                 }
                                             }
                                             "mixer" => {
-                                                if let Expr::Number(n) = value {
+                                                if let ExprKind::Number(n) = &value.kind {
                                                     config.mixer = Some(*n);
                                                 }
                                             }
@@ -388,45 +888,354 @@ fn evaluate_instrument_expr(ctx: &CompileCtx, expr: &Expr) -> Result<InstrumentC
                 _ => Err(format!("Unknown instrument preset '{function}'.")),
             }
         }
-        Expr::Identifier(name) => {
-            // Look up in param_bindings first, then consts.
-            if let Some(cfg) = ctx.param_bindings.get(name) {
-                Ok(cfg.clone())
+        ExprKind::Identifier(name) => {
+            // Look up in parameters first, then consts.
+            let bound = ctx.variables.get(name);
+            match bound.cloned().and_then(|v| v.coerce(ParamType::Instrument)) {
+                Some(Value::Instrument(cfg)) => Ok(cfg),
+                _ => match (ctx.consts.get(name), bound) {
+                    (Some(cfg), _) => Ok(cfg.clone()),
+                    (None, Some(value)) => {
+                        Err(format!("'{name}' is {}, not an instrument.", value.type_name()))
+                    }
+                    (None, None) => Err(format!("Unknown instrument '{name}'.")),
+                },
+            }
+        }
+        // Shorthand: 'triangle', 'square', etc.
+        ExprKind::StringLit(s) => Ok(InstrumentConfig::waveform(s.clone())),
+        other => Err(format!("Cannot resolve expression as instrument: {other:?}")),
+    }
+}
+
+/// The value of a numeric expression, or `None` if it is not one (e.g. an
+/// instrument or a string).
+fn resolve_number(ctx: &CompileCtx, expr: &Expr) -> Option<f64> {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Binary { .. } | ExprKind::Unary { .. } => {
+            evaluate_number(ctx, expr).ok()
+        }
+        ExprKind::Index { .. } => evaluate_number(ctx, expr).ok(),
+        ExprKind::FunctionCall { function, .. } if is_generator(function) => {
+            evaluate_number(ctx, expr).ok()
+        }
+        ExprKind::Identifier(name) => match name.as_str() {
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            _ => match ctx.variables.get(name) {
+                Some(Value::Number(n) | Value::Duration(n)) => Some(*n),
+                _ => None,
+            },
+        },
+        _ => None,
+    }
+}
+
+/// Evaluate an expression to a value, e.g. a track call argument.
+fn evaluate_value(ctx: &CompileCtx, expr: &Expr) -> Result<Value, String> {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Binary { .. } | ExprKind::Unary { .. } => {
+            evaluate_number(ctx, expr).map(Value::Number)
+        }
+        ExprKind::DurationLit(d) => Ok(Value::Duration(duration_to_beats(d, ctx.default_note_length))),
+        ExprKind::StringLit(s) => Ok(Value::String(s.clone())),
+        ExprKind::Identifier(name) => {
+            if let Some(degree) = name.strip_prefix('^') {
+                // Resolve `^name` now, while `name` is in scope.
+                Ok(Value::Pitch(format!("^{}", degree_number(ctx, degree)?)))
+            } else if let Some(value) = ctx.variables.get(name) {
+                Ok(value.clone())
             } else if let Some(cfg) = ctx.consts.get(name) {
-                Ok(cfg.clone())
+                Ok(Value::Instrument(cfg.clone()))
+            } else if let Some(n) = resolve_number(ctx, expr) {
+                Ok(Value::Number(n))
+            } else if is_pitch(name) {
+                Ok(Value::Pitch(name.clone()))
             } else {
-                Err(format!("Unknown instrument '{name}'."))
+                Err(format!("Unknown name '{name}'."))
+            }
+        }
+        ExprKind::FunctionCall { function, args } if is_generator(function) => {
+            evaluate_generator(ctx, function, args)
+        }
+        ExprKind::FunctionCall { .. } => evaluate_instrument_expr(ctx, expr).map(Value::Instrument),
+        ExprKind::Index { target, index } => {
+            let Value::Array(items) = evaluate_value(ctx, target)? else {
+                return Err(format!(
+                    "Cannot index '{}': it is not a list.",
+                    crate::format::format_expr(target)
+                ));
+            };
+            if items.is_empty() {
+                return Err(format!("Cannot index '{}': the list is empty.", crate::format::format_expr(target)));
             }
+            let i = evaluate_number(ctx, index)?;
+            if i.fract() != 0.0 {
+                return Err(format!("A list index must be a whole number, got {i}."));
+            }
+            Ok(items[(i as i64).rem_euclid(items.len() as i64) as usize].clone())
+        }
+        ExprKind::Array(items) => items
+            .iter()
+            .map(|item| evaluate_value(ctx, item))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        _ => Err(format!("Cannot use '{}' as a value.", crate::format::format_expr(expr))),
+    }
+}
+
+/// Evaluate a condition or numeric expression. Booleans are 1 (true) and
+/// 0 (false); a bound non-numeric parameter counts as true.
+fn evaluate_number(ctx: &CompileCtx, expr: &Expr) -> Result<f64, String> {
+    let truth = |b: bool| if b { 1.0 } else { 0.0 };
+    match &expr.kind {
+        ExprKind::Number(n) => Ok(*n),
+        ExprKind::DurationLit(d) => Ok(duration_to_beats(d, ctx.default_note_length)),
+        ExprKind::Identifier(name) => match name.as_str() {
+            "true" => Ok(1.0),
+            "false" => Ok(0.0),
+            _ => match ctx.variables.get(name) {
+                Some(Value::Number(n) | Value::Duration(n)) => Ok(*n),
+                Some(_) => Ok(1.0),
+                None => Err(format!("Unknown variable '{name}'.")),
+            },
+        },
+        ExprKind::Unary { op, operand } => {
+            let value = evaluate_number(ctx, operand)?;
+            Ok(match op {
+                UnaryOp::Not => truth(value == 0.0),
+                UnaryOp::Neg => -value,
+            })
         }
-        Expr::StringLit(s) => {
-            // Shorthand: 'triangle', 'square', etc.
-            Ok(InstrumentConfig {
-                waveform: s.clone(),
-                ..InstrumentConfig::default()
+        ExprKind::Binary { op, lhs, rhs } => {
+            let lhs = evaluate_number(ctx, lhs)?;
+            // Short-circuit so `x && y` does not require `y` to be bound.
+            match op {
+                BinaryOp::And if lhs == 0.0 => return Ok(0.0),
+                BinaryOp::Or if lhs != 0.0 => return Ok(1.0),
+                _ => {}
+            }
+            let rhs = evaluate_number(ctx, rhs)?;
+            Ok(match op {
+                BinaryOp::Or | BinaryOp::And => truth(rhs != 0.0),
+                BinaryOp::Eq => truth(lhs == rhs),
+                BinaryOp::Ne => truth(lhs != rhs),
+                BinaryOp::Lt => truth(lhs < rhs),
+                BinaryOp::Le => truth(lhs <= rhs),
+                BinaryOp::Gt => truth(lhs > rhs),
+                BinaryOp::Ge => truth(lhs >= rhs),
+                BinaryOp::Add => lhs + rhs,
+                BinaryOp::Sub => lhs - rhs,
+                BinaryOp::Mul => lhs * rhs,
+                BinaryOp::Div | BinaryOp::Rem if rhs == 0.0 => {
+                    return Err("Division by zero.".into());
+                }
+                BinaryOp::Div => lhs / rhs,
+                BinaryOp::Rem => lhs % rhs,
             })
         }
-        _ => Err(format!("Cannot resolve expression as instrument: {expr:?}")),
+        ExprKind::FunctionCall { .. } | ExprKind::Index { .. } => match evaluate_value(ctx, expr)? {
+            Value::Number(n) | Value::Duration(n) => Ok(n),
+            other => Err(format!(
+                "'{}' is {}, not a number.",
+                crate::format::format_expr(expr),
+                other.type_name()
+            )),
+        },
+        _ => Err(format!("'{}' is not a number.", crate::format::format_expr(expr))),
+    }
+}
+
+// ── Generators ──────────────────────────────────────────────
+
+fn is_generator(function: &str) -> bool {
+    matches!(function, "random" | "choose" | "shuffle" | "euclid")
+}
+
+/// Evaluate `random()`, `choose()`, `shuffle()` or `euclid()`. The random
+/// ones draw from the generator seeded by `song.seed`.
+fn evaluate_generator(ctx: &CompileCtx, function: &str, args: &[Expr]) -> Result<Value, String> {
+    let list = |expr: &Expr| match evaluate_value(ctx, expr)? {
+        Value::Array(items) => Ok(items),
+        other => Err(format!("{function}() needs a list, got {}.", other.type_name())),
+    };
+    let numbers = |args: &[Expr]| {
+        args.iter()
+            .map(|arg| evaluate_number(ctx, arg))
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    match function {
+        "random" => {
+            let (low, high) = match numbers(args)?[..] {
+                [] => (0.0, 1.0),
+                [high] => (0.0, high),
+                [low, high] => (low, high),
+                _ => return Err("random() takes at most two numbers: random(min, max).".into()),
+            };
+            Ok(Value::Number(low + (high - low) * ctx.rng.random()))
+        }
+        "choose" => {
+            let usage = "choose() takes a list and optional weights: choose([C4, E4], [3, 1]).";
+            let (items, weights) = match args {
+                [items] => (list(items)?, None),
+                [items, weights] => (list(items)?, Some(list(weights)?)),
+                _ => return Err(usage.into()),
+            };
+            if items.is_empty() {
+                return Err("choose() needs at least one item.".into());
+            }
+            let index = match weights {
+                None => (ctx.rng.random() * items.len() as f64) as usize,
+                Some(weights) => {
+                    let weights = weights
+                        .iter()
+                        .map(|w| match w {
+                            Value::Number(n) if *n >= 0.0 => Some(*n),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .filter(|w| w.len() == items.len() && w.iter().sum::<f64>() > 0.0)
+                        .ok_or("choose() needs one weight of 0 or more per item, not all 0.")?;
+                    let mut pick = ctx.rng.random() * weights.iter().sum::<f64>();
+                    let last = weights.iter().rposition(|&w| w > 0.0).unwrap_or(0);
+                    weights
+                        .iter()
+                        .position(|&w| {
+                            pick -= w;
+                            w > 0.0 && pick < 0.0
+                        })
+                        .unwrap_or(last)
+                }
+            };
+            Ok(items[index].clone())
+        }
+        "shuffle" => {
+            let [items] = args else {
+                return Err("shuffle() takes one list.".into());
+            };
+            let mut items = list(items)?;
+            for i in (1..items.len()).rev() {
+                let j = (ctx.rng.random() * (i + 1) as f64) as usize;
+                items.swap(i, j);
+            }
+            Ok(Value::Array(items))
+        }
+        "euclid" => {
+            let usage = "euclid() takes whole numbers: euclid(hits, steps) or euclid(hits, steps, rotation).";
+            let (hits, steps, rotation) = match numbers(args)?[..] {
+                [hits, steps] => (hits, steps, 0.0),
+                [hits, steps, rotation] => (hits, steps, rotation),
+                _ => return Err(usage.into()),
+            };
+            if [hits, steps, rotation].iter().any(|n| n.fract() != 0.0) {
+                return Err(usage.into());
+            }
+            if steps < 1.0 || hits < 0.0 || hits > steps {
+                return Err(format!("euclid() needs 0 to {steps} hits in {steps} steps, got {hits}."));
+            }
+            // Spread the hits as evenly as possible, starting with one.
+            let (hits, steps, rotation) = (hits as i64, steps as i64, rotation as i64);
+            Ok(Value::Array(
+                (0..steps)
+                    .map(|i| {
+                        let hit = ((i + rotation).rem_euclid(steps) * hits) % steps < hits;
+                        Value::Number(if hit { 1.0 } else { 0.0 })
+                    })
+                    .collect(),
+            ))
+        }
+        _ => unreachable!("is_generator lists every generator"),
     }
 }
 
 /// Handle an assignment statement (works for both top-level and track body).
-fn compile_assignment(ctx: &mut CompileCtx, target: &str, value: &Expr) -> Result<(), String> {
+fn compile_assignment(
+    ctx: &mut CompileCtx,
+    target: &str,
+    value: &Expr,
+    span: Span,
+) -> Result<(), String> {
+    // Evaluated only for properties passed through as events, so a random
+    // value is drawn once.
+    let set_property = |ctx: &CompileCtx, target: &str| EventKind::SetProperty {
+        target: target.to_string(),
+        value: match resolve_number(ctx, value) {
+            Some(n) => format!("{n}"),
+            None => expr_to_string(value),
+        },
+        source_start: span.start,
+        source_end: span.end,
+    };
     if target == "track.beatsPerMinute" {
-        ctx.emit(EventKind::SetProperty {
-            target: target.to_string(),
-            value: expr_to_string(value),
-        });
+        ctx.emit(set_property(ctx, target));
     } else if target == "track.tuningPitch" || target == "track.a4Frequency" {
         // Emit as track.tuningPitch regardless of which alias was used.
-        ctx.emit(EventKind::SetProperty {
-            target: "track.tuningPitch".to_string(),
-            value: expr_to_string(value),
-        });
+        ctx.emit(set_property(ctx, "track.tuningPitch"));
+    } else if target == "track.transpose" {
+        ctx.transpose = whole_number(ctx, "Transpose", value)?;
+    } else if target == "track.diatonicTranspose" {
+        ctx.diatonic_transpose = whole_number(ctx, "Transpose", value)?;
+    } else if target == "track.key" {
+        let Value::String(text) = evaluate_value(ctx, value)? else {
+            return Err("track.key must be a string such as 'D dorian'.".into());
+        };
+        ctx.key = Some(Key::parse(&text, &ctx.scales)?);
+    } else if target == "track.chordOctave" {
+        ctx.chord_style.octave = whole_number(ctx, target, value)?;
+    } else if target == "track.inversion" {
+        let n = whole_number(ctx, target, value)?;
+        ctx.chord_style.inversion = u32::try_from(n)
+            .map_err(|_| format!("track.inversion cannot be negative, got {n}."))?;
+    } else if target == "track.voicing" {
+        let name = expr_to_string(value);
+        ctx.chord_style.voicing = Voicing::from_name(&name).ok_or_else(|| {
+            format!("Unknown track.voicing '{name}'. Expected 'close', 'drop2', or 'spread'.")
+        })?;
+    } else if target == "track.arpeggio" {
+        let name = expr_to_string(value);
+        ctx.chord_style.arpeggio = match name.as_str() {
+            "none" => None,
+            _ => Some(Arpeggio::from_name(&name).ok_or_else(|| {
+                format!(
+                    "Unknown track.arpeggio '{name}'. Expected 'none', 'up', 'down', 'updown', or 'random'."
+                )
+            })?),
+        };
+    } else if target == "track.swing" {
+        let swing = evaluate_number(ctx, value)?;
+        if swing <= 0.0 || swing >= 1.0 {
+            return Err(format!("track.swing must be between 0 and 1, got {swing}."));
+        }
+        ctx.feel.swing = swing;
+    } else if target == "track.swingUnit" {
+        let unit = evaluate_number(ctx, value)?;
+        if unit <= 0.0 {
+            return Err(format!("track.swingUnit must be longer than 0, got {unit}."));
+        }
+        ctx.feel.swing_unit = unit;
+    } else if target == "track.groove" {
+        let name = expr_to_string(value);
+        ctx.feel.groove = match name.as_str() {
+            "none" => None,
+            _ => Some(
+                ctx.grooves
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown groove '{name}'."))?,
+            ),
+        };
+    } else if target == "track.humanizeTiming" {
+        ctx.feel.humanize_timing = evaluate_number(ctx, value)?.abs();
+    } else if target == "track.humanizeVelocity" {
+        ctx.feel.humanize_velocity = evaluate_number(ctx, value)?.abs();
+    } else if target == "song.seed" {
+        ctx.rng = Rng::new(evaluate_number(ctx, value)?.to_bits());
     } else if target == "track.noteLength" || target == "track.duration" {
-        if let Expr::DurationLit(d) = value {
+        if let ExprKind::DurationLit(d) = &value.kind {
             ctx.default_note_length = duration_to_beats(d, ctx.default_note_length);
-        } else if let Expr::Number(n) = value {
-            ctx.default_note_length = *n;
+        } else if let Some(n) = resolve_number(ctx, value) {
+            ctx.default_note_length = n;
         }
     } else if target == "song.endMode" {
         let mode_str = expr_to_string(value);
@@ -445,89 +1254,215 @@ fn compile_assignment(ctx: &mut CompileCtx, target: &str, value: &Expr) -> Resul
         // Resolve the value to an InstrumentConfig.
         let config = evaluate_instrument_expr(ctx, value)?;
         ctx.current_instrument = config;
-        ctx.emit(EventKind::SetProperty {
-            target: target.to_string(),
-            value: expr_to_string(value),
-        });
+        ctx.emit(set_property(ctx, target));
     } else {
-        ctx.emit(EventKind::SetProperty {
-            target: target.to_string(),
-            value: expr_to_string(value),
-        });
+        ctx.emit(set_property(ctx, target));
     }
     Ok(())
 }
 
+/// A track call, borrowed from a top-level or track-body statement.
+struct Call<'a> {
+    name: &'a str,
+    transpose: &'a Option<Expr>,
+    velocity: &'a Option<f64>,
+    play_duration: &'a Option<DurationExpr>,
+    looped: bool,
+    args: &'a [Expr],
+    step: &'a Option<DurationExpr>,
+    span: Span,
+}
+
 /// Inline a track call: resolve args → params, save/restore scope, compile body.
-fn inline_track_call(
-    ctx: &mut CompileCtx,
-    name: &str,
-    _velocity: &Option<f64>,
-    play_duration: &Option<DurationExpr>,
-    args: &[Expr],
-    step: &Option<DurationExpr>,
-) -> Result<(), String> {
+fn inline_track_call(ctx: &mut CompileCtx, call: Call) -> Result<(), String> {
     let track_body = ctx
         .track_defs
         .iter()
-        .find(|td| td.name == name)
+        .find(|td| td.name == call.name)
         .map(|td| (td.params.clone(), td.body.clone()));
 
     if let Some((params, body)) = track_body {
+        let transpose = match call.transpose {
+            Some(expr) => ctx.transpose + whole_number(ctx, "Transpose", expr)?,
+            None => ctx.transpose,
+        };
+
         // Save parent scope.
         let saved_cursor = ctx.cursor;
         let saved_note_len = ctx.default_note_length;
         let saved_instrument = ctx.current_instrument.clone();
-        let saved_params = ctx.param_bindings.clone();
-
-        // Resolve args → params: zip track def params with call args.
-        let mut new_bindings = ctx.param_bindings.clone();
-        for (param_name, arg_expr) in params.iter().zip(args.iter()) {
-            let config = evaluate_instrument_expr(ctx, arg_expr)?;
-            new_bindings.insert(param_name.clone(), config);
+        let saved_transpose = std::mem::replace(&mut ctx.transpose, transpose);
+        let saved_key = ctx.key.clone();
+        let saved_diatonic = ctx.diatonic_transpose;
+        let saved_chord_style = ctx.chord_style.clone();
+        let saved_feel = ctx.feel.clone();
+        let saved_variables = ctx.variables.clone();
+        let saved_track = std::mem::replace(&mut ctx.current_track, call.name.to_string());
+        if saved_track.is_empty() {
+            ctx.top_track = call.name.to_string();
+        }
+        let first_event = ctx.events.len();
+
+        // Resolve args → params, checking declared types. Untyped params
+        // without an argument are false (0); typed ones need an argument.
+        let mut new_variables = ctx.variables.clone();
+        for (i, param) in params.iter().enumerate() {
+            let value = match (call.args.get(i), param.ty) {
+                (Some(arg_expr), None) => evaluate_value(ctx, arg_expr)?,
+                (Some(arg_expr), Some(ty)) => {
+                    let value = evaluate_value(ctx, arg_expr)?;
+                    let type_name = value.type_name();
+                    value.coerce(ty).ok_or_else(|| {
+                        format!("Track '{}' parameter '{param}' got {type_name}.", call.name)
+                    })?
+                }
+                (None, None) => Value::Number(0.0),
+                (None, Some(_)) => {
+                    return Err(format!(
+                        "Track '{}' parameter '{param}' needs an argument.",
+                        call.name
+                    ));
+                }
+            };
+            new_variables.insert(param.name.clone(), value);
         }
-        ctx.param_bindings = new_bindings;
+        ctx.variables = new_variables;
 
         // Compile the track body inline (inherits parent state).
         compile_track_body(ctx, &body)?;
 
-        // If play_duration is set, cap the track's extent.
-        if let Some(pd) = play_duration {
-            let max_dur = duration_to_beats(pd, ctx.default_note_length);
-            ctx.cursor = saved_cursor + max_dur;
+        // If play_duration is set, cut the track off there, or repeat it
+        // up to there when looped.
+        if let Some(pd) = call.play_duration {
+            let limit = saved_cursor + duration_to_beats(pd, saved_note_len);
+            let length = ctx.cursor - saved_cursor;
+            if call.looped {
+                let mut start = saved_cursor + length;
+                for pass in 1.. {
+                    if start >= limit {
+                        break;
+                    }
+                    if pass == MAX_LOOP_PASSES {
+                        return Err(format!(
+                            "Looped call to '{}' ran {MAX_LOOP_PASSES} times without filling its duration.",
+                            call.name
+                        ));
+                    }
+                    ctx.cursor = start;
+                    ctx.default_note_length = saved_note_len;
+                    ctx.current_instrument = saved_instrument.clone();
+                    ctx.transpose = transpose;
+                    ctx.key = saved_key.clone();
+                    ctx.diatonic_transpose = saved_diatonic;
+                    ctx.chord_style = saved_chord_style.clone();
+                    ctx.feel = saved_feel.clone();
+                    compile_track_body(ctx, &body)?;
+                    start += length;
+                }
+            }
+            cut_at(ctx, first_event, limit);
+            ctx.cursor = limit;
         }
 
         // Restore parent scope.
         ctx.default_note_length = saved_note_len;
         ctx.current_instrument = saved_instrument;
-        ctx.param_bindings = saved_params;
+        ctx.transpose = saved_transpose;
+        ctx.key = saved_key;
+        ctx.diatonic_transpose = saved_diatonic;
+        ctx.chord_style = saved_chord_style;
+        ctx.feel = saved_feel;
+        ctx.variables = saved_variables;
+        ctx.current_track = saved_track;
 
         // Apply step (rest after the track call).
-        if let Some(s) = step {
+        if let Some(s) = call.step {
             let step_beats = duration_to_beats(s, ctx.default_note_length);
             ctx.cursor = saved_cursor + step_beats;
         }
     } else {
         // Unknown track: emit as a TrackStart event.
-        let arg_strings: Vec<String> = args.iter().map(expr_to_string).collect();
+        let arg_strings: Vec<String> = call.args.iter().map(expr_to_string).collect();
         ctx.emit(EventKind::TrackStart {
-            track_name: name.to_string(),
-            velocity: *_velocity,
-            play_duration: play_duration
+            track_name: call.name.to_string(),
+            velocity: *call.velocity,
+            play_duration: call
+                .play_duration
                 .as_ref()
                 .map(|d| duration_to_beats(d, ctx.default_note_length)),
             args: arg_strings,
+            source_start: call.span.start,
+            source_end: call.span.end,
         });
-        if let Some(s) = step {
+        if let Some(s) = call.step {
             ctx.cursor += duration_to_beats(s, ctx.default_note_length);
         }
     }
     Ok(())
 }
 
+/// Cut the events emitted since `from` off at `limit`: notes and track
+/// starts at or past it are dropped, and notes crossing it are gated there.
+/// Both are judged on the grid beat, before swing or humanize moved them.
+fn cut_at(ctx: &mut CompileCtx, from: usize, limit: f64) {
+    let events = ctx.events.split_off(from);
+    let beats = ctx.beats.split_off(from);
+    for (mut e, beat) in events.into_iter().zip(beats) {
+        match &mut e.kind {
+            EventKind::Note { .. } | EventKind::TrackStart { .. } | EventKind::SetProperty { .. }
+                if beat >= limit =>
+            {
+                continue;
+            }
+            EventKind::Note { gate, .. } => *gate = gate.min(limit - beat),
+            _ => {}
+        }
+        ctx.push(beat, e);
+    }
+}
+
+/// Passes after which a `for` loop, or a looped track call filling its
+/// play duration, is taken to never end.
+const MAX_LOOP_PASSES: usize = 10_000;
+
+/// Unroll `for (let i = 0; i < 4; i++)`, binding the loop variable on
+/// each pass like `repeat … as`.
+fn compile_for_loop(
+    ctx: &mut CompileCtx,
+    name: &str,
+    start: &Expr,
+    condition: &Expr,
+    update: &LoopUpdate,
+    body: &[TrackStatement],
+) -> Result<(), String> {
+    let saved_variables = ctx.variables.clone();
+    let mut value = evaluate_number(ctx, start)?;
+    for pass in 0.. {
+        ctx.variables.insert(name.to_string(), Value::Number(value));
+        if evaluate_number(ctx, condition)? == 0.0 {
+            break;
+        }
+        if pass == MAX_LOOP_PASSES {
+            return Err(format!(
+                "for loop over '{name}' ran {MAX_LOOP_PASSES} times without ending."
+            ));
+        }
+        compile_track_body(ctx, body)?;
+        value = match update {
+            LoopUpdate::Increment => value + 1.0,
+            LoopUpdate::Decrement => value - 1.0,
+            LoopUpdate::Add(e) => value + evaluate_number(ctx, e)?,
+            LoopUpdate::Sub(e) => value - evaluate_number(ctx, e)?,
+            LoopUpdate::Set(e) => evaluate_number(ctx, e)?,
+        };
+    }
+    ctx.variables = saved_variables;
+    Ok(())
+}
+
 fn compile_track_body(ctx: &mut CompileCtx, body: &[TrackStatement]) -> Result<(), String> {
     for stmt in body {
-        compile_track_statement(ctx, stmt)?;
+        compile_track_statement(ctx, stmt).inspect_err(|_| ctx.fail_at(stmt.span()))?;
     }
     Ok(())
 }
@@ -536,24 +1471,42 @@ fn compile_track_statement(ctx: &mut CompileCtx, stmt: &TrackStatement) -> Resul
     match stmt {
         TrackStatement::NoteEvent {
             pitch,
+            transpose,
             velocity,
             audible_duration,
             step_duration,
             span_start,
             span_end,
         } => {
+            // A parameter name plays the pitches bound to it; a number or
+            // duration parameter on its own is a rest.
+            let pitches = match ctx.variables.get(pitch) {
+                None => vec![pitch.clone()],
+                Some(Value::Number(n) | Value::Duration(n)) if transpose.is_none() => {
+                    ctx.cursor += *n;
+                    return Ok(());
+                }
+                Some(value) => value_pitches(pitch, value)?,
+            };
+            let interval = transpose.as_ref().map(|t| whole_number(ctx, "Transpose", t)).transpose()?;
             let vel = velocity.unwrap_or(100.0);
             let audible = ctx.resolve_duration(audible_duration);
             let step = ctx.resolve_duration(step_duration);
 
-            ctx.emit(EventKind::Note {
-                pitch: pitch.clone(),
-                velocity: vel,
-                gate: audible,
-                instrument: ctx.current_instrument.clone(),
-                source_start: *span_start,
-                source_end: *span_end,
-            });
+            for pitch in pitches {
+                let (pitch, midi) = resolve_pitch(ctx, pitch, interval)?;
+                ctx.emit(EventKind::Note {
+                    pitch,
+                    midi,
+                    velocity: vel,
+                    gate: audible,
+                    instrument: ctx.current_instrument.clone(),
+                    track: ctx.current_track.clone(),
+                    top_track: ctx.top_track.clone(),
+                    source_start: *span_start,
+                    source_end: *span_end,
+                });
+            }
             ctx.cursor += step;
             Ok(())
         }
@@ -575,50 +1528,181 @@ fn compile_track_statement(ctx: &mut CompileCtx, stmt: &TrackStatement) -> Resul
                     .map(|d| duration_to_beats(d, ctx.default_note_length))
                     .or(chord_audible)
                     .unwrap_or(ctx.default_note_length);
+                let pitches = match ctx.variables.get(&note.pitch) {
+                    Some(value) => value_pitches(&note.pitch, value)?,
+                    None => vec![note.pitch.clone()],
+                };
+                let interval = note.transpose.as_ref().map(|t| whole_number(ctx, "Transpose", t)).transpose()?;
+
+                for pitch in pitches {
+                    let (pitch, midi) = resolve_pitch(ctx, pitch, interval)?;
+                    ctx.emit(EventKind::Note {
+                        pitch,
+                        midi,
+                        velocity: 100.0,
+                        gate: note_dur,
+                        instrument: ctx.current_instrument.clone(),
+                        track: ctx.current_track.clone(),
+                        top_track: ctx.top_track.clone(),
+                        source_start: *span_start,
+                        source_end: *span_end,
+                    });
+                }
+            }
+
+            let step = ctx.resolve_duration(step_duration);
+            ctx.cursor += step;
+            Ok(())
+        }
+        TrackStatement::ChordSymbol {
+            symbol,
+            velocity,
+            audible_duration,
+            step_duration,
+            span_start,
+            span_end,
+        } => {
+            let chord = ChordSymbol::parse(symbol)
+                .ok_or_else(|| format!("Unknown chord symbol '{symbol}'."))?;
+            let keys = chord
+                .voice(&ctx.chord_style)
+                .into_iter()
+                .map(|k| u8::try_from(k).ok().filter(|k| *k <= 127))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| format!("Chord '{symbol}' falls outside the MIDI range."))?;
+            let vel = velocity.unwrap_or(100.0);
+            let step = ctx.resolve_duration(step_duration);
 
+            // An arpeggio plays one tone per slot, spread across the step.
+            let (order, slot) = match ctx.chord_style.arpeggio {
+                Some(arpeggio) => {
+                    let order = arpeggio.order(keys.len(), || ctx.rng.random());
+                    let slot = step / order.len() as f64;
+                    (order, slot)
+                }
+                None => ((0..keys.len()).collect(), 0.0),
+            };
+            let audible = match audible_duration {
+                None if slot > 0.0 => slot,
+                _ => ctx.resolve_duration(audible_duration),
+            };
+
+            let start = ctx.cursor;
+            for (i, index) in order.into_iter().enumerate() {
+                let note = crate::midi::midi_to_note(keys[index]);
+                let (pitch, midi) = resolve_pitch(ctx, note, None)?;
+                ctx.cursor = start + i as f64 * slot;
                 ctx.emit(EventKind::Note {
-                    pitch: note.pitch.clone(),
-                    velocity: 100.0,
-                    gate: note_dur,
+                    pitch,
+                    midi,
+                    velocity: vel,
+                    gate: audible,
                     instrument: ctx.current_instrument.clone(),
+                    track: ctx.current_track.clone(),
+                    top_track: ctx.top_track.clone(),
                     source_start: *span_start,
                     source_end: *span_end,
                 });
             }
-
-            let step = ctx.resolve_duration(step_duration);
-            ctx.cursor += step;
+            ctx.cursor = start + step;
             Ok(())
         }
-        TrackStatement::Rest(dur) => {
-            ctx.cursor += duration_to_beats(dur, ctx.default_note_length);
+        TrackStatement::Rest { duration, .. } => {
+            ctx.cursor += duration_to_beats(duration, ctx.default_note_length);
             Ok(())
         }
-        TrackStatement::Assignment { target, value } => {
-            compile_assignment(ctx, target, value)
-        }
+        TrackStatement::Assignment {
+            target,
+            value,
+            span_start,
+            span_end,
+        } => compile_assignment(
+            ctx,
+            target,
+            value,
+            Span {
+                start: *span_start,
+                end: *span_end,
+            },
+        ),
         TrackStatement::ForLoop {
-            init: _,
-            condition: _,
-            update: _,
+            variable,
+            start,
+            condition,
+            update,
             body,
+            ..
+        } => compile_for_loop(ctx, variable, start, condition, update, body),
+        TrackStatement::Repeat {
+            count,
+            index,
+            body,
+            endings,
+            ..
         } => {
-            // Phase 1: hardcoded unroll — extract loop count from condition.
-            // For now, just compile the body once as a placeholder.
-            // TODO: properly evaluate loop bounds.
-            compile_track_body(ctx, body)?;
+            let mut seen = Vec::new();
+            for pass in endings.iter().flat_map(|e| &e.passes) {
+                if pass > count {
+                    return Err(format!("ending {pass} is past the last pass of repeat {count}."));
+                }
+                if seen.contains(pass) {
+                    return Err(format!("Pass {pass} has more than one ending."));
+                }
+                seen.push(*pass);
+            }
+
+            let saved_variables = ctx.variables.clone();
+            for pass in 1..=*count {
+                if let Some(name) = index {
+                    ctx.variables.insert(name.clone(), Value::Number(pass as f64));
+                }
+                compile_track_body(ctx, body)?;
+                if let Some(ending) = endings.iter().find(|e| e.passes.contains(&pass)) {
+                    compile_track_body(ctx, &ending.body)?;
+                }
+            }
+            ctx.variables = saved_variables;
             Ok(())
         }
+        TrackStatement::If {
+            condition,
+            then_body,
+            else_body,
+            ..
+        } => {
+            if evaluate_number(ctx, condition)? != 0.0 {
+                compile_track_body(ctx, then_body)
+            } else {
+                compile_track_body(ctx, else_body)
+            }
+        }
         TrackStatement::TrackCall {
             name,
+            transpose,
             velocity,
             play_duration,
+            looped,
             args,
             step,
-        } => {
-            inline_track_call(ctx, name, velocity, play_duration, args, step)
-        }
-        TrackStatement::Comment(_) => Ok(()),
+            span_start,
+            span_end,
+        } => inline_track_call(
+            ctx,
+            Call {
+                name,
+                transpose,
+                velocity,
+                play_duration,
+                looped: *looped,
+                args,
+                step,
+                span: Span {
+                    start: *span_start,
+                    end: *span_end,
+                },
+            },
+        ),
+        TrackStatement::Comment { .. } => Ok(()),
     }
 }
 
@@ -627,10 +1711,10 @@ fn compile_track_statement(ctx: &mut CompileCtx, stmt: &TrackStatement) -> Resul
 pub fn extract_preset_refs(event_list: &EventList) -> Vec<String> {
     let mut refs = Vec::new();
     for event in &event_list.events {
-        if let EventKind::PresetRef { name } = &event.kind {
-            if !refs.contains(name) {
-                refs.push(name.clone());
-            }
+        if let EventKind::PresetRef { name } = &event.kind
+            && !refs.contains(name)
+        {
+            refs.push(name.clone());
         }
     }
     refs
@@ -943,6 +2027,916 @@ track melody(inst) {
         assert!(notes.iter().any(|(p, w)| *p == "C4" && *w == "triangle"));
     }
 
+    #[test]
+    fn test_nested_calls_keep_top_track() {
+        let program = parse(
+            r#"
+verse();
+drums() 0;
+fill();
+
+track verse() {
+    C4 /4
+    fill();
+}
+
+track fill() {
+    E4 /4
+}
+
+track drums() {
+    C2 /4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let notes: Vec<_> = events.events.iter().filter_map(|e| match &e.kind {
+            EventKind::Note { pitch, track, top_track, .. } => {
+                Some((pitch.as_str(), track.as_str(), top_track.as_str()))
+            }
+            _ => None,
+        }).collect();
+
+        assert_eq!(
+            notes,
+            [
+                ("C4", "verse", "verse"),
+                ("E4", "fill", "verse"),
+                ("C2", "drums", "drums"),
+                ("E4", "fill", "fill"),
+            ]
+        );
+    }
+
+    /// A compiled note, for asserting on.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Played<'a> {
+        pitch: &'a str,
+        midi: Option<u8>,
+        time: f64,
+        gate: f64,
+        velocity: f64,
+    }
+
+    fn played(events: &EventList) -> Vec<Played<'_>> {
+        events
+            .events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note {
+                    pitch,
+                    midi,
+                    gate,
+                    velocity,
+                    ..
+                } => Some(Played {
+                    pitch,
+                    midi: *midi,
+                    time: e.time,
+                    gate: *gate,
+                    velocity: *velocity,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_block_lasts_as_long_as_its_longest_member() {
+        let program = parse(
+            r#"
+parallel {
+    drums();
+    bass();
+}
+lead() & drums();
+end();
+
+track drums() {
+    C2 /1
+}
+
+track bass() {
+    C3 /2
+    D3 /2
+}
+
+track lead() {
+    E4 /1
+    F4 /1
+    G4 /1
+}
+
+track end() {
+    C5 /1
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.time)).collect::<Vec<_>>(),
+            [
+                ("C2", 0.0),
+                ("C3", 0.0),
+                ("D3", 0.5),
+                ("E4", 1.0),
+                ("C2", 1.0),
+                ("F4", 2.0),
+                ("G4", 3.0),
+                ("C5", 4.0),
+            ]
+        );
+        assert_eq!(events.total_beats, 5.0);
+    }
+
+    #[test]
+    fn test_at_positions_from_the_enclosing_block() {
+        let program = parse(
+            r#"
+at 8: chorus();
+outro();
+at 2: parallel {
+    verse();
+    at 4: verse();
+}
+at 12: at 1: outro();
+
+track chorus() {
+    C4 /1
+}
+
+track outro() {
+    D4 /1
+}
+
+track verse() {
+    E4 /1
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.time)).collect::<Vec<_>>(),
+            [("E4", 2.0), ("E4", 6.0), ("C4", 8.0), ("D4", 9.0), ("D4", 13.0)]
+        );
+        // The song still ends after the furthest statement.
+        assert_eq!(events.total_beats, 14.0);
+    }
+
+    #[test]
+    fn test_repeat_with_endings() {
+        let program = parse(
+            r#"
+verse();
+
+track verse() {
+    repeat 3 {
+        C4 /1
+    } ending 1, 2 {
+        D4 /1
+    }
+    ending 3 {
+        E4 /2
+    }
+    F4 /1
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let pitches: Vec<(&str, f64)> = played(&events).iter().map(|n| (n.pitch, n.time)).collect();
+        assert_eq!(
+            pitches,
+            [
+                ("C4", 0.0),
+                ("D4", 1.0),
+                ("C4", 2.0),
+                ("D4", 3.0),
+                ("C4", 4.0),
+                ("E4", 5.0),
+                ("F4", 5.5),
+            ]
+        );
+        assert_eq!(events.total_beats, 6.5);
+    }
+
+    #[test]
+    fn test_repeat_index_is_bound_per_pass() {
+        let program = parse(
+            r#"
+t();
+
+track t() {
+    repeat 3 as pass {
+        track.noteLength = pass;
+        C4
+    }
+    track.noteLength = 1/4;
+    D4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.time)).collect::<Vec<_>>(),
+            [("C4", 0.0), ("C4", 1.0), ("C4", 3.0), ("D4", 6.0)]
+        );
+    }
+
+    #[test]
+    fn test_repeat_rejects_bad_endings() {
+        let err = |src: &str| compile(&parse(src).unwrap()).unwrap_err();
+        assert!(err("t();\ntrack t() {\n repeat 2 { C4 } ending 3 { D4 }\n}").contains("past the last pass"));
+        assert!(err("t();\ntrack t() {\n repeat 2 { C4 } ending 1 { D4 } ending 1, 2 { E4 }\n}").contains("more than one ending"));
+    }
+
+    #[test]
+    fn test_if_uses_params_and_loop_variables() {
+        let program = parse(
+            r#"
+drums(true);
+drums();
+
+track drums(fill) {
+    repeat 4 as bar {
+        if (fill && bar == 4) {
+            Snare /1
+        } else if (bar % 2 == 0) {
+            HiHat /1
+        } else {
+            Kick /1
+        }
+    }
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let pitches: Vec<&str> = played(&events).iter().map(|n| n.pitch).collect();
+        assert_eq!(
+            pitches,
+            ["Kick", "HiHat", "Kick", "Snare", "Kick", "HiHat", "Kick", "HiHat"]
+        );
+    }
+
+    #[test]
+    fn test_for_loops_bind_their_variable() {
+        let program = parse(
+            r#"
+t();
+
+track t() {
+    for (let i = 0; i < 4; i++) {
+        if (i == 3) {
+            C4 /1
+        } else {
+            D4 /1
+        }
+    }
+    for (let n = 6; n > 0; n -= 3) {
+        E4 /1
+    }
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let pitches: Vec<&str> = played(&events).iter().map(|n| n.pitch).collect();
+        assert_eq!(pitches, ["D4", "D4", "D4", "C4", "E4", "E4"]);
+        let program = parse("t();\ntrack t() {\n    for (let i = 0; i >= 0; i++) { 1 }\n}").unwrap();
+        assert_eq!(compile(&program).unwrap_err(), "for loop over 'i' ran 10000 times without ending.");
+    }
+
+    #[test]
+    fn test_if_reports_unknown_variables() {
+        let program = parse("t();\ntrack t() {\n    if (missing > 1) { C4 }\n}").unwrap();
+        assert_eq!(compile(&program).unwrap_err(), "Unknown variable 'missing'.");
+    }
+
+    #[test]
+    fn test_typed_params_in_notes_and_assignments() {
+        let program = parse(
+            r#"
+riff(C3, 1/2, [E3, G3]);
+riff('D3', 1, []);
+
+track riff(root: pitch, len: duration, upper: array) {
+    track.noteLength = len;
+    root
+    upper
+    len
+    root /4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.time, n.gate)).collect::<Vec<_>>(),
+            [
+                ("C3", 0.0, 0.5),
+                ("E3", 0.5, 0.5),
+                ("G3", 0.5, 0.5),
+                ("C3", 1.5, 0.5),
+                ("D3", 1.75, 1.0),
+                ("D3", 4.75, 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_param_type_mismatches() {
+        let program = parse("riff(4);\ntrack riff(root: pitch) {\n    root\n}").unwrap();
+        assert_eq!(
+            compile(&program).unwrap_err(),
+            "Track 'riff' parameter 'root: pitch' got a number."
+        );
+
+        let program = parse("riff();\ntrack riff(root: pitch) {\n    root\n}").unwrap();
+        assert_eq!(
+            compile(&program).unwrap_err(),
+            "Track 'riff' parameter 'root: pitch' needs an argument."
+        );
+
+        let program = parse("riff(Oscillator({type: 'sine'}));\ntrack riff(root) {\n    root\n}").unwrap();
+        assert_eq!(compile(&program).unwrap_err(), "'root' is an instrument, not a pitch.");
+
+        let program = parse("riff(C4);\ntrack riff(inst) {\n    track.instrument = inst;\n}").unwrap();
+        assert_eq!(compile(&program).unwrap_err(), "'inst' is a pitch, not an instrument.");
+    }
+
+    #[test]
+    fn test_transpose_is_inherited_and_restored() {
+        let program = parse(
+            r#"
+track.transpose = 2;
+riff();
+riff+12();
+riff();
+
+track riff() {
+    C4
+    Kick
+    inner-2();
+}
+
+track inner() {
+    track.transpose = 0;
+    A4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.midi)).collect::<Vec<_>>(),
+            [
+                ("D4", Some(62)),
+                ("Kick", None),
+                ("A4", Some(69)),
+                ("D5", Some(74)),
+                ("Kick", None),
+                ("A4", Some(69)),
+                ("D4", Some(62)),
+                ("Kick", None),
+                ("A4", Some(69)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pitch_arithmetic() {
+        let program = parse(
+            r#"
+riff(C4, 4);
+
+track riff(root: pitch, third: number) {
+    track.transpose = -12;
+    root+7
+    [root, root+third, root+(third + 3)]
+    Eb4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.midi)).collect::<Vec<_>>(),
+            [
+                ("G3", Some(55)),
+                ("C3", Some(48)),
+                ("E3", Some(52)),
+                ("G3", Some(55)),
+                ("Eb3", Some(51)),
+            ]
+        );
+
+        let errors = [
+            ("Kick+1", "Cannot transpose 'Kick': it is not a pitch."),
+            ("C4+0.5", "Transpose must be a whole number, got 0.5."),
+            ("G9+1", "'G9' falls outside the MIDI range."),
+        ];
+        for (note, message) in errors {
+            let program = parse(&format!("t();\ntrack t() {{\n    {note}\n}}")).unwrap();
+            assert_eq!(compile(&program).unwrap_err(), message);
+        }
+    }
+
+    #[test]
+    fn test_degree_notes_follow_the_key() {
+        let program = parse(
+            r#"
+scale hijaz = [0, 1, 4, 5, 7, 8, 10];
+track.key = 'D dorian';
+melody(^1);
+melody(^5);
+
+track melody(root: pitch) {
+    ^1 ^3 ^-1 ^8+2
+    [^1, ^3, ^5]
+    root
+    track.key = 'E3 hijaz';
+    ^2 ^3
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let pitches: Vec<&str> = played(&events).iter().map(|n| n.pitch).collect();
+        assert_eq!(
+            pitches,
+            [
+                "D4", "F4", "B3", "F5", "D4", "F4", "A4", "D4", "F3", "Ab3", //
+                "D4", "F4", "B3", "F5", "D4", "F4", "A4", "A4", "F3", "Ab3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diatonic_transpose_stays_in_key() {
+        let program = parse(
+            r#"
+track.key = 'C major';
+riff();
+track.diatonicTranspose = 2;
+riff();
+
+track riff() {
+    C4 E4 ^5 Gb4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let pitches: Vec<&str> = played(&events).iter().map(|n| n.pitch).collect();
+        assert_eq!(pitches, ["C4", "E4", "G4", "Gb4", "E4", "G4", "B4", "Bb4"]);
+    }
+
+    #[test]
+    fn test_key_errors() {
+        let errors = [
+            ("^1", "Degree note '^1' needs track.key to be set."),
+            ("track.key = 'C bebop';", "Unknown scale 'bebop'."),
+            ("track.key = 12;", "track.key must be a string such as 'D dorian'."),
+            ("track.diatonicTranspose = 1;\n    C4", "track.diatonicTranspose needs track.key to be set."),
+            ("track.key = 'C';\n    ^x", "Unknown scale degree '^x'."),
+        ];
+        for (body, message) in errors {
+            let program = parse(&format!("t();\ntrack t() {{\n    {body}\n}}")).unwrap();
+            assert_eq!(compile(&program).unwrap_err(), message);
+        }
+
+        let program = parse("scale odd = [1, 3];").unwrap();
+        assert!(compile(&program).unwrap_err().starts_with("Scale 'odd' must list"));
+    }
+
+    #[test]
+    fn test_chord_symbols_follow_track_settings() {
+        let program = parse(
+            r#"
+voiced();
+plain();
+
+track plain() {
+    'C' /1
+}
+
+track voiced() {
+    track.chordOctave = 3;
+    'Cmaj7/E' /1
+    track.voicing = 'drop2';
+    track.transpose = 2;
+    'Cmaj7' /1
+    track.voicing = 'close';
+    track.transpose = 0;
+    track.inversion = 1;
+    'F#m' /1
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let keys: Vec<u8> = played(&events).iter().filter_map(|n| n.midi).collect();
+        assert_eq!(
+            keys,
+            [
+                40, 48, 52, 55, 59, // Cmaj7/E: E2 under C3 E3 G3 B3
+                45, 50, 54, 61, // Cmaj7 drop-2, up a tone
+                57, 61, 66, // F#m, first inversion
+                60, 64, 67, // settings end with the track
+            ]
+        );
+        assert_eq!(played(&events)[9].pitch, "A3");
+    }
+
+    #[test]
+    fn test_arpeggios_spread_across_the_step() {
+        let source = |pattern: &str| {
+            format!("song.seed = 7;
+t();
+track t() {{
+    track.arpeggio = '{pattern}';
+    'Am7' /1
+    'Am7'@/8 /1
+}}")
+        };
+        let events = compile(&parse(&source("updown")).unwrap()).unwrap();
+        let notes: Vec<(f64, &str, f64)> = events
+            .events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note { pitch, gate, .. } => Some((e.time, pitch.as_str(), *gate)),
+                _ => None,
+            })
+            .collect();
+        let slot = 1.0 / 6.0;
+        let pitches: Vec<&str> = notes.iter().take(6).map(|n| n.1).collect();
+        assert_eq!(pitches, ["A4", "C5", "E5", "G5", "E5", "C5"]);
+        for (i, (time, _, gate)) in notes.iter().take(6).enumerate() {
+            assert!((time - i as f64 * slot).abs() < 1e-9);
+            assert!((gate - slot).abs() < 1e-9);
+        }
+        // `@` sets the gate of each tone; the next chord starts a step later.
+        assert_eq!((notes[6].0, notes[6].2), (1.0, 0.125));
+
+        let random = |program: &str| {
+            let events = compile(&parse(program).unwrap()).unwrap();
+            played(&events).iter().map(|n| n.pitch.to_string()).collect::<Vec<_>>()
+        };
+        let first = random(&source("random"));
+        assert_eq!(first, random(&source("random")));
+        let mut sorted = first[..4].to_vec();
+        sorted.sort();
+        assert_eq!(sorted, ["A4", "C5", "E5", "G5"]);
+    }
+
+    #[test]
+    fn test_chord_symbol_errors() {
+        let errors = [
+            ("'Cfoo'", "Unknown chord symbol 'Cfoo'."),
+            ("track.voicing = 'drop3';", "Unknown track.voicing 'drop3'. Expected 'close', 'drop2', or 'spread'."),
+            ("track.inversion = -1;", "track.inversion cannot be negative, got -1."),
+            ("track.chordOctave = 4.5;", "track.chordOctave must be a whole number, got 4.5."),
+            ("track.chordOctave = 10;\n    'G'", "Chord 'G' falls outside the MIDI range."),
+            ("track.inversion = 30;\n    'G'", "Chord 'G' falls outside the MIDI range."),
+            (
+                "track.arpeggio = 'sideways';",
+                "Unknown track.arpeggio 'sideways'. Expected 'none', 'up', 'down', 'updown', or 'random'.",
+            ),
+        ];
+        for (body, message) in errors {
+            let program = parse(&format!("t();\ntrack t() {{\n    {body}\n}}")).unwrap();
+            assert_eq!(compile(&program).unwrap_err(), message);
+        }
+    }
+
+    #[test]
+    fn test_swing_and_groove_move_notes() {
+        let program = parse(
+            r#"
+groove push = {unit: 1/4, timing: [0, 0.2], velocity: [0, -20]};
+swung();
+grooved();
+
+track swung() {
+    track.swing = 0.6;
+    C4 /2
+    C4 /2
+    C4 /2
+    C4 /2
+}
+
+track grooved() {
+    track.groove = 'push';
+    C4 /4
+    C4 /4
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let times: Vec<(f64, f64)> = played(&events).iter().map(|n| (n.time, n.velocity)).collect();
+        let expected = [(0.0, 100.0), (0.6, 100.0), (1.0, 100.0), (1.6, 100.0), (2.0, 100.0), (2.3, 80.0)];
+        for ((time, velocity), (t, v)) in times.iter().zip(expected) {
+            assert!((time - t).abs() < 1e-9, "{times:?}");
+            assert_eq!(*velocity, v);
+        }
+    }
+
+    #[test]
+    fn test_humanize_is_seeded() {
+        let source = |seed: u32| {
+            format!(
+                "song.seed = {seed};\nt();\ntrack t() {{\n    track.humanizeTiming = 0.05;\n    \
+                 track.humanizeVelocity = 10;\n    C4 /1\n    C4 /1\n}}"
+            )
+        };
+        let render = |seed| {
+            let events = compile(&parse(&source(seed)).unwrap()).unwrap();
+            played(&events).iter().map(|n| (n.time, n.velocity)).collect::<Vec<_>>()
+        };
+        let first = render(1);
+        assert_eq!(first, render(1));
+        assert_ne!(first, render(2));
+        for ((time, velocity), beat) in first.iter().zip([0.0, 1.0]) {
+            assert!((time - beat).abs() <= 0.05);
+            assert!((velocity - 100.0).abs() <= 10.0);
+        }
+    }
+
+    #[test]
+    fn test_feel_errors() {
+        let errors = [
+            ("track.swing = 1;", "track.swing must be between 0 and 1, got 1."),
+            ("track.swingUnit = 0;", "track.swingUnit must be longer than 0, got 0."),
+            ("track.groove = 'mpc';", "Unknown groove 'mpc'."),
+        ];
+        for (body, message) in errors {
+            let program = parse(&format!("t();\ntrack t() {{\n    {body}\n}}")).unwrap();
+            assert_eq!(compile(&program).unwrap_err(), message);
+        }
+
+        let program = parse("groove g = {swing: 0.6};").unwrap();
+        assert_eq!(
+            compile(&program).unwrap_err(),
+            "Groove 'g' has no setting 'swing'. Expected unit, timing or velocity."
+        );
+        let program = parse("groove g = {timing: [0, 'late']};").unwrap();
+        assert_eq!(compile(&program).unwrap_err(), "Groove 'g' timing must be a list of numbers.");
+    }
+
+    #[test]
+    fn test_generators_are_seeded() {
+        let source = |seed: u32| {
+            format!(
+                "song.seed = {seed};\nmelody();\nchord(shuffle([C5, D5, E5, F5]));\n\
+                 track melody() {{\n    repeat 16 {{\n        note(choose([C4, E4, G4], [1, 0, 3]));\n    }}\n}}\n\
+                 track note(p: pitch) {{\n    if (random() < 2) {{\n        p /4\n    }}\n}}\n\
+                 track chord(notes) {{\n    notes /1\n}}"
+            )
+        };
+        let render = |seed| {
+            let events = compile(&parse(&source(seed)).unwrap()).unwrap();
+            played(&events).iter().map(|n| n.pitch.to_string()).collect::<Vec<_>>()
+        };
+        let first = render(1);
+        assert_eq!(first, render(1));
+        assert_ne!(first, render(2));
+
+        assert_eq!(first.len(), 20);
+        assert!(first[..16].iter().all(|p| p == "C4" || p == "G4"));
+        let mut shuffled = first[16..].to_vec();
+        shuffled.sort();
+        assert_eq!(shuffled, ["C5", "D5", "E5", "F5"]);
+    }
+
+    #[test]
+    fn test_euclidean_rhythms() {
+        let program = parse(
+            r#"
+beat();
+
+track beat() {
+    repeat 8 as i {
+        if (euclid(3, 8)[i - 1]) {
+            C4 /4
+        } else {
+            1/4
+        }
+    }
+    repeat 4 as i {
+        if (euclid(1, 4, 1)[i - 1]) {
+            E4 /4
+        } else {
+            1/4
+        }
+    }
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let times: Vec<f64> = played(&events).iter().map(|n| n.time).collect();
+        // x..x..x. then x... rotated left by one step
+        assert_eq!(times, [0.0, 0.75, 1.5, 2.75]);
+    }
+
+    #[test]
+    fn test_generator_errors() {
+        let errors = [
+            ("t(random(1, 2, 3));", "random() takes at most two numbers: random(min, max)."),
+            ("t(choose([]));", "choose() needs at least one item."),
+            ("t(choose(3));", "choose() needs a list, got a number."),
+            ("t(choose([C4], [0]));", "choose() needs one weight of 0 or more per item, not all 0."),
+            ("t(shuffle([C4], [E4]));", "shuffle() takes one list."),
+            ("t(euclid(5, 4));", "euclid() needs 0 to 4 hits in 4 steps, got 5."),
+            ("t(euclid(1.5, 4));", "euclid() takes whole numbers: euclid(hits, steps) or euclid(hits, steps, rotation)."),
+            ("t(C4[0]);", "Cannot index 'C4': it is not a list."),
+            ("t([C4, E4][0.5]);", "A list index must be a whole number, got 0.5."),
+            ("t(random([1]));", "'[1]' is not a number."),
+        ];
+        for (call, message) in errors {
+            let program = parse(&format!("{call}\ntrack t(x) {{\n}}")).unwrap();
+            assert_eq!(compile(&program).unwrap_err(), message, "{call}");
+        }
+
+        let program = parse("t();\ntrack t() {\n    track.transpose = choose([C4]);\n}").unwrap();
+        assert_eq!(compile(&program).unwrap_err(), "'choose([C4])' is a pitch, not a number.");
+    }
+
+    #[test]
+    fn test_play_duration_truncates_notes() {
+        let program = parse(
+            r#"
+riff@3();
+end();
+
+track riff() {
+    C4 /2
+    D4@2 /2
+    E4 /2
+    F4@4 2
+    G4 /1
+}
+
+track end() {
+    C5 /1
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.time, n.gate)).collect::<Vec<_>>(),
+            [
+                ("C4", 0.0, 1.0),
+                ("D4", 0.5, 2.0),
+                ("E4", 1.0, 1.0),
+                ("F4", 1.5, 1.5),
+                ("C5", 3.0, 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_play_duration_drops_later_properties() {
+        let program = parse(
+            r#"
+a@2();
+
+track a() {
+    track.beatsPerMinute = 90;
+    C4 /2
+    C4 /2
+    C4 /2
+    C4 /2
+    track.beatsPerMinute = 30;
+    C4 /2
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let properties: Vec<(f64, &str)> = events
+            .events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::SetProperty { value, .. } => Some((e.time, value.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(properties, [(0.0, "90")]);
+    }
+
+    #[test]
+    fn test_looped_play_duration_repeats_to_fill() {
+        let program = parse(
+            r#"
+beat@~5() 8;
+end();
+
+track beat() {
+    track.noteLength = 1/2;
+    C2 /1
+    D2
+}
+
+track end() {
+    C5 /1
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        assert_eq!(
+            played(&events).iter().map(|n| (n.pitch, n.time, n.gate)).collect::<Vec<_>>(),
+            [
+                ("C2", 0.0, 0.5),
+                ("D2", 1.0, 0.5),
+                ("C2", 1.5, 0.5),
+                ("D2", 2.5, 0.5),
+                ("C2", 3.0, 0.5),
+                ("D2", 4.0, 0.5),
+                ("C2", 4.5, 0.5),
+                ("C5", 8.0, 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_play_duration_cuts_on_the_grid_beat() {
+        let program = parse(
+            r#"
+groove rush = {unit: 1/2, timing: [-0.1], velocity: [0]};
+rushed@1();
+swung@0.55() 1;
+
+track rushed() {
+    track.groove = 'rush';
+    C4 /2
+    D4 /2
+    E4 /2
+}
+
+track swung() {
+    track.swing = 0.6;
+    F4 /2
+    G4 /2
+}
+"#,
+        )
+        .unwrap();
+
+        let events = compile(&program).unwrap();
+        let notes: Vec<(&str, f64, f64)> =
+            played(&events).iter().map(|n| (n.pitch, n.time, n.gate)).collect();
+        // E4 is rushed ahead of the limit but sits on it, so it is cut; G4
+        // swings past the limit but starts before it, so it is gated.
+        let expected = [
+            ("C4", 0.0, 1.0),
+            ("D4", 0.45, 0.5),
+            ("F4", 1.0, 0.55),
+            ("G4", 1.6, 0.05),
+        ];
+        assert_eq!(notes.len(), expected.len(), "{notes:?}");
+        for ((pitch, time, gate), (p, t, g)) in notes.iter().zip(expected) {
+            assert_eq!(*pitch, p);
+            assert!((time - t).abs() < 1e-9, "{notes:?}");
+            assert!((gate - g).abs() < 1e-9, "{notes:?}");
+        }
+    }
+
+    #[test]
+    fn test_looped_call_without_length_is_an_error() {
+        let program = parse("empty@~4();\ntrack empty() {\n    track.noteLength = 1/2;\n}").unwrap();
+        assert_eq!(
+            compile(&program).unwrap_err(),
+            format!("Looped call to 'empty' ran {MAX_LOOP_PASSES} times without filling its duration.")
+        );
+    }
+
     #[test]
     fn test_string_shorthand_instrument() {
         let program = parse(
@@ -1005,4 +2999,113 @@ track riff() {
             assert_eq!(instrument.waveform, "sawtooth");
         }
     }
+
+    #[test]
+    fn test_resolve_consts() {
+        let program = crate::parse(
+            "const lead = Oscillator({type: 'square', attack: 0.1});\nconst alias = lead;\nconst bad = nope;",
+        )
+        .unwrap();
+        let consts = resolve_consts(&program);
+        assert_eq!(consts["lead"].waveform, "square");
+        assert_eq!(consts["alias"].attack, Some(0.1));
+        assert!(!consts.contains_key("bad"));
+    }
+
+    #[test]
+    fn test_compile_recovering_keeps_valid_tracks() {
+        let source = r#"
+good();
+bad();
+
+track good() {
+    C3 /4
+    D3 / )
+    E3 /4
+}
+
+track bad() {
+    track.instrument = nothing;
+    G3 /4
+}
+"#;
+        let (program, errors) = crate::parse_recovering(source);
+        assert_eq!(errors.len(), 1);
+
+        let (events, compile_errors) = compile_recovering(&program);
+        assert_eq!(compile_errors.len(), 1);
+        assert!(compile_errors[0].0.contains("nothing"));
+        // Attributed to the failing statement inside `bad`, not the call.
+        let failing = source.find("track.instrument = nothing").unwrap();
+        assert_eq!(compile_errors[0].1.start, failing);
+
+        let pitches: Vec<_> = events
+            .events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note { pitch, .. } => Some(pitch.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pitches, ["C3", "E3"]);
+        assert_eq!(events.total_beats, 0.5);
+    }
+
+    #[test]
+    fn test_compile_recovering_rolls_back_end_and_random_state() {
+        let tracks = r#"
+track long() {
+    repeat 8 { C3 /1 }
+}
+
+track bad() {
+    track.humanizeVelocity = 20;
+    C4 /1
+    C4 /1
+    track.instrument = nothing;
+}
+
+track good() {
+    track.humanizeVelocity = 20;
+    E4 /1
+    E4 /1
+}
+"#;
+        let failing = format!("song.seed = 3;\nparallel {{\n    long();\n    bad();\n}}\ngood();\n{tracks}");
+        let (events, compile_errors) = compile_recovering(&parse(&failing).unwrap());
+        assert_eq!(compile_errors.len(), 1);
+
+        let expected = compile(&parse(&format!("song.seed = 3;\ngood();\n{tracks}")).unwrap()).unwrap();
+        let placements = |events: &EventList| -> Vec<(f64, f64)> {
+            played(events).iter().map(|n| (n.time, n.velocity)).collect()
+        };
+        assert_eq!(placements(&events), placements(&expected));
+        assert_eq!(events.total_beats, 2.0);
+    }
+
+    #[test]
+    fn test_set_property_and_track_start_spans() {
+        let src = "track.beatsPerMinute = 140;\nexternal(1) 4;";
+        let program = parse(src).unwrap();
+        let events = compile(&program).unwrap();
+
+        let spans: Vec<_> = events
+            .events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::SetProperty {
+                    source_start,
+                    source_end,
+                    ..
+                }
+                | EventKind::TrackStart {
+                    source_start,
+                    source_end,
+                    ..
+                } => Some(&src[*source_start..*source_end]),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(spans, ["track.beatsPerMinute = 140", "external(1) 4"]);
+    }
 }
@@ -5,7 +5,7 @@
 //! - **Split**: Route notes to children by MIDI key range
 //! - **Chain**: Audio passes through children in series (for effects)
 
-use super::sampler::{SamplerVoice, LoadedZone, Sampler};
+use super::sampler::{SamplerVoice, Sampler};
 
 /// Mode of combination for composite children.
 #[derive(Debug, Clone, PartialEq)]
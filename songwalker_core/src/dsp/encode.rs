@@ -0,0 +1,586 @@
+//! Audio file encoders — stream a `Renderer` to WAV, FLAC or raw PCM.
+//!
+//! Every format is written block by block from the renderer's current
+//! position to its end, so memory use doesn't grow with song length. The
+//! mono mix is duplicated to every output channel. On its way out the
+//! master passes through the output gain, an optional true-peak limiter
+//! and a loudness meter.
+
+use std::io::{self, Write};
+
+use super::flac::FlacEncoder;
+use super::limiter::Limiter;
+use super::loudness::{Loudness, LoudnessMeter};
+use super::renderer::Renderer;
+
+/// Frames rendered per encoding step.
+const CHUNK: usize = 4096;
+
+/// Sample encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl SampleFormat {
+    pub fn bits(self) -> u16 {
+        match self {
+            SampleFormat::Int16 => 16,
+            SampleFormat::Int24 => 24,
+            SampleFormat::Float32 => 32,
+        }
+    }
+
+    fn bytes(self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// Largest integer sample value, or `None` for float.
+    fn int_max(self) -> Option<f64> {
+        match self {
+            SampleFormat::Int16 => Some(32767.0),
+            SampleFormat::Int24 => Some(8_388_607.0),
+            SampleFormat::Float32 => None,
+        }
+    }
+}
+
+/// Output file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Wav,
+    Flac,
+    /// Headerless interleaved little-endian samples.
+    Raw,
+}
+
+impl FileFormat {
+    /// Parse a format name (`wav`, `flac`, `raw`/`pcm`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "wav" | "wave" => Some(FileFormat::Wav),
+            "flac" => Some(FileFormat::Flac),
+            "raw" | "pcm" => Some(FileFormat::Raw),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from a file name's extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, ext) = path.rsplit_once('.')?;
+        Self::from_name(ext)
+    }
+}
+
+/// How samples are written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeSpec {
+    pub sample_rate: u32,
+    /// 1 (mono) or 2 (stereo).
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+    /// Linear gain applied before quantization.
+    pub gain: f64,
+    /// Add TPDF dither when quantizing to integers.
+    pub dither: bool,
+    /// Limit the master to this ceiling in dBTP, in place of the
+    /// renderer's soft clipper.
+    pub limiter: Option<f64>,
+}
+
+impl EncodeSpec {
+    /// 16-bit stereo at unity gain, undithered.
+    pub fn new(sample_rate: u32) -> Self {
+        EncodeSpec {
+            sample_rate,
+            channels: 2,
+            sample_format: SampleFormat::Int16,
+            gain: 1.0,
+            dither: false,
+            limiter: None,
+        }
+    }
+}
+
+/// Encode a renderer from its current position to the end, returning the
+/// loudness of what was written. Any loop range is ignored. FLAC supports
+/// integer formats only.
+pub fn encode<W: Write>(
+    renderer: &mut Renderer,
+    spec: &EncodeSpec,
+    format: FileFormat,
+    out: W,
+) -> io::Result<Loudness> {
+    let frames = prepare(renderer, spec);
+    let mut encoder = Encoder::new(out, spec, format, frames)?;
+    let mut master = Master::new(spec);
+    let mut block = [0.0_f64; CHUNK];
+    loop {
+        let n = renderer.process_f64(&mut block);
+        if n == 0 {
+            break;
+        }
+        encoder.write(master.process(&block[..n]))?;
+    }
+    encoder.write(master.flush())?;
+    encoder.finish()?;
+    Ok(master.meter.loudness())
+}
+
+/// Encode the master mix and one file per bus in a single pass, so every
+/// stem is sample-aligned with the master. `stems` are in the order of
+/// `Renderer::buses`. Stems get the output gain but bypass the limiter.
+/// Returns the master's loudness.
+pub fn encode_stems<W: Write>(
+    renderer: &mut Renderer,
+    spec: &EncodeSpec,
+    format: FileFormat,
+    master: W,
+    stems: Vec<W>,
+) -> io::Result<Loudness> {
+    if stems.len() != renderer.buses().len() {
+        return Err(invalid(format!(
+            "{} stem outputs for {} buses",
+            stems.len(),
+            renderer.buses().len()
+        )));
+    }
+    let frames = prepare(renderer, spec);
+    let mut master_encoder = Encoder::new(master, spec, format, frames)?;
+    let mut master = Master::new(spec);
+    let mut encoders = stems
+        .into_iter()
+        .map(|out| Encoder::new(out, spec, format, frames))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut block = [0.0_f64; CHUNK];
+    let mut stem_blocks = vec![Vec::new(); encoders.len()];
+    loop {
+        let n = renderer.process_stems(&mut block, &mut stem_blocks);
+        if n == 0 {
+            break;
+        }
+        master_encoder.write(master.process(&block[..n]))?;
+        for (encoder, stem) in encoders.iter_mut().zip(&mut stem_blocks) {
+            stem[..n].iter_mut().for_each(|s| *s *= spec.gain);
+            encoder.write(&stem[..n])?;
+        }
+    }
+    master_encoder.write(master.flush())?;
+    master_encoder.finish()?;
+    for encoder in encoders {
+        encoder.finish()?;
+    }
+    Ok(master.meter.loudness())
+}
+
+/// Drop any loop and switch off the soft clipper if a limiter replaces it.
+/// Returns the number of frames left to encode.
+fn prepare(renderer: &mut Renderer, spec: &EncodeSpec) -> usize {
+    renderer.set_loop(None);
+    if spec.limiter.is_some() {
+        renderer.set_soft_clip(false);
+    }
+    renderer.total_samples() - renderer.position()
+}
+
+/// The master chain: output gain, then the limiter, then the meter. The
+/// limiter's latency is hidden by dropping its first output samples and
+/// flushing it with silence at the end, so the output lines up with the
+/// input sample for sample.
+struct Master {
+    gain: f64,
+    limiter: Option<Limiter>,
+    /// Leading limiter samples still to drop.
+    skip: usize,
+    meter: LoudnessMeter,
+    out: Vec<f64>,
+}
+
+impl Master {
+    fn new(spec: &EncodeSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let limiter = spec.limiter.map(|ceiling| Limiter::new(sample_rate, ceiling));
+        Master {
+            gain: spec.gain,
+            skip: limiter.as_ref().map_or(0, Limiter::latency),
+            limiter,
+            meter: LoudnessMeter::new(sample_rate, spec.channels),
+            out: Vec::with_capacity(CHUNK),
+        }
+    }
+
+    fn process(&mut self, block: &[f64]) -> &[f64] {
+        self.out.clear();
+        for &s in block {
+            let s = s * self.gain;
+            match self.limiter.as_mut() {
+                Some(limiter) => {
+                    let limited = limiter.process(s);
+                    if self.skip > 0 {
+                        self.skip -= 1;
+                    } else {
+                        self.out.push(limited);
+                    }
+                }
+                None => self.out.push(s),
+            }
+        }
+        self.meter.push(&self.out);
+        &self.out
+    }
+
+    /// The samples still inside the limiter.
+    fn flush(&mut self) -> &[f64] {
+        let latency = self.limiter.as_ref().map_or(0, Limiter::latency);
+        let silence = vec![0.0; latency];
+        // Zeros pass through the gain unchanged.
+        self.process(&silence)
+    }
+}
+
+/// Writes mono frames to one output file, duplicating them to every
+/// channel. The frame count is fixed up front because WAV and FLAC
+/// headers record it.
+pub struct Encoder<W: Write> {
+    sink: Sink<W>,
+    spec: EncodeSpec,
+    quantizer: Quantizer,
+    bytes: Vec<u8>,
+    ints: Vec<i32>,
+}
+
+enum Sink<W: Write> {
+    Pcm(W),
+    Flac(FlacEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Start a file of `frames` frames, writing its header.
+    pub fn new(mut out: W, spec: &EncodeSpec, format: FileFormat, frames: usize) -> io::Result<Self> {
+        if !matches!(spec.channels, 1 | 2) {
+            return Err(invalid(format!("unsupported channel count: {}", spec.channels)));
+        }
+        let sink = match format {
+            FileFormat::Wav => {
+                write_wav_header(&mut out, spec, frames)?;
+                Sink::Pcm(out)
+            }
+            FileFormat::Raw => Sink::Pcm(out),
+            FileFormat::Flac => {
+                if spec.sample_format == SampleFormat::Float32 {
+                    return Err(invalid("FLAC does not support float samples".to_string()));
+                }
+                Sink::Flac(FlacEncoder::new(
+                    out,
+                    spec.sample_rate,
+                    spec.channels,
+                    spec.sample_format.bits(),
+                    frames as u64,
+                )?)
+            }
+        };
+        Ok(Encoder {
+            sink,
+            spec: *spec,
+            quantizer: Quantizer::new(spec),
+            bytes: Vec::new(),
+            ints: Vec::new(),
+        })
+    }
+
+    /// Append mono frames, already scaled by the output gain.
+    pub fn write(&mut self, samples: &[f64]) -> io::Result<()> {
+        let channels = self.spec.channels as usize;
+        let format = self.spec.sample_format;
+        match &mut self.sink {
+            Sink::Pcm(out) => {
+                // Interleaved little-endian samples
+                self.bytes.clear();
+                for &s in samples {
+                    let sample: [u8; 4] = match format {
+                        SampleFormat::Float32 => (s as f32).to_le_bytes(),
+                        _ => self.quantizer.int(s).to_le_bytes(),
+                    };
+                    for _ in 0..channels {
+                        self.bytes.extend_from_slice(&sample[..format.bytes()]);
+                    }
+                }
+                out.write_all(&self.bytes)
+            }
+            Sink::Flac(flac) => {
+                self.ints.clear();
+                for &s in samples {
+                    let sample = self.quantizer.int(s);
+                    self.ints.extend(std::iter::repeat_n(sample, channels));
+                }
+                flac.write(&self.ints)
+            }
+        }
+    }
+
+    /// Flush the last frames and return the output.
+    pub fn finish(self) -> io::Result<W> {
+        match self.sink {
+            Sink::Pcm(mut out) => {
+                out.flush()?;
+                Ok(out)
+            }
+            Sink::Flac(flac) => flac.finish(),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn write_wav_header<W: Write>(out: &mut W, spec: &EncodeSpec, frames: usize) -> io::Result<()> {
+    let float = spec.sample_format == SampleFormat::Float32;
+    let channels = spec.channels;
+    let bits_per_sample = spec.sample_format.bits();
+    let block_align = channels * (bits_per_sample / 8);
+    let byte_rate = spec.sample_rate * block_align as u32;
+    let data_size = (frames * block_align as usize) as u32;
+    // Float files carry the extended fmt chunk and a fact chunk.
+    let fmt_size: u32 = if float { 18 } else { 16 };
+    let fact_size: u32 = if float { 12 } else { 0 };
+    let file_size = 4 + (8 + fmt_size) + fact_size + 8 + data_size;
+
+    // RIFF header
+    out.write_all(b"RIFF")?;
+    out.write_all(&file_size.to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    // fmt chunk
+    out.write_all(b"fmt ")?;
+    out.write_all(&fmt_size.to_le_bytes())?;
+    let format_tag: u16 = if float { 3 } else { 1 }; // IEEE float / PCM
+    out.write_all(&format_tag.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&spec.sample_rate.to_le_bytes())?;
+    out.write_all(&byte_rate.to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&bits_per_sample.to_le_bytes())?;
+    if float {
+        out.write_all(&0u16.to_le_bytes())?; // no extension
+        out.write_all(b"fact")?;
+        out.write_all(&4u32.to_le_bytes())?;
+        out.write_all(&(frames as u32).to_le_bytes())?;
+    }
+
+    // data chunk
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())
+}
+
+// ── Quantization ────────────────────────────────────────────
+
+/// Converts float samples to integers, with optional TPDF dither: the sum
+/// of two uniform random values spanning ±1 LSB, which decorrelates the
+/// rounding error from the signal. The generator is seeded, so renders
+/// stay reproducible.
+struct Quantizer {
+    max: f64,
+    dither: bool,
+    rng: u64,
+}
+
+impl Quantizer {
+    fn new(spec: &EncodeSpec) -> Self {
+        Quantizer {
+            max: spec.sample_format.int_max().unwrap_or(1.0),
+            dither: spec.dither,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    fn int(&mut self, sample: f64) -> i32 {
+        let mut scaled = sample * self.max;
+        if self.dither {
+            scaled += self.uniform() - self.uniform();
+        }
+        scaled.round().clamp(-self.max - 1.0, self.max) as i32
+    }
+
+    /// Uniform value in [0, 1) from xorshift64.
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::engine::AudioEngine;
+
+    fn renderer() -> Renderer {
+        let source = "riff();\ntrack riff() {\n    C4 /4\n    [E4, G4]@1 /4\n    C5 /2\n}\n";
+        let song = crate::compiler::compile(&crate::parse(source).unwrap()).unwrap();
+        AudioEngine::new(8000.0).renderer(&song)
+    }
+
+    fn encode_to_vec(renderer: &mut Renderer, spec: &EncodeSpec, format: FileFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode(renderer, spec, format, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn wav_spec_and_beat_range() {
+        let mut renderer = renderer();
+        renderer.seek(renderer.beat_to_sample(0.25));
+        renderer.set_end(renderer.beat_to_sample(0.75));
+        let spec = EncodeSpec {
+            channels: 1,
+            sample_format: SampleFormat::Int24,
+            gain: 0.5,
+            ..EncodeSpec::new(8000)
+        };
+        let wav = encode_to_vec(&mut renderer, &spec, FileFormat::Wav);
+
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 1);
+        assert_eq!(u16::from_le_bytes([wav[34], wav[35]]), 24);
+        // Half a beat at 120 BPM = 2000 frames of 3 bytes.
+        let data_size = u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]);
+        assert_eq!(data_size, 6000);
+        assert_eq!(wav.len(), 44 + 6000);
+    }
+
+    #[test]
+    fn float_wav_matches_renderer() {
+        let expected: Vec<f32> = {
+            let mut r = renderer();
+            let mut buf = vec![0.0; r.total_samples()];
+            r.process(&mut buf);
+            buf
+        };
+        let spec = EncodeSpec {
+            channels: 1,
+            sample_format: SampleFormat::Float32,
+            ..EncodeSpec::new(8000)
+        };
+        let wav = encode_to_vec(&mut renderer(), &spec, FileFormat::Wav);
+        assert_eq!(u16::from_le_bytes([wav[20], wav[21]]), 3);
+        assert_eq!(&wav[38..42], b"fact");
+        assert_eq!(&wav[50..54], b"data");
+        let samples: Vec<f32> = wav[58..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(samples, expected);
+        let riff_size = u32::from_le_bytes([wav[4], wav[5], wav[6], wav[7]]) as usize;
+        assert_eq!(riff_size + 8, wav.len());
+    }
+
+    #[test]
+    fn flac_decodes_to_the_same_pcm_as_wav() {
+        let spec = EncodeSpec::new(8000);
+        let raw = encode_to_vec(&mut renderer(), &spec, FileFormat::Raw);
+        let flac = encode_to_vec(&mut renderer(), &spec, FileFormat::Flac);
+        assert!(flac.len() < raw.len());
+
+        let mut reader = claxon::FlacReader::new(&flac[..]).unwrap();
+        let decoded: Vec<i16> = reader.samples().map(|s| s.unwrap() as i16).collect();
+        let pcm: Vec<i16> = raw.chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(decoded, pcm);
+
+        let float = EncodeSpec {
+            sample_format: SampleFormat::Float32,
+            ..spec
+        };
+        assert!(encode(&mut renderer(), &float, FileFormat::Flac, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn dither_is_small_and_reproducible() {
+        let spec = EncodeSpec {
+            channels: 1,
+            ..EncodeSpec::new(8000)
+        };
+        let dithered = EncodeSpec { dither: true, ..spec };
+        let plain = encode_to_vec(&mut renderer(), &spec, FileFormat::Raw);
+        let a = encode_to_vec(&mut renderer(), &dithered, FileFormat::Raw);
+        let b = encode_to_vec(&mut renderer(), &dithered, FileFormat::Raw);
+        assert_eq!(a, b);
+        assert_ne!(a, plain);
+        let to_i16 = |v: &[u8]| -> Vec<i16> {
+            v.chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect()
+        };
+        for (x, y) in to_i16(&a).iter().zip(to_i16(&plain)) {
+            assert!((*x as i32 - y as i32).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn stems_are_aligned_with_the_master() {
+        let source = "a();\nb() 0;\ntrack a() {\n    C4 /2\n}\ntrack b() {\n    G4@1 /2\n}\n";
+        let song = crate::compiler::compile(&crate::parse(source).unwrap()).unwrap();
+        let engine = AudioEngine::new(8000.0);
+        let mut renderer =
+            engine.stem_renderer(&song, crate::dsp::engine::StemGrouping::Track);
+        let spec = EncodeSpec::new(8000);
+        let (mut master, mut stems) = (Vec::new(), vec![Vec::new(), Vec::new()]);
+        {
+            let outs = stems.iter_mut().collect();
+            encode_stems(&mut renderer, &spec, FileFormat::Wav, &mut master, outs).unwrap();
+        }
+        let plain = encode_to_vec(&mut engine.renderer(&song), &spec, FileFormat::Wav);
+        assert_eq!(master, plain);
+        assert!(stems.iter().all(|stem| stem.len() == master.len()));
+        assert_ne!(stems[0], stems[1]);
+
+        let too_few = vec![Vec::new()];
+        let mut renderer =
+            engine.stem_renderer(&song, crate::dsp::engine::StemGrouping::Track);
+        assert!(encode_stems(&mut renderer, &spec, FileFormat::Wav, Vec::new(), too_few).is_err());
+    }
+
+    #[test]
+    fn limiter_holds_the_ceiling_without_shifting_audio() {
+        let source = "pad();\ntrack pad() {\n    track.instrument = 'square';\n    [C3, E3, G3, C4, E4] 2\n}\n";
+        let song = crate::compiler::compile(&crate::parse(source).unwrap()).unwrap();
+        let engine = AudioEngine::new(8000.0);
+        let spec = EncodeSpec {
+            channels: 1,
+            sample_format: SampleFormat::Float32,
+            gain: 4.0,
+            ..EncodeSpec::new(8000)
+        };
+        let limited = EncodeSpec {
+            limiter: Some(-1.0),
+            ..spec
+        };
+        let samples = |wav: &[u8]| -> Vec<f32> {
+            wav[58..].chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+        };
+
+        let mut out = Vec::new();
+        let loud = encode(&mut engine.renderer(&song), &spec, FileFormat::Wav, &mut out).unwrap();
+        let plain = samples(&out);
+        let mut out = Vec::new();
+        let loudness = encode(&mut engine.renderer(&song), &limited, FileFormat::Wav, &mut out).unwrap();
+        let limited = samples(&out);
+
+        assert_eq!(plain.len(), limited.len());
+        assert!(loud.true_peak > 6.0);
+        assert!(loudness.true_peak <= -0.9, "{}", loudness.true_peak);
+        assert!(loudness.integrated < loud.integrated);
+        assert!(limited.iter().all(|s| s.abs() <= 0.9));
+        // The first note starts at the same sample in both renders.
+        let onset = |v: &[f32]| v.iter().position(|s| s.abs() > 1e-4);
+        assert_eq!(onset(&plain), onset(&limited));
+    }
+
+    #[test]
+    fn formats_from_names() {
+        assert_eq!(FileFormat::from_path("out/song.FLAC"), Some(FileFormat::Flac));
+        assert_eq!(FileFormat::from_path("song.pcm"), Some(FileFormat::Raw));
+        assert_eq!(FileFormat::from_path("song"), None);
+        assert_eq!(FileFormat::from_name("wav"), Some(FileFormat::Wav));
+    }
+}
@@ -1,12 +1,12 @@
 //! Audio Engine — renders an EventList to audio samples.
 //!
-//! The engine manages voices, processes events at the correct sample offsets,
-//! and produces interleaved stereo f32 output.
+//! The engine turns note events into a sample-accurate schedule; the
+//! `Renderer` it creates plays that schedule block by block.
 
-use crate::compiler::{EndMode, EventKind, EventList, InstrumentConfig};
+use crate::compiler::{EndMode, EventKind, EventList};
+use crate::tempo::{DEFAULT_BPM, TempoMap};
 
-use super::mixer::Mixer;
-use super::voice::Voice;
+use super::renderer::{Renderer, ScheduledNote};
 
 /// Parse a note name (e.g. "C4", "F#3", "Bb5") into a MIDI note number.
 pub fn note_to_midi(note: &str) -> Option<i32> {
@@ -78,21 +78,19 @@ pub fn note_to_frequency_with_tuning(note: &str, tuning_pitch: f64) -> Option<f6
     Some(midi_to_frequency(midi, tuning_pitch))
 }
 
-/// Scheduled voice event for the engine.
-struct ScheduledNote {
-    /// Sample offset when the note starts.
-    start_sample: usize,
-    /// Sample offset when the note should be released (gate off).
-    release_sample: usize,
-    frequency: f64,
-    velocity: f64,
-    /// Instrument configuration for this note.
-    instrument: InstrumentConfig,
+/// How `AudioEngine::stem_renderer` splits notes into buses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StemGrouping {
+    /// One bus per top-level track call, named after the track.
+    Track,
+    /// One bus per instrument, named after its preset or waveform.
+    Instrument,
 }
 
 /// The audio rendering engine.
 pub struct AudioEngine {
     pub sample_rate: f64,
+    /// Tempo until the song's first `track.beatsPerMinute`.
     pub bpm: f64,
     /// Tuning pitch for A4 in Hz. Default is 440.0.
     pub tuning_pitch: f64,
@@ -103,7 +101,7 @@ impl AudioEngine {
     pub fn new(sample_rate: f64) -> Self {
         AudioEngine {
             sample_rate,
-            bpm: 120.0,
+            bpm: DEFAULT_BPM,
             tuning_pitch: 440.0,
             max_voices: 64,
         }
@@ -111,54 +109,81 @@ impl AudioEngine {
 
     /// Render an entire EventList to mono f64 samples.
     pub fn render(&self, event_list: &EventList) -> Vec<f64> {
-        // Extract BPM and tuning from events
-        let mut bpm = self.bpm;
+        let mut renderer = self.renderer(event_list);
+        let mut output = vec![0.0_f64; renderer.total_samples()];
+        renderer.process_f64(&mut output);
+        output
+    }
+
+    /// Create a streaming renderer for an EventList.
+    pub fn renderer(&self, event_list: &EventList) -> Renderer {
+        self.schedule(event_list, None)
+    }
+
+    /// Create a renderer that also mixes each track or instrument onto its
+    /// own bus, for `Renderer::process_stems`. Buses are in order of first
+    /// appearance.
+    pub fn stem_renderer(&self, event_list: &EventList, grouping: StemGrouping) -> Renderer {
+        self.schedule(event_list, Some(grouping))
+    }
+
+    fn schedule(&self, event_list: &EventList, grouping: Option<StemGrouping>) -> Renderer {
+        // Extract tempo changes and tuning from events
+        let tempo = TempoMap::from_events(event_list, self.bpm);
         let mut tuning_pitch = self.tuning_pitch;
         for evt in &event_list.events {
-            if let EventKind::SetProperty { target, value } = &evt.kind {
-                if target == "track.beatsPerMinute" {
-                    if let Ok(v) = value.parse::<f64>() {
-                        bpm = v;
-                    }
-                } else if target == "track.tuningPitch" {
-                    if let Ok(v) = value.parse::<f64>() {
-                        tuning_pitch = v;
-                    }
-                }
+            if let EventKind::SetProperty { target, value, .. } = &evt.kind
+                && target == "track.tuningPitch"
+                && let Ok(v) = value.parse::<f64>()
+            {
+                tuning_pitch = v;
             }
         }
 
-        let cursor_samples = {
-            let seconds = event_list.total_beats * 60.0 / bpm;
-            (seconds * self.sample_rate) as usize
-        };
+        let cursor_samples = (tempo.seconds_at(event_list.total_beats) * self.sample_rate) as usize;
 
         // Collect note events with their sample timings
         let mut scheduled: Vec<ScheduledNote> = Vec::new();
+        let mut buses: Vec<String> = Vec::new();
         for evt in &event_list.events {
             if let EventKind::Note {
-                pitch,
+                midi: Some(key),
                 velocity,
                 gate,
                 instrument,
+                top_track,
+                source_start,
+                source_end,
                 ..
             } = &evt.kind
             {
-                if let Some(freq) = note_to_frequency_with_tuning(pitch, tuning_pitch) {
-                    let start = {
-                        let s = evt.time * 60.0 / bpm;
-                        (s * self.sample_rate) as usize
-                    };
-                    let gate_seconds = gate * 60.0 / bpm;
-                    let release = start + (gate_seconds * self.sample_rate) as usize;
-                    scheduled.push(ScheduledNote {
-                        start_sample: start,
-                        release_sample: release,
-                        frequency: freq,
-                        velocity: *velocity / 127.0,
-                        instrument: instrument.clone(),
-                    });
-                }
+                let freq = midi_to_frequency(*key as i32, tuning_pitch);
+                let bus = match grouping {
+                    Some(StemGrouping::Track) => Some(top_track.clone()),
+                    Some(StemGrouping::Instrument) => Some(instrument.label().to_string()),
+                    None => None,
+                };
+                let bus = match bus {
+                    Some(name) => buses.iter().position(|b| *b == name).unwrap_or_else(|| {
+                        buses.push(name);
+                        buses.len() - 1
+                    }),
+                    None => 0,
+                };
+                let start_seconds = tempo.seconds_at(evt.time);
+                let start = (start_seconds * self.sample_rate) as usize;
+                let gate_seconds = tempo.seconds_at(evt.time + gate) - start_seconds;
+                let release = start + (gate_seconds * self.sample_rate) as usize;
+                scheduled.push(ScheduledNote {
+                    start_sample: start,
+                    release_sample: release,
+                    frequency: freq,
+                    velocity: *velocity / 127.0,
+                    instrument: instrument.clone(),
+                    source_start: *source_start,
+                    source_end: *source_end,
+                    bus,
+                });
             }
         }
 
@@ -203,63 +228,7 @@ impl AudioEngine {
             }
         };
 
-        // Render in blocks
-        let block_size = 128;
-        let mut mixer = Mixer::new();
-        let mut voices: Vec<Voice> = Vec::new();
-        let mut output = vec![0.0_f64; total_samples];
-        let mut next_note_idx = 0;
-
-        let mut block_start = 0;
-        while block_start < total_samples {
-            let block_end = (block_start + block_size).min(total_samples);
-            let this_block = block_end - block_start;
-
-            // Activate new notes that start in this block
-            while next_note_idx < scheduled.len()
-                && scheduled[next_note_idx].start_sample < block_end
-            {
-                let note = &scheduled[next_note_idx];
-                if voices.len() < self.max_voices {
-                    let mut voice = Voice::with_config(self.sample_rate, &note.instrument);
-                    voice.release_sample = note.release_sample;
-                    voice.note_on(note.frequency, note.velocity);
-                    voices.push(voice);
-                }
-                next_note_idx += 1;
-            }
-
-            // Check for note releases — each voice carries its own release_sample
-            for voice in voices.iter_mut() {
-                if voice.release_sample >= block_start && voice.release_sample < block_end {
-                    voice.note_off();
-                }
-            }
-
-            // Render voices into mixer
-            mixer.clear(this_block);
-            for voice in voices.iter_mut() {
-                if !voice.is_finished() {
-                    for i in 0..this_block {
-                        let sample = voice.next_sample();
-                        mixer.add(i, sample);
-                    }
-                }
-            }
-
-            // Copy mixer output to main buffer
-            let mixed = mixer.output();
-            for (i, &s) in mixed.iter().enumerate() {
-                output[block_start + i] = s;
-            }
-
-            // Remove finished voices
-            voices.retain(|v| !v.is_finished());
-
-            block_start = block_end;
-        }
-
-        output
+        Renderer::new(self.sample_rate, tempo, scheduled, total_samples, self.max_voices, buses)
     }
 
     /// Render to interleaved stereo i16 PCM (for WAV export).
@@ -288,15 +257,20 @@ mod tests {
                     kind: EventKind::SetProperty {
                         target: "track.beatsPerMinute".to_string(),
                         value: "120".to_string(),
+                        source_start: 0,
+                        source_end: 0,
                     },
                 },
                 Event {
                     time: 0.0,
                     kind: EventKind::Note {
                         pitch: "C4".to_string(),
+                        midi: Some(60),
                         velocity: 100.0,
                         gate: 1.0,
                         instrument: InstrumentConfig::default(),
+                        track: String::new(),
+                        top_track: String::new(),
                         source_start: 0,
                         source_end: 0,
                     },
@@ -305,9 +279,12 @@ mod tests {
                     time: 1.0,
                     kind: EventKind::Note {
                         pitch: "E4".to_string(),
+                        midi: Some(64),
                         velocity: 80.0,
                         gate: 1.0,
                         instrument: InstrumentConfig::default(),
+                        track: String::new(),
+                        top_track: String::new(),
                         source_start: 0,
                         source_end: 0,
                     },
@@ -416,6 +393,8 @@ mod tests {
                     kind: EventKind::SetProperty {
                         target: "track.beatsPerMinute".to_string(),
                         value: "120".to_string(),
+                        source_start: 0,
+                        source_end: 0,
                     },
                 },
                 Event {
@@ -423,15 +402,20 @@ mod tests {
                     kind: EventKind::SetProperty {
                         target: "track.tuningPitch".to_string(),
                         value: "432".to_string(),
+                        source_start: 0,
+                        source_end: 0,
                     },
                 },
                 Event {
                     time: 0.0,
                     kind: EventKind::Note {
                         pitch: "A4".to_string(),
+                        midi: Some(69),
                         velocity: 100.0,
                         gate: 1.0,
                         instrument: InstrumentConfig::default(),
+                        track: String::new(),
+                        top_track: String::new(),
                         source_start: 0,
                         source_end: 0,
                     },
@@ -510,9 +494,12 @@ mod tests {
                 time: 0.0,
                 kind: EventKind::Note {
                     pitch: "A4".to_string(),
+                    midi: Some(69),
                     velocity: 100.0,
                     gate: 1.0,
                     instrument: InstrumentConfig::default(),
+                    track: String::new(),
+                    top_track: String::new(),
                     source_start: 0,
                     source_end: 0,
                 },
@@ -526,9 +513,12 @@ mod tests {
                 time: 0.0,
                 kind: EventKind::Note {
                     pitch: "A4".to_string(),
+                    midi: Some(69),
                     velocity: 100.0,
                     gate: 1.0,
                     instrument: InstrumentConfig::default(),
+                    track: String::new(),
+                    top_track: String::new(),
                     source_start: 0,
                     source_end: 0,
                 },
@@ -562,15 +552,20 @@ mod tests {
                     kind: EventKind::SetProperty {
                         target: "track.beatsPerMinute".to_string(),
                         value: "120".to_string(),
+                        source_start: 0,
+                        source_end: 0,
                     },
                 },
                 Event {
                     time: 0.0,
                     kind: EventKind::Note {
                         pitch: "A4".to_string(),
+                        midi: Some(69),
                         velocity: 100.0,
                         gate: 0.1,
                         instrument: InstrumentConfig::default(),
+                        track: String::new(),
+                        top_track: String::new(),
                         source_start: 0,
                         source_end: 0,
                     },
@@ -592,4 +587,16 @@ mod tests {
             "Audio should be silent after note gate + release, max={tail_max}"
         );
     }
+
+    #[test]
+    fn notes_follow_tempo_changes() {
+        let source = "t();\ntrack t() {\n    C4@/4 2\n    track.beatsPerMinute = 60;\n    C4@/4 2\n}\n";
+        let song = crate::compiler::compile(&crate::parse(source).unwrap()).unwrap();
+        let engine = AudioEngine::new(1000.0);
+        let renderer = engine.renderer(&song);
+        // 2 beats at 120 BPM, then 2 beats at 60 BPM.
+        assert_eq!(renderer.total_samples(), 3000);
+        assert_eq!(renderer.beat_to_sample(2.0), 1000);
+        assert!((renderer.sample_to_beat(2000) - 3.0).abs() < 1e-9);
+    }
 }
@@ -220,13 +220,13 @@ mod tests {
 
         for _ in 0..10000 {
             let s = env.next_sample();
-            assert!(s >= 0.0 && s <= 1.0, "Envelope out of range: {s}");
+            assert!((0.0..=1.0).contains(&s), "Envelope out of range: {s}");
         }
 
         env.gate_off();
         for _ in 0..10000 {
             let s = env.next_sample();
-            assert!(s >= 0.0 && s <= 1.0, "Envelope out of range after release: {s}");
+            assert!((0.0..=1.0).contains(&s), "Envelope out of range after release: {s}");
         }
 
         assert!(env.is_finished());
@@ -0,0 +1,470 @@
+//! FLAC encoder — lossless compression for rendered audio, in pure Rust.
+//!
+//! Each block of 4096 frames is coded per channel with the best of a
+//! constant, verbatim or fixed-predictor (order 0–4) subframe, with
+//! Rice-coded residuals split into up to 256 partitions. Stereo uses
+//! left/side decorrelation, which costs almost nothing for SongWalker's
+//! mono mix duplicated to both channels. The STREAMINFO MD5 is left
+//! unset (allowed by the format), so encoding streams in one pass.
+
+use std::io::{self, Write};
+
+/// Frames per FLAC block.
+const BLOCK_SIZE: usize = 4096;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+/// Largest Rice parameter codable without the escape code.
+const MAX_RICE_PARAM: u32 = 14;
+
+/// Streaming FLAC writer. Feed interleaved integer samples with `write`,
+/// then call `finish`.
+pub struct FlacEncoder<W: Write> {
+    out: W,
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    /// Interleaved samples waiting for a full block.
+    pending: Vec<i32>,
+    frame_number: u64,
+}
+
+impl<W: Write> FlacEncoder<W> {
+    /// Write the stream header. `total_frames` is recorded in STREAMINFO.
+    pub fn new(
+        mut out: W,
+        sample_rate: u32,
+        channels: u16,
+        bits_per_sample: u16,
+        total_frames: u64,
+    ) -> io::Result<Self> {
+        if !(1..=2).contains(&channels) || sample_size_code(bits_per_sample as u32).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported FLAC format: {bits_per_sample}-bit, {channels} channel(s)"),
+            ));
+        }
+        let mut header = BitWriter::new();
+        header.write(1, 1); // last metadata block
+        header.write(0, 7); // STREAMINFO
+        header.write(34, 24);
+        header.write(BLOCK_SIZE as u64, 16); // min block size
+        header.write(BLOCK_SIZE as u64, 16); // max block size
+        header.write(0, 24); // min frame size (unknown)
+        header.write(0, 24); // max frame size (unknown)
+        header.write(sample_rate as u64, 20);
+        header.write(channels as u64 - 1, 3);
+        header.write(bits_per_sample as u64 - 1, 5);
+        header.write(total_frames, 36);
+        header.write(0, 64); // MD5 (unset)
+        header.write(0, 64);
+
+        out.write_all(b"fLaC")?;
+        out.write_all(&header.into_bytes())?;
+        Ok(FlacEncoder {
+            out,
+            sample_rate,
+            channels: channels as usize,
+            bits_per_sample: bits_per_sample as u32,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+        })
+    }
+
+    /// Queue interleaved samples, encoding every full block.
+    pub fn write(&mut self, samples: &[i32]) -> io::Result<()> {
+        let block_len = BLOCK_SIZE * self.channels;
+        for chunk in samples.chunks(block_len) {
+            let room = block_len - self.pending.len();
+            let (now, later) = chunk.split_at(chunk.len().min(room));
+            self.pending.extend_from_slice(now);
+            if self.pending.len() == block_len {
+                self.flush_block()?;
+            }
+            self.pending.extend_from_slice(later);
+        }
+        Ok(())
+    }
+
+    /// Encode the final partial block and flush the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            self.flush_block()?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        let frames = self.pending.len() / self.channels;
+        let channel = |c: usize| -> Vec<i64> {
+            self.pending.iter().skip(c).step_by(self.channels).map(|&s| s as i64).collect()
+        };
+        let bps = self.bits_per_sample;
+
+        let mut frame = BitWriter::new();
+        // Frame header: sync code, fixed blocking, then block size and
+        // sample rate codes whose values may follow the frame number.
+        let (rate_code, rate_value) = sample_rate_code(self.sample_rate);
+        frame.write(0b11_1111_1111_1110, 14);
+        frame.write(0, 1); // reserved
+        frame.write(0, 1); // fixed blocking
+        frame.write(0b0111, 4); // 16-bit block size - 1 follows
+        frame.write(rate_code, 4);
+        let subframes: Vec<(Vec<i64>, u32)> = if self.channels == 2 {
+            let (left, right) = (channel(0), channel(1));
+            let side = left.iter().zip(&right).map(|(l, r)| l - r).collect();
+            frame.write(0b1000, 4); // left/side
+            vec![(left, bps), (side, bps + 1)]
+        } else {
+            frame.write(0, 4); // mono
+            vec![(channel(0), bps)]
+        };
+        frame.write(sample_size_code(bps).unwrap(), 3);
+        frame.write(0, 1); // reserved
+        frame.write_utf8(self.frame_number);
+        frame.write(frames as u64 - 1, 16);
+        if let Some((value, bits)) = rate_value {
+            frame.write(value, bits);
+        }
+        let crc = crc8(frame.bytes());
+        frame.write(crc as u64, 8);
+
+        for (samples, bits) in &subframes {
+            write_subframe(&mut frame, samples, *bits);
+        }
+        frame.align();
+        let crc = crc16(frame.bytes());
+        frame.write(crc as u64, 16);
+
+        self.out.write_all(frame.bytes())?;
+        self.pending.clear();
+        self.frame_number += 1;
+        Ok(())
+    }
+}
+
+/// Frame header code for a bit depth.
+fn sample_size_code(bits: u32) -> Option<u64> {
+    match bits {
+        8 => Some(0b001),
+        12 => Some(0b010),
+        16 => Some(0b100),
+        20 => Some(0b101),
+        24 => Some(0b110),
+        _ => None,
+    }
+}
+
+/// Frame header code for a sample rate, plus the explicit value that
+/// follows the header for rates without a code of their own.
+fn sample_rate_code(rate: u32) -> (u64, Option<(u64, u32)>) {
+    let code = match rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        _ if rate.is_multiple_of(1000) && rate / 1000 < 256 => return (0b1100, Some((rate as u64 / 1000, 8))),
+        _ if rate < 65536 => return (0b1101, Some((rate as u64, 16))),
+        _ if rate.is_multiple_of(10) && rate / 10 < 65536 => return (0b1110, Some((rate as u64 / 10, 16))),
+        _ => 0b0000, // from STREAMINFO
+    };
+    (code, None)
+}
+
+// ── Subframes ───────────────────────────────────────────────
+
+/// Write the cheapest subframe for one channel of a block.
+fn write_subframe(w: &mut BitWriter, samples: &[i64], bits: u32) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        w.write(0, 1);
+        w.write(0b000000, 6); // constant
+        w.write(0, 1); // no wasted bits
+        w.write_signed(samples[0], bits);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * bits as u64;
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (cost, partition_order, params) = best_rice_partitioning(&residual, order, samples.len());
+            (order as u64 * bits as u64 + cost, order, residual, partition_order, params)
+        })
+        .min_by_key(|candidate| candidate.0);
+
+    match best {
+        Some((cost, order, residual, partition_order, params)) if cost < verbatim_bits => {
+            w.write(0, 1);
+            w.write(0b001000 | order as u64, 6); // fixed predictor
+            w.write(0, 1);
+            for &s in &samples[..order] {
+                w.write_signed(s, bits);
+            }
+            w.write(0b00, 2); // Rice, 4-bit parameters
+            w.write(partition_order as u64, 4);
+            let partition_len = samples.len() >> partition_order;
+            let mut start = 0;
+            for (p, &k) in params.iter().enumerate() {
+                let end = (p + 1) * partition_len - order;
+                w.write(k as u64, 4);
+                for &r in &residual[start..end] {
+                    w.write_rice(zigzag(r), k);
+                }
+                start = end;
+            }
+        }
+        _ => {
+            w.write(0, 1);
+            w.write(0b000001, 6); // verbatim
+            w.write(0, 1);
+            for &s in samples {
+                w.write_signed(s, bits);
+            }
+        }
+    }
+}
+
+/// Residual of the fixed polynomial predictor of the given order.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    let s = samples;
+    (order..s.len())
+        .map(|i| match order {
+            0 => s[i],
+            1 => s[i] - s[i - 1],
+            2 => s[i] - 2 * s[i - 1] + s[i - 2],
+            3 => s[i] - 3 * s[i - 1] + 3 * s[i - 2] - s[i - 3],
+            _ => s[i] - 4 * s[i - 1] + 6 * s[i - 2] - 4 * s[i - 3] + s[i - 4],
+        })
+        .collect()
+}
+
+/// Choose the partition order and per-partition Rice parameters for a
+/// residual. Returns (estimated bits, order, parameters). Costs are
+/// estimated from each partition's sum, as libFLAC does, so the search
+/// stays linear in the block length.
+fn best_rice_partitioning(
+    residual: &[i64],
+    predictor_order: usize,
+    block_len: usize,
+) -> (u64, u32, Vec<u32>) {
+    let mut best: Option<(u64, u32, Vec<u32>)> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        if !block_len.is_multiple_of(partitions) || block_len / partitions <= predictor_order {
+            break;
+        }
+        let partition_len = block_len / partitions;
+        let mut cost = 6; // coding method + partition order
+        let mut params = Vec::with_capacity(partitions);
+        let mut start = 0;
+        for p in 0..partitions {
+            let end = (p + 1) * partition_len - predictor_order;
+            let sum: u64 = residual[start..end].iter().map(|&r| zigzag(r)).sum();
+            let (bits, k) = rice_param(sum, (end - start) as u64);
+            cost += 4 + bits;
+            params.push(k);
+            start = end;
+        }
+        if best.as_ref().is_none_or(|b| cost < b.0) {
+            best = Some((cost, partition_order, params));
+        }
+    }
+    best.expect("partition order 0 is always valid")
+}
+
+/// Estimate the best Rice parameter for `len` values summing to `sum`.
+fn rice_param(sum: u64, len: u64) -> (u64, u32) {
+    let estimate = |k: u32| len * (k as u64 + 1) + (sum >> k);
+    let mean = sum / len.max(1);
+    let guess = (u64::BITS - mean.leading_zeros()).min(MAX_RICE_PARAM);
+    (guess.saturating_sub(1)..=(guess + 1).min(MAX_RICE_PARAM))
+        .map(|k| (estimate(k), k))
+        .min()
+        .unwrap()
+}
+
+/// Map signed residuals to unsigned: 0, -1, 1, -2, ... → 0, 1, 2, 3, ...
+fn zigzag(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+// ── Bit writer and checksums ────────────────────────────────
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    /// Write the low `n` bits of `value`, most significant first.
+    fn write(&mut self, value: u64, n: u32) {
+        for i in (0..n).rev() {
+            self.acc = (self.acc << 1) | ((value >> i) & 1);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.bytes.push(self.acc as u8);
+                self.acc = 0;
+                self.bits = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, n: u32) {
+        self.write(value as u64 & ((1u64 << n) - 1), n);
+    }
+
+    fn write_rice(&mut self, value: u64, k: u32) {
+        for _ in 0..value >> k {
+            self.write(0, 1);
+        }
+        self.write(1, 1);
+        self.write(value, k);
+    }
+
+    /// FLAC's UTF-8-style variable-length integer.
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        // Continuation bytes carry 6 bits each; the lead byte starts with
+        // one 1 per byte in the sequence, then a 0.
+        let extra = (1..=6).find(|&n| value < 1u64 << (5 * n + 6)).unwrap_or(6);
+        self.write((1 << (extra + 1)) - 1, extra + 1);
+        self.write(0, 1);
+        self.write(value >> (6 * extra), 6 - extra);
+        for i in (0..extra).rev() {
+            self.write(0b10, 2);
+            self.write(value >> (6 * i), 6);
+        }
+    }
+
+    /// Pad with zero bits to a byte boundary.
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+
+    /// Completed bytes (the header must be byte-aligned when read).
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(samples: &[i32], channels: u16, bits: u16) -> Vec<u8> {
+        let frames = samples.len() / channels as usize;
+        let mut enc = FlacEncoder::new(Vec::new(), 44100, channels, bits, frames as u64).unwrap();
+        // Odd write sizes exercise block buffering.
+        for chunk in samples.chunks(1000 * channels as usize) {
+            enc.write(chunk).unwrap();
+        }
+        enc.finish().unwrap()
+    }
+
+    fn decode(bytes: &[u8]) -> (claxon::metadata::StreamInfo, Vec<i32>) {
+        let mut reader = claxon::FlacReader::new(bytes).unwrap();
+        let info = reader.streaminfo();
+        let samples = reader.samples().map(|s| s.unwrap()).collect();
+        (info, samples)
+    }
+
+    fn tone(frames: usize, amplitude: f64) -> Vec<i32> {
+        (0..frames)
+            .map(|i| ((i as f64 * 0.031).sin() * amplitude + (i as f64 * 0.37).sin() * amplitude * 0.1) as i32)
+            .collect()
+    }
+
+    #[test]
+    fn mono_16_bit_round_trip() {
+        let samples = tone(10_000, 20000.0);
+        let bytes = encode(&samples, 1, 16);
+        let (info, decoded) = decode(&bytes);
+        assert_eq!(info.samples, Some(10_000));
+        assert_eq!(info.bits_per_sample, 16);
+        assert_eq!(decoded, samples);
+        assert!(bytes.len() < samples.len() * 2, "should compress");
+    }
+
+    #[test]
+    fn stereo_24_bit_round_trip() {
+        let mono = tone(9_000, 4_000_000.0);
+        let mut samples: Vec<i32> = mono.iter().flat_map(|&s| [s, s]).collect();
+        // Make the channels differ in one spot.
+        samples[101] = -8_388_608;
+        let bytes = encode(&samples, 2, 24);
+        let (info, decoded) = decode(&bytes);
+        assert_eq!(info.channels, 2);
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn silence_and_noise_round_trip() {
+        let mut samples = vec![0; 5000];
+        let mut x = 12345u32;
+        samples.extend((0..5000).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            (x >> 16) as i16 as i32
+        }));
+        let (_, decoded) = decode(&encode(&samples, 1, 16));
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn utf8_frame_numbers() {
+        let mut w = BitWriter::new();
+        w.write_utf8(0x7F);
+        w.write_utf8(0x80);
+        w.write_utf8(0x800);
+        assert_eq!(w.into_bytes(), [0x7F, 0xC2, 0x80, 0xE0, 0xA0, 0x80]);
+    }
+}
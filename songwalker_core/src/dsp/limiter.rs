@@ -0,0 +1,141 @@
+//! Limiter — lookahead true-peak limiter for the master bus.
+//!
+//! The input is delayed by the lookahead so gain reduction can ramp down
+//! before a peak arrives instead of clipping it. Peaks are detected between
+//! samples (see `TruePeak`), so the output stays under the ceiling after
+//! conversion to analog or lossy formats too.
+
+use std::collections::VecDeque;
+
+use super::loudness::TruePeak;
+
+/// How far ahead the limiter looks, in seconds.
+const LOOKAHEAD: f64 = 0.005;
+/// Time constant for recovering from gain reduction, in seconds.
+const RELEASE: f64 = 0.08;
+
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    /// Linear ceiling.
+    ceiling: f64,
+    detector: TruePeak,
+    /// Lookahead in samples.
+    window: usize,
+    step: usize,
+    /// Gain needed for the previous sample interval.
+    prev_required: f64,
+    /// Sliding minimum of the required gain, as (step, gain) pairs.
+    hold: VecDeque<(usize, f64)>,
+    envelope: f64,
+    release: f64,
+    /// The last `window` envelope values and their sum, for smoothing.
+    smooth: VecDeque<f64>,
+    smooth_sum: f64,
+    delay: VecDeque<f64>,
+}
+
+impl Limiter {
+    /// A limiter holding true peaks at or below `ceiling_db` dBTP.
+    pub fn new(sample_rate: f64, ceiling_db: f64) -> Self {
+        let detector = TruePeak::new();
+        let window = ((LOOKAHEAD * sample_rate).round() as usize).max(1);
+        let latency = window + detector.latency();
+        Limiter {
+            ceiling: 10f64.powf(ceiling_db / 20.0),
+            detector,
+            window,
+            step: 0,
+            prev_required: 1.0,
+            hold: VecDeque::with_capacity(window),
+            envelope: 1.0,
+            release: 1.0 - (-1.0 / (RELEASE * sample_rate)).exp(),
+            smooth: std::iter::repeat_n(1.0, window).collect(),
+            smooth_sum: window as f64,
+            delay: std::iter::repeat_n(0.0, latency).collect(),
+        }
+    }
+
+    /// Delay in samples between input and output.
+    pub fn latency(&self) -> usize {
+        self.delay.len()
+    }
+
+    /// Process one sample, returning the limited sample `latency()`
+    /// samples earlier.
+    pub fn process(&mut self, sample: f64) -> f64 {
+        // Gain that keeps the latest interval under the ceiling. A sample
+        // borders two intervals, so it takes the lower of the two.
+        let peak = self.detector.push(sample);
+        let now = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+        let required = now.min(self.prev_required);
+        self.prev_required = now;
+
+        // Hold the lowest requirement across the lookahead window...
+        while self.hold.back().is_some_and(|&(_, g)| g >= required) {
+            self.hold.pop_back();
+        }
+        self.hold.push_back((self.step, required));
+        while self.hold.front().is_some_and(|&(s, _)| s + self.window <= self.step) {
+            self.hold.pop_front();
+        }
+        let held = self.hold.front().map_or(1.0, |&(_, g)| g);
+        self.step += 1;
+
+        // ...drop instantly and recover slowly...
+        self.envelope = if held < self.envelope {
+            held
+        } else {
+            self.envelope + (held - self.envelope) * self.release
+        };
+
+        // ...then average over the window. Every value averaged is at or
+        // below what the delayed peak needs, so the ramp lands in time.
+        self.smooth_sum += self.envelope - self.smooth.pop_front().unwrap_or(1.0);
+        self.smooth.push_back(self.envelope);
+        let gain = (self.smooth_sum / self.window as f64).min(1.0);
+
+        self.delay.push_back(sample);
+        self.delay.pop_front().unwrap_or(0.0) * gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 48000.0;
+
+    fn sine(amplitude: f64, samples: usize) -> Vec<f64> {
+        (0..samples)
+            .map(|i| amplitude * (std::f64::consts::TAU * 997.0 * i as f64 / RATE).sin())
+            .collect()
+    }
+
+    #[test]
+    fn quiet_signal_passes_through_delayed() {
+        let mut limiter = Limiter::new(RATE, -1.0);
+        let input = sine(0.5, 4800);
+        let output: Vec<f64> = input.iter().map(|&s| limiter.process(s)).collect();
+        let latency = limiter.latency();
+        for (out, inp) in output[latency..].iter().zip(&input) {
+            assert!((out - inp).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn loud_signal_stays_under_the_ceiling() {
+        let mut limiter = Limiter::new(RATE, -1.0);
+        // Silence, then a sudden burst 12 dB over full scale.
+        let mut input = vec![0.0; 2400];
+        input.extend(sine(4.0, 9600));
+        input.extend(vec![0.0; limiter.latency()]);
+        let output: Vec<f64> = input.iter().map(|&s| limiter.process(s)).collect();
+
+        let mut detector = TruePeak::new();
+        let true_peak = output.iter().fold(0.0_f64, |m, &s| m.max(detector.push(s)));
+        assert!(20.0 * true_peak.log10() <= -0.95, "{}", 20.0 * true_peak.log10());
+        // Heavily limited, but not silenced.
+        let louder = output.iter().filter(|s| s.abs() > 0.5).count();
+        assert!(louder > 4000);
+    }
+}
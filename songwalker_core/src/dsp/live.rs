@@ -0,0 +1,331 @@
+//! Live engine — plays the current instrument from real-time MIDI input.
+//!
+//! Unlike `AudioEngine`, which renders a precompiled `EventList`, the live
+//! engine has no timeline: note-on/note-off/CC messages are applied as they
+//! arrive and `process` renders the next block. Messages take effect at the
+//! start of the next block, so latency is one block (128 samples in an
+//! AudioWorklet). Input is omni — the channel of each message is ignored.
+
+use midly::MidiMessage;
+use midly::live::LiveEvent;
+use midly::stream::MidiStream;
+
+use crate::ast::Program;
+use crate::compiler::{self, EventKind, InstrumentConfig};
+
+use super::engine::midi_to_frequency;
+use super::mixer::Mixer;
+use super::voice::Voice;
+
+/// Pitch bend range in semitones at full deflection.
+const BEND_RANGE: f64 = 2.0;
+
+// Controller numbers handled by the engine.
+const CC_VOLUME: u8 = 7;
+const CC_SUSTAIN: u8 = 64;
+const CC_ALL_SOUND_OFF: u8 = 120;
+const CC_RESET_CONTROLLERS: u8 = 121;
+const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// A sounding voice and the key that started it.
+struct LiveVoice {
+    key: u8,
+    voice: Voice,
+    /// Key is still held down.
+    held: bool,
+    /// Key was released while the sustain pedal was down.
+    sustained: bool,
+}
+
+/// Block-based synth driven by MIDI messages.
+pub struct LiveEngine {
+    pub sample_rate: f64,
+    /// Tuning pitch for A4 in Hz. Default is 440.0.
+    pub tuning_pitch: f64,
+    instrument: InstrumentConfig,
+    voices: Vec<LiveVoice>,
+    max_voices: usize,
+    mixer: Mixer,
+    stream: MidiStream,
+    /// Channel volume (CC 7) as a gain [0, 1].
+    volume: f64,
+    /// Pitch bend in semitones.
+    bend: f64,
+    sustain_pedal: bool,
+}
+
+impl LiveEngine {
+    pub fn new(sample_rate: f64) -> Self {
+        LiveEngine {
+            sample_rate,
+            tuning_pitch: 440.0,
+            instrument: InstrumentConfig::default(),
+            voices: Vec::new(),
+            max_voices: 64,
+            mixer: Mixer::new(),
+            stream: MidiStream::new(),
+            volume: 1.0,
+            bend: 0.0,
+            sustain_pedal: false,
+        }
+    }
+
+    /// Set the instrument used for new notes. Sounding notes keep theirs.
+    pub fn set_instrument(&mut self, instrument: InstrumentConfig) {
+        self.instrument = instrument;
+    }
+
+    /// Number of voices still producing sound.
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Feed raw MIDI bytes. Messages may be split across calls and may use
+    /// running status; system messages are ignored.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut messages = Vec::new();
+        self.stream.feed(bytes, |event| {
+            if let LiveEvent::Midi { message, .. } = event {
+                messages.push(message);
+            }
+        });
+        for message in messages {
+            self.handle(message);
+        }
+    }
+
+    /// Apply one channel message.
+    pub fn handle(&mut self, message: MidiMessage) {
+        match message {
+            // Note-on with velocity 0 is a note-off by convention.
+            MidiMessage::NoteOn { key, vel } if vel == 0 => self.note_off(key.as_int()),
+            MidiMessage::NoteOn { key, vel } => self.note_on(key.as_int(), vel.as_int()),
+            MidiMessage::NoteOff { key, .. } => self.note_off(key.as_int()),
+            MidiMessage::Controller { controller, value } => {
+                self.control_change(controller.as_int(), value.as_int())
+            }
+            MidiMessage::PitchBend { bend } => self.pitch_bend(bend.as_f64()),
+            _ => {}
+        }
+    }
+
+    /// Start a note. Re-striking a sounding key releases the old voice;
+    /// when all voices are busy the oldest one is stolen.
+    pub fn note_on(&mut self, key: u8, velocity: u8) {
+        for v in self.voices.iter_mut().filter(|v| v.key == key && (v.held || v.sustained)) {
+            v.voice.note_off();
+            v.held = false;
+            v.sustained = false;
+        }
+        if self.voices.len() >= self.max_voices {
+            self.voices.remove(0);
+        }
+
+        let mut voice = Voice::with_config(self.sample_rate, &self.instrument);
+        voice.note_on(self.frequency(key), velocity as f64 / 127.0);
+        self.voices.push(LiveVoice {
+            key,
+            voice,
+            held: true,
+            sustained: false,
+        });
+    }
+
+    /// Release a note, or hold it until the sustain pedal is lifted.
+    pub fn note_off(&mut self, key: u8) {
+        let sustain = self.sustain_pedal;
+        for v in self.voices.iter_mut().filter(|v| v.key == key && v.held) {
+            v.held = false;
+            if sustain {
+                v.sustained = true;
+            } else {
+                v.voice.note_off();
+            }
+        }
+    }
+
+    /// Handle a control change: volume, sustain pedal, reset and
+    /// all-notes-off. Other controllers are ignored.
+    pub fn control_change(&mut self, controller: u8, value: u8) {
+        match controller {
+            CC_VOLUME => self.volume = value as f64 / 127.0,
+            CC_SUSTAIN => {
+                self.sustain_pedal = value >= 64;
+                if !self.sustain_pedal {
+                    for v in self.voices.iter_mut().filter(|v| v.sustained) {
+                        v.sustained = false;
+                        v.voice.note_off();
+                    }
+                }
+            }
+            CC_ALL_SOUND_OFF => self.voices.clear(),
+            CC_RESET_CONTROLLERS => {
+                self.volume = 1.0;
+                self.pitch_bend(0.0);
+                self.control_change(CC_SUSTAIN, 0);
+            }
+            CC_ALL_NOTES_OFF => self.all_notes_off(),
+            _ => {}
+        }
+    }
+
+    /// Bend all sounding notes; `bend` is in [-1, 1].
+    pub fn pitch_bend(&mut self, bend: f64) {
+        self.bend = bend.clamp(-1.0, 1.0) * BEND_RANGE;
+        for i in 0..self.voices.len() {
+            let frequency = self.frequency(self.voices[i].key);
+            self.voices[i].voice.oscillator.frequency = frequency;
+        }
+    }
+
+    /// Release every note, including ones held by the sustain pedal.
+    pub fn all_notes_off(&mut self) {
+        self.sustain_pedal = false;
+        for v in &mut self.voices {
+            v.held = false;
+            v.sustained = false;
+            v.voice.note_off();
+        }
+    }
+
+    /// Render the next block of mono samples into `out`.
+    pub fn process(&mut self, out: &mut [f32]) {
+        self.mixer.clear(out.len());
+        for v in &mut self.voices {
+            for i in 0..out.len() {
+                self.mixer.add(i, v.voice.next_sample() * self.volume);
+            }
+        }
+        for (o, s) in out.iter_mut().zip(self.mixer.output()) {
+            *o = s as f32;
+        }
+        self.voices.retain(|v| !v.voice.is_finished());
+    }
+
+    fn frequency(&self, key: u8) -> f64 {
+        midi_to_frequency(key as i32, self.tuning_pitch) * 2f64.powf(self.bend / 12.0)
+    }
+}
+
+/// Pick the instrument to play live from a song: the named `const`, or
+/// else the instrument of the song's first note.
+pub fn instrument_from_program(
+    program: &Program,
+    name: Option<&str>,
+) -> Result<InstrumentConfig, String> {
+    if let Some(name) = name {
+        return compiler::resolve_consts(program)
+            .remove(name)
+            .ok_or_else(|| format!("No instrument const named '{name}'"));
+    }
+    let events = compiler::compile(program)?;
+    Ok(events
+        .events
+        .into_iter()
+        .find_map(|e| match e.kind {
+            EventKind::Note { instrument, .. } => Some(instrument),
+            _ => None,
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn short_release() -> InstrumentConfig {
+        InstrumentConfig {
+            attack: Some(0.001),
+            release: Some(0.01),
+            ..InstrumentConfig::default()
+        }
+    }
+
+    fn peak(engine: &mut LiveEngine, samples: usize) -> f32 {
+        let mut block = vec![0.0; samples];
+        engine.process(&mut block);
+        block.iter().fold(0.0, |m, s| m.max(s.abs()))
+    }
+
+    #[test]
+    fn note_on_sounds_until_released() {
+        let mut engine = LiveEngine::new(44100.0);
+        engine.set_instrument(short_release());
+        assert_eq!(peak(&mut engine, 128), 0.0);
+
+        engine.feed(&[0x90, 60, 100]);
+        assert!(peak(&mut engine, 1024) > 0.01);
+        assert_eq!(engine.active_voices(), 1);
+
+        // Note-off via running status: note-on with velocity 0.
+        engine.feed(&[0x90, 62, 100, 60, 0]);
+        assert_eq!(engine.active_voices(), 2);
+        engine.feed(&[0x80, 62, 0]);
+        peak(&mut engine, 4410);
+        assert_eq!(engine.active_voices(), 0);
+        assert_eq!(peak(&mut engine, 128), 0.0);
+    }
+
+    #[test]
+    fn sustain_pedal_holds_released_notes() {
+        let mut engine = LiveEngine::new(44100.0);
+        engine.set_instrument(short_release());
+        engine.feed(&[0xB0, 64, 127, 0x90, 60, 100]);
+        engine.feed(&[0x80, 60, 0]);
+        peak(&mut engine, 4410);
+        assert_eq!(engine.active_voices(), 1);
+
+        engine.feed(&[0xB0, 64, 0]);
+        peak(&mut engine, 4410);
+        assert_eq!(engine.active_voices(), 0);
+    }
+
+    #[test]
+    fn messages_may_split_across_feeds() {
+        let mut engine = LiveEngine::new(44100.0);
+        engine.feed(&[0x90]);
+        engine.feed(&[64]);
+        assert_eq!(engine.active_voices(), 0);
+        engine.feed(&[90]);
+        assert_eq!(engine.active_voices(), 1);
+    }
+
+    #[test]
+    fn pitch_bend_and_all_notes_off() {
+        let mut engine = LiveEngine::new(44100.0);
+        engine.set_instrument(short_release());
+        engine.note_on(69, 100);
+        engine.pitch_bend(1.0);
+        let f = engine.voices[0].voice.oscillator.frequency;
+        assert!((f - 440.0 * 2f64.powf(2.0 / 12.0)).abs() < 1e-9);
+
+        engine.control_change(CC_ALL_NOTES_OFF, 0);
+        peak(&mut engine, 4410);
+        assert_eq!(engine.active_voices(), 0);
+    }
+
+    #[test]
+    fn instrument_from_const_or_first_note() {
+        let program = crate::parse(
+            "const lead = Oscillator({type: 'square'});\n\
+             riff();\n\
+             track riff() {\n    track.instrument = Oscillator({type: 'sine'});\n    C4 /4\n}\n",
+        )
+        .unwrap();
+        let first = instrument_from_program(&program, None).unwrap();
+        assert_eq!(first.waveform, "sine");
+        let lead = instrument_from_program(&program, Some("lead")).unwrap();
+        assert_eq!(lead.waveform, "square");
+        assert!(instrument_from_program(&program, Some("pad")).is_err());
+    }
+
+    #[test]
+    fn steals_oldest_voice_when_full() {
+        let mut engine = LiveEngine::new(44100.0);
+        for key in 0..70 {
+            engine.note_on(key, 100);
+        }
+        assert_eq!(engine.active_voices(), 64);
+        assert_eq!(engine.voices[0].key, 6);
+    }
+}
@@ -0,0 +1,287 @@
+//! Loudness — ITU-R BS.1770 / EBU R128 metering.
+//!
+//! `LoudnessMeter` measures integrated loudness in LUFS: the signal is
+//! K-weighted, cut into 400 ms blocks overlapping by 75%, and gated at
+//! −70 LUFS and then 10 LU below the ungated level. `TruePeak` estimates
+//! the peak between samples by 4× oversampling.
+
+use std::collections::VecDeque;
+
+/// Taps per oversampling phase of the true-peak interpolator.
+const TAPS: usize = 12;
+/// Oversampling factor for true-peak detection.
+const PHASES: usize = 4;
+
+/// Integrated loudness and true peak of a signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS (−∞ for silence).
+    pub integrated: f64,
+    /// Highest true peak in dBTP (−∞ for silence).
+    pub true_peak: f64,
+}
+
+// ── True peak ───────────────────────────────────────────────
+
+/// Streaming inter-sample peak detector. Each pushed sample yields the
+/// peak of the interpolated signal `latency()` samples earlier.
+#[derive(Debug, Clone)]
+pub struct TruePeak {
+    coeffs: [[f64; TAPS]; PHASES],
+    history: [f64; TAPS],
+    pos: usize,
+}
+
+impl Default for TruePeak {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TruePeak {
+    pub fn new() -> Self {
+        // Hann-windowed sinc, one phase per fractional offset. Phase 0
+        // passes the original samples through unchanged.
+        let center = (TAPS / 2 - 1) as f64;
+        let mut coeffs = [[0.0; TAPS]; PHASES];
+        for (p, phase) in coeffs.iter_mut().enumerate() {
+            for (j, c) in phase.iter_mut().enumerate() {
+                let u = j as f64 - center - p as f64 / PHASES as f64;
+                let sinc = if u == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * u).sin() / (std::f64::consts::PI * u)
+                };
+                let window = 0.5 * (1.0 + (std::f64::consts::PI * u / (TAPS / 2) as f64).cos());
+                *c = sinc * window;
+            }
+        }
+        TruePeak {
+            coeffs,
+            history: [0.0; TAPS],
+            pos: 0,
+        }
+    }
+
+    /// Delay in samples between a pushed sample and the peak reported for it.
+    pub fn latency(&self) -> usize {
+        TAPS / 2 - 1
+    }
+
+    /// Push one sample and return the absolute peak of the interpolated
+    /// signal over the preceding sample interval.
+    pub fn push(&mut self, sample: f64) -> f64 {
+        self.pos = (self.pos + 1) % TAPS;
+        self.history[self.pos] = sample;
+        let mut peak = 0.0_f64;
+        for phase in &self.coeffs {
+            let mut y = 0.0;
+            for (j, c) in phase.iter().enumerate() {
+                y += c * self.history[(self.pos + TAPS - j) % TAPS];
+            }
+            peak = peak.max(y.abs());
+        }
+        peak
+    }
+
+    pub fn reset(&mut self) {
+        self.history = [0.0; TAPS];
+    }
+}
+
+// ── Loudness meter ──────────────────────────────────────────
+
+/// Direct form I biquad.
+#[derive(Debug, Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Integrated loudness meter for a mono mix written to `channels`
+/// identical channels.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    shelf: Biquad,
+    highpass: Biquad,
+    channels: u16,
+    /// Samples per 100 ms hop.
+    hop: usize,
+    /// Energy of the hop being filled, and its sample count.
+    hop_energy: f64,
+    hop_len: usize,
+    /// Energies of the last four complete hops.
+    recent: VecDeque<f64>,
+    /// Mean square of each complete 400 ms block.
+    blocks: Vec<f64>,
+    peak: TruePeak,
+    max_peak: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f64, channels: u16) -> Self {
+        // K-weighting: a high shelf for the head's acoustic effect, then
+        // the RLB high-pass, with BS.1770 coefficients re-derived for the
+        // sample rate.
+        let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        };
+
+        let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        };
+
+        LoudnessMeter {
+            shelf,
+            highpass,
+            channels,
+            hop: ((sample_rate * 0.1).round() as usize).max(1),
+            hop_energy: 0.0,
+            hop_len: 0,
+            recent: VecDeque::with_capacity(4),
+            blocks: Vec::new(),
+            peak: TruePeak::new(),
+            max_peak: 0.0,
+        }
+    }
+
+    /// Measure a run of samples.
+    pub fn push(&mut self, samples: &[f64]) {
+        for &s in samples {
+            self.max_peak = self.max_peak.max(self.peak.push(s));
+            let k = self.highpass.process(self.shelf.process(s));
+            self.hop_energy += k * k;
+            self.hop_len += 1;
+            if self.hop_len == self.hop {
+                if self.recent.len() == 4 {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back(self.hop_energy);
+                if self.recent.len() == 4 {
+                    let sum: f64 = self.recent.iter().sum();
+                    self.blocks.push(sum / (4 * self.hop) as f64);
+                }
+                self.hop_energy = 0.0;
+                self.hop_len = 0;
+            }
+        }
+    }
+
+    /// Gated integrated loudness in LUFS; −∞ when every block is below
+    /// the absolute gate (including signals shorter than 400 ms).
+    pub fn integrated(&self) -> f64 {
+        let weight = self.channels as f64;
+        let lufs = |mean_square: f64| -0.691 + 10.0 * (weight * mean_square).log10();
+
+        let absolute: Vec<f64> = self.blocks.iter().copied().filter(|&b| lufs(b) > -70.0).collect();
+        let Some(ungated) = mean(absolute.iter().copied()) else {
+            return f64::NEG_INFINITY;
+        };
+        let threshold = lufs(ungated) - 10.0;
+        match mean(absolute.into_iter().filter(|&b| lufs(b) > threshold)) {
+            Some(gated) => lufs(gated),
+            None => f64::NEG_INFINITY,
+        }
+    }
+
+    /// Highest true peak so far in dBTP.
+    pub fn true_peak(&self) -> f64 {
+        20.0 * self.max_peak.log10()
+    }
+
+    pub fn loudness(&self) -> Loudness {
+        Loudness {
+            integrated: self.integrated(),
+            true_peak: self.true_peak(),
+        }
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, n) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+    if n == 0 { None } else { Some(sum / n as f64) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, amplitude: f64, sample_rate: f64, seconds: f64) -> Vec<f64> {
+        (0..(sample_rate * seconds) as usize)
+            .map(|i| amplitude * (std::f64::consts::TAU * freq * i as f64 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn stereo_sine_reads_its_level() {
+        // EBU Tech 3341: a 1 kHz stereo sine at −23 dBFS reads −23 LUFS.
+        for rate in [44100.0, 48000.0] {
+            let mut meter = LoudnessMeter::new(rate, 2);
+            meter.push(&sine(1000.0, 10f64.powf(-23.0 / 20.0), rate, 5.0));
+            assert!((meter.integrated() + 23.0).abs() < 0.1, "{}", meter.integrated());
+        }
+
+        let mut mono = LoudnessMeter::new(48000.0, 1);
+        mono.push(&sine(1000.0, 10f64.powf(-23.0 / 20.0), 48000.0, 5.0));
+        assert!((mono.integrated() + 26.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn gating_ignores_silence() {
+        let rate = 48000.0;
+        let mut meter = LoudnessMeter::new(rate, 2);
+        meter.push(&sine(1000.0, 10f64.powf(-20.0 / 20.0), rate, 3.0));
+        meter.push(&vec![0.0; (rate * 10.0) as usize]);
+        // Only the blocks straddling the fade-out pull the reading down.
+        assert!((meter.integrated() + 20.0).abs() < 0.3);
+
+        let silent = LoudnessMeter::new(rate, 2);
+        assert_eq!(silent.integrated(), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn true_peak_finds_inter_sample_peaks() {
+        // A quarter-rate sine sampled at ±45° peaks between samples.
+        let rate = 48000.0;
+        let samples: Vec<f64> = (0..4800)
+            .map(|i| (std::f64::consts::TAU * 0.25 * i as f64 + std::f64::consts::FRAC_PI_4).sin())
+            .collect();
+        let sample_peak = samples.iter().fold(0.0_f64, |m, s| m.max(s.abs()));
+        assert!((sample_peak - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+
+        let mut meter = LoudnessMeter::new(rate, 1);
+        meter.push(&samples);
+        assert!(meter.true_peak().abs() < 0.3, "{}", meter.true_peak());
+    }
+}
@@ -4,13 +4,22 @@
 #[derive(Debug, Clone)]
 pub struct Mixer {
     pub master_gain: f64,
+    /// Soft-clip the output. Turned off when a limiter follows.
+    pub soft_clip: bool,
     buffer: Vec<f64>,
 }
 
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Mixer {
     pub fn new() -> Self {
         Mixer {
             master_gain: 0.8,
+            soft_clip: true,
             buffer: Vec::new(),
         }
     }
@@ -32,7 +41,8 @@ impl Mixer {
     pub fn output(&self) -> Vec<f64> {
         self.buffer
             .iter()
-            .map(|&s| soft_clip(s * self.master_gain))
+            .map(|&s| s * self.master_gain)
+            .map(|s| if self.soft_clip { soft_clip(s) } else { s })
             .collect()
     }
 
@@ -79,6 +89,15 @@ mod tests {
         assert!((out[2] - 0.0).abs() < 1e-10);
     }
 
+    #[test]
+    fn soft_clip_can_be_bypassed() {
+        let mut m = Mixer::new();
+        m.soft_clip = false;
+        m.clear(1);
+        m.add(0, 2.0);
+        assert!((m.output()[0] - 1.6).abs() < 1e-10);
+    }
+
     #[test]
     fn soft_clip_prevents_overflow() {
         let mut m = Mixer::new();
@@ -7,10 +7,16 @@
 pub mod engine;
 pub mod envelope;
 pub mod filter;
+pub mod flac;
+pub mod limiter;
+pub mod live;
+pub mod loudness;
 pub mod mixer;
 pub mod oscillator;
 pub mod renderer;
 pub mod sampler;
+pub mod session;
 pub mod composite;
+pub mod encode;
 pub mod tuner;
 pub mod voice;
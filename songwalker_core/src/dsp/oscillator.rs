@@ -89,12 +89,11 @@ impl Oscillator {
         // band-limited triangle via the phase:
         let _ = sq;
         // Direct computation: piecewise linear, -1→+1 in [0, 0.5], +1→-1 in [0.5, 1]
-        let value = if self.phase < 0.5 {
+        if self.phase < 0.5 {
             4.0 * self.phase - 1.0
         } else {
             3.0 - 4.0 * self.phase
-        };
-        value
+        }
     }
 
     /// Reset oscillator phase.
@@ -140,7 +139,7 @@ mod tests {
         osc.frequency = 440.0;
         for _ in 0..44100 {
             let s = osc.next_sample();
-            assert!(s >= -1.0 && s <= 1.0, "Sine out of range: {s}");
+            assert!((-1.0..=1.0).contains(&s), "Sine out of range: {s}");
         }
     }
 
@@ -150,7 +149,7 @@ mod tests {
         osc.frequency = 440.0;
         for _ in 0..44100 {
             let s = osc.next_sample();
-            assert!(s >= -1.5 && s <= 1.5, "Saw out of range: {s}");
+            assert!((-1.5..=1.5).contains(&s), "Saw out of range: {s}");
         }
     }
 
@@ -160,7 +159,7 @@ mod tests {
         osc.frequency = 440.0;
         for _ in 0..44100 {
             let s = osc.next_sample();
-            assert!(s >= -1.5 && s <= 1.5, "Square out of range: {s}");
+            assert!((-1.5..=1.5).contains(&s), "Square out of range: {s}");
         }
     }
 
@@ -170,7 +169,7 @@ mod tests {
         osc.frequency = 440.0;
         for _ in 0..44100 {
             let s = osc.next_sample();
-            assert!(s >= -1.0 && s <= 1.0, "Triangle out of range: {s}");
+            assert!((-1.0..=1.0).contains(&s), "Triangle out of range: {s}");
         }
     }
 
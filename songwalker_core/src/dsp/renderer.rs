@@ -1,49 +1,409 @@
-//! WAV renderer — renders an EventList to a WAV byte buffer.
+//! Renderer — streams an EventList as audio, block by block.
+//!
+//! `Renderer` holds the scheduled notes and the live voices, so a song of
+//! any length renders in constant memory: an AudioWorklet pulls 128 frames
+//! per callback and the CLI streams straight to disk. Voices are started
+//! and released on an internal 128-sample grid, which keeps the output
+//! identical whatever buffer sizes the caller asks for. An optional loop
+//! range wraps playback back to its start, and optional buses keep a
+//! separate mix per track or instrument for stem export.
 
-use crate::compiler::EventList;
+use crate::compiler::{EventList, InstrumentConfig};
+use crate::tempo::TempoMap;
+
+use super::encode::{EncodeSpec, FileFormat, encode};
 use super::engine::AudioEngine;
+use super::loudness::{Loudness, LoudnessMeter};
+use super::mixer::Mixer;
+use super::voice::Voice;
+
+/// Internal render block size in samples.
+const BLOCK_SIZE: usize = 128;
+
+/// A note placed on the sample timeline.
+pub(crate) struct ScheduledNote {
+    /// Sample offset when the note starts.
+    pub start_sample: usize,
+    /// Sample offset when the note should be released (gate off).
+    pub release_sample: usize,
+    pub frequency: f64,
+    pub velocity: f64,
+    /// Instrument configuration for this note.
+    pub instrument: InstrumentConfig,
+    pub source_start: usize,
+    pub source_end: usize,
+    /// Index into the renderer's buses (unused without buses).
+    pub bus: usize,
+}
+
+/// A playing voice, the index of the note that started it, and its bus.
+/// Voices left over from a previous song by `reload` have no note.
+struct ActiveVoice {
+    voice: Voice,
+    note: Option<usize>,
+    bus: usize,
+}
+
+/// Stateful block renderer for one compiled song. Create one with
+/// `AudioEngine::renderer`.
+pub struct Renderer {
+    sample_rate: f64,
+    tempo: TempoMap,
+    /// Notes sorted by start sample.
+    notes: Vec<ScheduledNote>,
+    total_samples: usize,
+    max_voices: usize,
+    voices: Vec<ActiveVoice>,
+    /// Loop range as (start, end) sample offsets.
+    loop_range: Option<(usize, usize)>,
+    mixer: Mixer,
+    /// Index of the next note to start.
+    next_note: usize,
+    /// Next sample to output.
+    position: usize,
+    /// The current mixed block and the read offset into it.
+    block: Vec<f64>,
+    block_pos: usize,
+    /// Bus names, and the current block of each bus.
+    buses: Vec<String>,
+    bus_blocks: Vec<Vec<f64>>,
+}
+
+impl Renderer {
+    pub(crate) fn new(
+        sample_rate: f64,
+        tempo: TempoMap,
+        notes: Vec<ScheduledNote>,
+        total_samples: usize,
+        max_voices: usize,
+        buses: Vec<String>,
+    ) -> Self {
+        Renderer {
+            sample_rate,
+            tempo,
+            notes,
+            total_samples,
+            max_voices,
+            voices: Vec::new(),
+            loop_range: None,
+            mixer: Mixer::new(),
+            next_note: 0,
+            position: 0,
+            block: Vec::new(),
+            block_pos: 0,
+            bus_blocks: vec![Vec::new(); buses.len()],
+            buses,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Length of the song in samples.
+    pub fn total_samples(&self) -> usize {
+        self.total_samples
+    }
+
+    /// The next sample `process` will output.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// True once the end of the song is reached. A looping renderer never
+    /// finishes.
+    pub fn is_finished(&self) -> bool {
+        self.loop_range.is_none() && self.position >= self.total_samples
+    }
+
+    /// Convert a beat position to a sample offset.
+    pub fn beat_to_sample(&self, beat: f64) -> usize {
+        (self.tempo.seconds_at(beat.max(0.0)) * self.sample_rate) as usize
+    }
+
+    /// Convert a sample offset to a beat position.
+    pub fn sample_to_beat(&self, sample: usize) -> f64 {
+        self.tempo.beat_at(sample as f64 / self.sample_rate)
+    }
+
+    /// Loop between two sample offsets, or stop looping with `None`. The
+    /// end is clamped to the song length; empty ranges disable looping.
+    pub fn set_loop(&mut self, range: Option<(usize, usize)>) {
+        self.loop_range = range
+            .map(|(start, end)| (start, end.min(self.total_samples)))
+            .filter(|(start, end)| start < end);
+    }
+
+    pub fn loop_range(&self) -> Option<(usize, usize)> {
+        self.loop_range
+    }
+
+    /// Turn the master soft clipper on or off (it's on by default). Turn it
+    /// off when the output goes through a limiter.
+    pub fn set_soft_clip(&mut self, on: bool) {
+        self.mixer.soft_clip = on;
+    }
+
+    /// Number of voices still producing sound, including releasing ones.
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Source spans (`source_start`, `source_end`) of the notes whose gate
+    /// is open at the current position, for editor highlighting.
+    pub fn active_notes(&self) -> Vec<(usize, usize)> {
+        self.voices
+            .iter()
+            .filter_map(|v| v.note.map(|i| &self.notes[i]))
+            .filter(|n| n.start_sample <= self.position && self.position < n.release_sample)
+            .map(|n| (n.source_start, n.source_end))
+            .collect()
+    }
+
+    /// Bus names, in the order `process_stems` fills them. Empty unless
+    /// the renderer came from `AudioEngine::stem_renderer`.
+    pub fn buses(&self) -> &[String] {
+        &self.buses
+    }
+
+    /// Render the next `out.len()` mono samples. Past the end of the song
+    /// the buffer is filled with silence. Returns the number of song
+    /// samples written.
+    pub fn process(&mut self, out: &mut [f32]) -> usize {
+        self.render_frames(out.len(), |r, i, at| {
+            out[i] = at.map_or(0.0, |p| r.block[p]) as f32;
+        })
+    }
+
+    /// Like `process`, at full precision.
+    pub fn process_f64(&mut self, out: &mut [f64]) -> usize {
+        self.render_frames(out.len(), |r, i, at| out[i] = at.map_or(0.0, |p| r.block[p]))
+    }
+
+    /// Like `process_f64`, also filling one buffer per bus with the same
+    /// frames. Each stem buffer is resized to `master.len()`. Stems get the
+    /// master gain but not the soft clipper, so they sum to the master
+    /// wherever it isn't clipping.
+    pub fn process_stems(&mut self, master: &mut [f64], stems: &mut [Vec<f64>]) -> usize {
+        for stem in stems.iter_mut() {
+            stem.resize(master.len(), 0.0);
+        }
+        self.render_frames(master.len(), |r, i, at| {
+            master[i] = at.map_or(0.0, |p| r.block[p]);
+            for (stem, bus) in stems.iter_mut().zip(&r.bus_blocks) {
+                stem[i] = at.map_or(0.0, |p| bus[p]);
+            }
+        })
+    }
+
+    /// End the song early at a sample offset (it can only be shortened).
+    pub fn set_end(&mut self, sample: usize) {
+        self.total_samples = self.total_samples.min(sample.max(self.position));
+    }
+
+    /// Jump to a sample offset. Notes already sounding at that point are
+    /// restarted there, so seeking into a held chord plays the chord.
+    pub fn seek(&mut self, sample: usize) {
+        let sample = sample.min(self.total_samples);
+        self.position = sample;
+        self.block.clear();
+        self.block_pos = 0;
+        self.voices.clear();
+        self.next_note = self.notes.partition_point(|n| n.start_sample < sample);
+        for (i, note) in self.notes[..self.next_note].iter().enumerate() {
+            if note.release_sample > sample && self.voices.len() < self.max_voices {
+                self.voices.push(start_voice(self.sample_rate, &self.notes, i));
+            }
+        }
+    }
+
+    /// Continue playback with a recompiled song. The playhead keeps its
+    /// beat position. Sounding notes that are unchanged in the new song
+    /// carry on uninterrupted; changed or removed ones release naturally,
+    /// and new notes held at the playhead start at once. The loop range
+    /// is cleared.
+    pub fn reload(&mut self, next: Renderer) {
+        let beat = self.sample_to_beat(self.position);
+        let old_notes = std::mem::take(&mut self.notes);
+        let old_voices = std::mem::take(&mut self.voices);
+        *self = next;
+
+        let position = self.beat_to_sample(beat).min(self.total_samples);
+        self.position = position;
+        self.next_note = self.notes.partition_point(|n| n.start_sample < position);
+        let sounding = |n: &ScheduledNote| n.release_sample > position;
+
+        let mut claimed = vec![false; self.next_note];
+        for mut active in old_voices {
+            let matching = active.note.and_then(|old| {
+                let old = &old_notes[old];
+                (0..self.next_note).find(|&i| {
+                    let new = &self.notes[i];
+                    !claimed[i] && sounding(new) && same_note(old, new)
+                })
+            });
+            match matching {
+                Some(i) => {
+                    claimed[i] = true;
+                    active.note = Some(i);
+                    active.bus = self.notes[i].bus;
+                }
+                None if active.note.is_some() => {
+                    active.voice.note_off();
+                    active.voice.release_sample = usize::MAX;
+                    active.note = None;
+                }
+                None => {}
+            }
+            self.voices.push(active);
+        }
+        for (i, claimed) in claimed.into_iter().enumerate() {
+            if !claimed && sounding(&self.notes[i]) && self.voices.len() < self.max_voices {
+                self.voices.push(start_voice(self.sample_rate, &self.notes, i));
+            }
+        }
+    }
+
+    /// Advance `len` frames, handing `write` each output index and the
+    /// offset of its sample in the current block (`None` past the end).
+    fn render_frames(&mut self, len: usize, mut write: impl FnMut(&Self, usize, Option<usize>)) -> usize {
+        let mut written = 0;
+        for i in 0..len {
+            if let Some((start, end)) = self.loop_range
+                && self.position >= end
+            {
+                self.seek(start);
+            }
+            if self.position >= self.total_samples {
+                write(self, i, None);
+                continue;
+            }
+            if self.block_pos == self.block.len() {
+                self.render_block();
+            }
+            write(self, i, Some(self.block_pos));
+            self.block_pos += 1;
+            self.position += 1;
+            written += 1;
+        }
+        written
+    }
+
+    /// Mix the next block starting at `position`.
+    fn render_block(&mut self) {
+        let block_start = self.position;
+        let mut block_end = (block_start + BLOCK_SIZE).min(self.total_samples);
+        if let Some((_, end)) = self.loop_range
+            && block_start < end
+        {
+            block_end = block_end.min(end);
+        }
+        let this_block = block_end - block_start;
+
+        // Activate new notes that start in this block
+        while self.next_note < self.notes.len()
+            && self.notes[self.next_note].start_sample < block_end
+        {
+            if self.voices.len() < self.max_voices {
+                let voice = start_voice(self.sample_rate, &self.notes, self.next_note);
+                self.voices.push(voice);
+            }
+            self.next_note += 1;
+        }
+
+        // Check for note releases — each voice carries its own release_sample
+        for ActiveVoice { voice, .. } in self.voices.iter_mut() {
+            if voice.release_sample >= block_start && voice.release_sample < block_end {
+                voice.note_off();
+            }
+        }
+
+        // Render voices into mixer, and into their bus if there are buses
+        self.mixer.clear(this_block);
+        for bus in self.bus_blocks.iter_mut() {
+            bus.clear();
+            bus.resize(this_block, 0.0);
+        }
+        let gain = self.mixer.master_gain;
+        for ActiveVoice { voice, bus, .. } in self.voices.iter_mut() {
+            if !voice.is_finished() {
+                let mut bus = self.bus_blocks.get_mut(*bus);
+                for i in 0..this_block {
+                    let sample = voice.next_sample();
+                    self.mixer.add(i, sample);
+                    if let Some(bus) = bus.as_mut() {
+                        bus[i] += sample * gain;
+                    }
+                }
+            }
+        }
+        self.block = self.mixer.output();
+        self.block_pos = 0;
+
+        // Remove finished voices
+        self.voices.retain(|v| !v.voice.is_finished());
+    }
+}
+
+/// Whether two scheduled notes would sound the same.
+fn same_note(a: &ScheduledNote, b: &ScheduledNote) -> bool {
+    a.start_sample == b.start_sample
+        && a.release_sample == b.release_sample
+        && a.frequency == b.frequency
+        && a.velocity == b.velocity
+        && a.instrument == b.instrument
+}
+
+fn start_voice(sample_rate: f64, notes: &[ScheduledNote], index: usize) -> ActiveVoice {
+    let note = &notes[index];
+    let mut voice = Voice::with_config(sample_rate, &note.instrument);
+    voice.release_sample = note.release_sample;
+    voice.note_on(note.frequency, note.velocity);
+    ActiveVoice {
+        voice,
+        note: Some(index),
+        bus: note.bus,
+    }
+}
+
+// ── Offline helpers ─────────────────────────────────────────
 
 /// Render an EventList to a WAV file as bytes (16-bit stereo PCM).
 pub fn render_wav(event_list: &EventList, sample_rate: u32) -> Vec<u8> {
     let engine = AudioEngine::new(sample_rate as f64);
-    let pcm = engine.render_pcm_i16(event_list);
-
-    encode_wav(&pcm, sample_rate, 2)
+    let mut renderer = engine.renderer(event_list);
+    let mut buf = Vec::with_capacity(44 + renderer.total_samples() * 4);
+    encode(&mut renderer, &EncodeSpec::new(sample_rate), FileFormat::Wav, &mut buf)
+        .expect("writing to a Vec cannot fail");
+    buf
 }
 
-/// Encode interleaved i16 PCM samples to a WAV byte buffer.
-fn encode_wav(samples: &[i16], sample_rate: u32, channels: u16) -> Vec<u8> {
-    let bits_per_sample: u16 = 16;
-    let byte_rate = sample_rate * channels as u32 * (bits_per_sample as u32 / 8);
-    let block_align = channels * (bits_per_sample / 8);
-    let data_size = (samples.len() * 2) as u32;
-    let file_size = 36 + data_size;
-
-    let mut buf = Vec::with_capacity(44 + data_size as usize);
-
-    // RIFF header
-    buf.extend_from_slice(b"RIFF");
-    buf.extend_from_slice(&file_size.to_le_bytes());
-    buf.extend_from_slice(b"WAVE");
-
-    // fmt chunk
-    buf.extend_from_slice(b"fmt ");
-    buf.extend_from_slice(&16u32.to_le_bytes()); // chunk size
-    buf.extend_from_slice(&1u16.to_le_bytes()); // PCM format
-    buf.extend_from_slice(&channels.to_le_bytes());
-    buf.extend_from_slice(&sample_rate.to_le_bytes());
-    buf.extend_from_slice(&byte_rate.to_le_bytes());
-    buf.extend_from_slice(&block_align.to_le_bytes());
-    buf.extend_from_slice(&bits_per_sample.to_le_bytes());
-
-    // data chunk
-    buf.extend_from_slice(b"data");
-    buf.extend_from_slice(&data_size.to_le_bytes());
-    for &sample in samples {
-        buf.extend_from_slice(&sample.to_le_bytes());
+/// Render from the current position to the end and return the highest
+/// absolute sample value. Consumes the renderer's playback.
+pub fn scan_peak(renderer: &mut Renderer) -> f64 {
+    let mut block = [0.0_f64; 4096];
+    let mut peak = 0.0_f64;
+    loop {
+        let n = renderer.process_f64(&mut block);
+        if n == 0 {
+            return peak;
+        }
+        peak = block[..n].iter().fold(peak, |m, s| m.max(s.abs()));
     }
+}
 
-    buf
+/// Render from the current position to the end and measure its loudness
+/// as written to `channels` channels. Consumes the renderer's playback.
+pub fn scan_loudness(renderer: &mut Renderer, channels: u16) -> Loudness {
+    let mut meter = LoudnessMeter::new(renderer.sample_rate(), channels);
+    let mut block = [0.0_f64; 4096];
+    loop {
+        let n = renderer.process_f64(&mut block);
+        if n == 0 {
+            return meter.loudness();
+        }
+        meter.push(&block[..n]);
+    }
 }
 
 #[cfg(test)]
@@ -58,9 +418,12 @@ mod tests {
                 time: 0.0,
                 kind: EventKind::Note {
                     pitch: "C4".to_string(),
+                    midi: Some(60),
                     velocity: 100.0,
                     gate: 1.0,
                     instrument: InstrumentConfig::default(),
+                    track: String::new(),
+                    top_track: String::new(),
                     source_start: 0,
                     source_end: 0,
                 },
@@ -138,4 +501,67 @@ track riff() {
         }
         assert!(has_nonzero, "Rendered WAV should contain non-silent audio");
     }
+
+    fn riff() -> EventList {
+        let source = "riff();\ntrack riff() {\n    C4@/8 /4\n    [E4, G4]@1 /4\n    C5 /2\n}\n";
+        crate::compiler::compile(&crate::parse(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn block_size_does_not_change_output() {
+        let engine = AudioEngine::new(22050.0);
+        let song = riff();
+        let whole = engine.render(&song);
+
+        let mut renderer = engine.renderer(&song);
+        let mut streamed = Vec::new();
+        let mut buf = [0.0_f64; 77];
+        while !renderer.is_finished() {
+            let n = renderer.process_f64(&mut buf);
+            streamed.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(streamed, whole);
+        assert_eq!(renderer.process_f64(&mut buf), 0);
+        assert!(buf.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn seek_restarts_held_notes() {
+        let engine = AudioEngine::new(22050.0);
+        let mut renderer = engine.renderer(&riff());
+        let total = renderer.total_samples();
+
+        // 0.3 beats in: C4 has finished its gate but the chord is held.
+        renderer.seek((0.3 * 0.5 * 22050.0) as usize);
+        assert_eq!(renderer.voices.len(), 2);
+        let mut buf = [0.0_f32; 512];
+        renderer.process(&mut buf);
+        assert!(buf.iter().any(|s| s.abs() > 0.01));
+
+        renderer.seek(total + 100);
+        assert!(renderer.is_finished());
+        assert_eq!(renderer.position(), total);
+        assert_eq!(renderer.process(&mut buf), 0);
+    }
+
+    #[test]
+    fn stems_sum_to_the_master() {
+        use crate::dsp::engine::StemGrouping;
+
+        let source = "bass();\nlead() 0;\ntrack bass() {\n    C2 1\n}\ntrack lead() {\n    track.instrument = 'square';\n    [E4, G4] 1\n}\n";
+        let song = crate::compiler::compile(&crate::parse(source).unwrap()).unwrap();
+        let engine = AudioEngine::new(8000.0);
+        let mut renderer = engine.stem_renderer(&song, StemGrouping::Track);
+        assert_eq!(renderer.buses(), ["bass", "lead"]);
+        assert_eq!(engine.stem_renderer(&song, StemGrouping::Instrument).buses(), ["triangle", "square"]);
+
+        let mut master = vec![0.0_f64; renderer.total_samples()];
+        let mut stems = vec![Vec::new(); 2];
+        renderer.process_stems(&mut master, &mut stems);
+        assert_eq!(master, engine.render(&song));
+        assert!(stems.iter().all(|stem| stem.iter().any(|&s| s != 0.0)));
+        for (i, &m) in master.iter().enumerate() {
+            assert!((m - (stems[0][i] + stems[1][i]).tanh()).abs() < 1e-12);
+        }
+    }
 }
@@ -0,0 +1,202 @@
+//! Playback session — transport state around a `Renderer`.
+//!
+//! A session is what the editor's player talks to: it plays, pauses,
+//! seeks and loops in beats, reports which notes are sounding, and can
+//! swap in a recompiled song without moving the playhead.
+
+use crate::compiler::EventList;
+
+use super::engine::AudioEngine;
+use super::renderer::Renderer;
+
+pub struct Session {
+    engine: AudioEngine,
+    renderer: Renderer,
+    playing: bool,
+    /// Loop range in beats, kept so it survives a song swap.
+    loop_beats: Option<(f64, f64)>,
+}
+
+impl Session {
+    /// Create a paused session at the start of the song.
+    pub fn new(event_list: &EventList, sample_rate: f64) -> Self {
+        let engine = AudioEngine::new(sample_rate);
+        let renderer = engine.renderer(event_list);
+        Session {
+            engine,
+            renderer,
+            playing: false,
+            loop_beats: None,
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Playhead position in beats.
+    pub fn position(&self) -> f64 {
+        self.renderer.sample_to_beat(self.renderer.position())
+    }
+
+    /// Song length in beats, including any release tail.
+    pub fn duration(&self) -> f64 {
+        self.renderer.sample_to_beat(self.renderer.total_samples())
+    }
+
+    /// Move the playhead to a beat.
+    pub fn seek(&mut self, beat: f64) {
+        let sample = self.renderer.beat_to_sample(beat);
+        self.renderer.seek(sample);
+    }
+
+    /// Loop between two beats.
+    pub fn set_loop(&mut self, start_beat: f64, end_beat: f64) {
+        self.loop_beats = Some((start_beat, end_beat));
+        self.apply_loop();
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_beats = None;
+        self.renderer.set_loop(None);
+    }
+
+    /// Source spans of the notes sounding at the playhead.
+    pub fn active_notes(&self) -> Vec<(usize, usize)> {
+        self.renderer.active_notes()
+    }
+
+    /// Hot-swap a recompiled song, keeping the playhead beat, loop and
+    /// play state. See `Renderer::reload` for what happens to the notes
+    /// sounding at the playhead.
+    pub fn load(&mut self, event_list: &EventList) {
+        self.renderer.reload(self.engine.renderer(event_list));
+        self.apply_loop();
+    }
+
+    /// Render the next block of mono samples. Outputs silence while
+    /// paused, and pauses itself at the end of the song.
+    pub fn process(&mut self, out: &mut [f32]) {
+        if !self.playing {
+            out.fill(0.0);
+            return;
+        }
+        self.renderer.process(out);
+        if self.renderer.is_finished() {
+            self.playing = false;
+        }
+    }
+
+    fn apply_loop(&mut self) {
+        let range = self.loop_beats.map(|(start, end)| {
+            (self.renderer.beat_to_sample(start), self.renderer.beat_to_sample(end))
+        });
+        self.renderer.set_loop(range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 8000.0;
+
+    fn song(source: &str) -> EventList {
+        crate::compiler::compile(&crate::parse(source).unwrap()).unwrap()
+    }
+
+    fn riff() -> EventList {
+        // C4 at beat 0, D4 at 1, E4 at 2 (`E4@1 1` is source 49..55), F4 at 3.
+        song("riff();\ntrack riff() {\n    C4@1 1\n    D4@1 1\n    E4@1 1\n    F4@1 1\n}\n")
+    }
+
+    #[test]
+    fn paused_session_is_silent() {
+        let mut session = Session::new(&riff(), RATE);
+        let mut buf = [1.0_f32; 256];
+        session.process(&mut buf);
+        assert!(buf.iter().all(|&s| s == 0.0));
+        assert_eq!(session.position(), 0.0);
+
+        session.play();
+        session.process(&mut buf);
+        assert!(buf.iter().any(|&s| s != 0.0));
+        assert!(session.position() > 0.0);
+    }
+
+    #[test]
+    fn seek_reports_active_note_spans() {
+        let mut session = Session::new(&riff(), RATE);
+        session.seek(2.5);
+        assert!((session.position() - 2.5).abs() < 1e-3);
+        assert_eq!(session.active_notes(), [(49, 55)]);
+    }
+
+    #[test]
+    fn loop_wraps_playhead() {
+        let mut session = Session::new(&riff(), RATE);
+        session.set_loop(1.0, 2.0);
+        session.seek(1.0);
+        session.play();
+        // Two and a half beats of audio at 120 BPM.
+        let mut buf = vec![0.0_f32; (2.5 * 0.5 * RATE) as usize];
+        session.process(&mut buf);
+        assert!(session.is_playing());
+        assert!((session.position() - 1.5).abs() < 1e-3);
+
+        session.clear_loop();
+        let mut rest = vec![0.0_f32; (session.duration() * 0.5 * RATE) as usize];
+        session.process(&mut rest);
+        assert!(!session.is_playing());
+    }
+
+    #[test]
+    fn load_keeps_playhead_and_loop() {
+        let mut session = Session::new(&riff(), RATE);
+        session.set_loop(0.0, 2.0);
+        session.seek(1.5);
+        session.play();
+
+        // Same notes at a faster tempo: beats stay put, samples move.
+        let faster = song(
+            "track.beatsPerMinute = 240;\nriff();\ntrack riff() {\n    C4@1 1\n    D4@1 1\n    E4@1 1\n    F4@1 1\n}\n",
+        );
+        session.load(&faster);
+        assert!((session.position() - 1.5).abs() < 1e-3);
+        assert!(session.is_playing());
+        assert_eq!(session.active_notes().len(), 1);
+        assert_eq!(session.renderer.loop_range(), Some((0, (RATE / 2.0) as usize)));
+    }
+
+    #[test]
+    fn load_keeps_unchanged_voices_and_releases_edited_ones() {
+        let mut session = Session::new(&riff(), RATE);
+        session.seek(1.5);
+        session.play();
+        let mut buf = [0.0_f32; 64];
+        session.process(&mut buf);
+        let before = session.active_notes();
+
+        // Editing a later note leaves the sounding D4 untouched.
+        session.load(&song(
+            "riff();\ntrack riff() {\n    C4@1 1\n    D4@1 1\n    E4@1 1\n    G4@1 1\n}\n",
+        ));
+        assert_eq!(session.renderer.active_voices(), 1);
+        assert_eq!(session.active_notes(), before);
+
+        // Changing the sounding note releases the old voice and starts the new one.
+        session.load(&song(
+            "riff();\ntrack riff() {\n    C4@1 1\n    Eb4@1 1\n    E4@1 1\n    G4@1 1\n}\n",
+        ));
+        assert_eq!(session.renderer.active_voices(), 2);
+        assert_eq!(session.active_notes().len(), 1);
+    }
+}
@@ -84,7 +84,7 @@ pub fn detect_pitch(
         if cmnd[tau] < threshold {
             // Find the local minimum after this point
             let mut t = tau;
-            while t + 1 <= window_size.min(max_lag) && cmnd[t + 1] < cmnd[t] {
+            while t < window_size.min(max_lag) && cmnd[t + 1] < cmnd[t] {
                 t += 1;
             }
             best_tau = t;
@@ -95,9 +95,14 @@ pub fn detect_pitch(
 
     // Fallback: if no period found below threshold, use the global minimum
     if best_tau == 0 {
-        for tau in min_lag..=window_size.min(max_lag) {
-            if cmnd[tau] < best_val {
-                best_val = cmnd[tau];
+        for (tau, &val) in cmnd
+            .iter()
+            .enumerate()
+            .take(window_size.min(max_lag) + 1)
+            .skip(min_lag)
+        {
+            if val < best_val {
+                best_val = val;
                 best_tau = tau;
             }
         }
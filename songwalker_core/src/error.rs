@@ -1,4 +1,5 @@
 use crate::token::{Span, Token};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 #[derive(Debug)]
@@ -38,6 +39,61 @@ impl fmt::Display for SongWalkerError {
 
 impl std::error::Error for SongWalkerError {}
 
+impl SongWalkerError {
+    /// Source range the error points at, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            SongWalkerError::Lex(e) => {
+                let pos = e.pos();
+                Some(Span {
+                    start: pos,
+                    end: pos + 1,
+                })
+            }
+            SongWalkerError::Parse(ParseError::UnexpectedToken { span, .. }) => Some(*span),
+            SongWalkerError::Parse(ParseError::UnexpectedEOF { .. }) => None,
+        }
+    }
+}
+
+impl LexError {
+    /// Source offset where the error was detected.
+    pub fn pos(&self) -> usize {
+        match self {
+            LexError::UnexpectedChar { pos, .. }
+            | LexError::UnterminatedString { pos }
+            | LexError::UnterminatedRegex { pos }
+            | LexError::InvalidNumber { pos, .. } => *pos,
+        }
+    }
+}
+
+/// A serializable error report for editors: message plus source range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Source offset (start).
+    pub start: usize,
+    /// Source offset (end).
+    pub end: usize,
+}
+
+impl Diagnostic {
+    /// Build a diagnostic from an error, falling back to `default_pos`
+    /// (typically the end of the source) when the error has no span.
+    pub fn from_error(error: &SongWalkerError, default_pos: usize) -> Self {
+        let span = error.span().unwrap_or(Span {
+            start: default_pos,
+            end: default_pos,
+        });
+        Diagnostic {
+            message: error.to_string(),
+            start: span.start,
+            end: span.end,
+        }
+    }
+}
+
 impl fmt::Display for LexError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,1046 @@
+//! Source formatter — pretty-prints a `Program` back to canonical `.sw` source.
+//!
+//! The output re-parses to the same program. Comments are kept (trailing
+//! comments stay on their line), blank lines between statements are
+//! preserved but collapsed to one, statements are indented by four spaces
+//! per block and terminated with `;`, and note lines in a track body are
+//! aligned so that notes and step durations form columns.
+
+use crate::ast::*;
+use crate::error::SongWalkerError;
+use crate::token::Span;
+
+const INDENT: &str = "    ";
+
+/// Parse and format `.sw` source. Fails if the source has syntax errors.
+pub fn format_source(source: &str) -> Result<String, SongWalkerError> {
+    let program = crate::parse(source)?;
+    let chars: Vec<char> = source.chars().collect();
+    Ok(Formatter::new(Some(&chars)).format(&program))
+}
+
+/// Format a program that has no original source (e.g. a generated AST).
+///
+/// Every statement goes on its own line and track definitions are
+/// separated by blank lines.
+pub fn format_program(program: &Program) -> String {
+    Formatter::new(None).format(program)
+}
+
+// ── Layout ──────────────────────────────────────────────────
+
+/// One output line before alignment.
+enum Line {
+    Blank,
+    /// A complete line of text.
+    Text {
+        indent: usize,
+        text: String,
+        comment: Option<String>,
+    },
+    /// Note-like statements sharing one line, aligned with neighbours.
+    Cells {
+        indent: usize,
+        cells: Vec<Cell>,
+        comment: Option<String>,
+    },
+}
+
+/// A note, chord or rest: the part before the step duration, and the step.
+struct Cell {
+    head: String,
+    step: Option<String>,
+}
+
+struct Formatter<'a> {
+    /// Original source (as chars, matching span offsets), if available.
+    source: Option<&'a [char]>,
+    lines: Vec<Line>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: Option<&'a [char]>) -> Self {
+        Formatter {
+            source,
+            lines: Vec::new(),
+        }
+    }
+
+    fn format(mut self, program: &Program) -> String {
+        self.layout_statements(&program.statements, 0);
+        self.render()
+    }
+
+    /// Number of newlines between two source offsets (None without source).
+    fn newlines_between(&self, start: usize, end: usize) -> Option<usize> {
+        let src = self.source?;
+        let end = end.min(src.len());
+        let start = start.min(end);
+        Some(src[start..end].iter().filter(|&&c| c == '\n').count())
+    }
+
+    /// Offset just past the first `{` at or after `from` (a block opener).
+    fn block_open(&self, from: usize) -> Option<usize> {
+        let src = self.source?;
+        src.iter()
+            .skip(from)
+            .position(|&c| c == '{')
+            .map(|i| from + i + 1)
+    }
+
+    fn push_text(&mut self, indent: usize, text: String) {
+        self.lines.push(Line::Text {
+            indent,
+            text,
+            comment: None,
+        });
+    }
+
+    /// Attach a trailing comment to the last emitted line.
+    fn attach_comment(&mut self, text: &str) -> bool {
+        match self.lines.last_mut() {
+            Some(Line::Text { comment, .. }) | Some(Line::Cells { comment, .. })
+                if comment.is_none() =>
+            {
+                *comment = Some(text.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn push_blank(&mut self) {
+        if !matches!(self.lines.last(), None | Some(Line::Blank)) {
+            self.lines.push(Line::Blank);
+        }
+    }
+
+    // ── Top level ──
+
+    /// Lay out top-level statements, or the members of a `parallel` block.
+    fn layout_statements(&mut self, statements: &[Statement], indent: usize) {
+        let mut prev: Option<(&Statement, usize)> = None;
+        for stmt in statements {
+            let Span { start, end } = stmt.span();
+            if let Some((prev_stmt, prev_end)) = prev {
+                let gap = self.newlines_between(prev_end, start);
+                if let Statement::Comment { text, .. } = stmt
+                    && gap == Some(0)
+                    && self.attach_comment(text)
+                {
+                    prev = Some((stmt, end));
+                    continue;
+                }
+                let blank = match gap {
+                    Some(n) => n >= 2,
+                    None => {
+                        matches!(prev_stmt, Statement::TrackDef { .. })
+                            || (matches!(stmt, Statement::TrackDef { .. })
+                                && !matches!(prev_stmt, Statement::Comment { .. }))
+                    }
+                };
+                if blank {
+                    self.push_blank();
+                }
+            }
+            self.layout_statement(stmt, indent);
+            prev = Some((stmt, end));
+        }
+    }
+
+    fn layout_statement(&mut self, stmt: &Statement, indent: usize) {
+        match stmt {
+            Statement::TrackDef {
+                name,
+                params,
+                body,
+                span_start,
+                ..
+            } => {
+                let params: Vec<String> = params.iter().map(Param::to_string).collect();
+                self.push_text(0, format!("track {name}({}) {{", params.join(", ")));
+                let open = self.block_open(*span_start);
+                self.layout_block(body, open, 1);
+                self.push_text(0, "}".into());
+            }
+            Statement::TrackCall {
+                name,
+                transpose,
+                velocity,
+                play_duration,
+                looped,
+                args,
+                step,
+                ..
+            } => {
+                let call =
+                    format_track_call(name, transpose, velocity, play_duration, *looped, args, step);
+                self.push_text(indent, format!("{call};"));
+            }
+            Statement::Parallel {
+                body,
+                joined: true,
+                ..
+            } => {
+                let calls: Vec<String> = body
+                    .iter()
+                    .filter_map(|member| match member {
+                        Statement::TrackCall {
+                            name,
+                            transpose,
+                            velocity,
+                            play_duration,
+                            looped,
+                            args,
+                            step,
+                            ..
+                        } => Some(format_track_call(
+                            name,
+                            transpose,
+                            velocity,
+                            play_duration,
+                            *looped,
+                            args,
+                            step,
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                self.push_text(indent, format!("{};", calls.join(" & ")));
+            }
+            Statement::Parallel { body, .. } => {
+                self.push_text(indent, "parallel {".into());
+                self.layout_statements(body, indent + 1);
+                self.push_text(indent, "}".into());
+            }
+            Statement::At { beat, body, .. } => {
+                let first = self.lines.len();
+                self.layout_statement(body, indent);
+                if let Some(Line::Text { text, .. }) = self.lines.get_mut(first) {
+                    text.insert_str(0, &format!("at {}: ", format_duration(beat)));
+                }
+            }
+            Statement::ConstDecl { name, value, .. } => {
+                self.push_text(indent, format!("const {name} = {};", format_expr(value)));
+            }
+            Statement::ScaleDecl { name, value, .. } => {
+                self.push_text(indent, format!("scale {name} = {};", format_expr(value)));
+            }
+            Statement::GrooveDecl { name, value, .. } => {
+                self.push_text(indent, format!("groove {name} = {};", format_expr(value)));
+            }
+            Statement::Assignment { target, value, .. } => {
+                self.push_text(indent, format!("{target} = {};", format_expr(value)));
+            }
+            Statement::Comment { text, .. } => self.push_text(indent, format_comment(text)),
+        }
+    }
+
+    // ── Track bodies ──
+
+    /// Lay out a block body. `open` is the offset just past its `{`.
+    fn layout_block(&mut self, body: &[TrackStatement], open: Option<usize>, indent: usize) {
+        let mut prev_end = open;
+        let mut prev: Option<&TrackStatement> = None;
+        for stmt in body {
+            let Span { start, end } = stmt.span();
+            let gap = prev_end.and_then(|p| self.newlines_between(p, start));
+
+            if let TrackStatement::Comment { text, .. } = stmt
+                && gap == Some(0)
+                && self.attach_comment(text)
+            {
+                prev_end = Some(end);
+                prev = Some(stmt);
+                continue;
+            }
+
+            if let Some(cell) = note_cell(stmt) {
+                // Keep notes that shared a source line together, unless the
+                // previous note has no step and this cell would become its step.
+                let same_line = gap == Some(0)
+                    && prev.is_some_and(|p| note_cell(p).is_some_and(|c| c.step.is_some()))
+                    && !self.last_line_has_comment();
+                if same_line && let Some(Line::Cells { cells, .. }) = self.lines.last_mut() {
+                    cells.push(cell);
+                } else {
+                    if prev.is_some() && gap.is_some_and(|n| n >= 2) {
+                        self.push_blank();
+                    }
+                    self.lines.push(Line::Cells {
+                        indent,
+                        cells: vec![cell],
+                        comment: None,
+                    });
+                }
+            } else {
+                if prev.is_some() && gap.is_some_and(|n| n >= 2) {
+                    self.push_blank();
+                }
+                self.layout_track_statement(stmt, indent);
+            }
+            prev_end = Some(end);
+            prev = Some(stmt);
+        }
+    }
+
+    fn last_line_has_comment(&self) -> bool {
+        matches!(
+            self.lines.last(),
+            Some(Line::Cells {
+                comment: Some(_),
+                ..
+            })
+        )
+    }
+
+    fn layout_track_statement(&mut self, stmt: &TrackStatement, indent: usize) {
+        match stmt {
+            TrackStatement::Assignment { target, value, .. } => {
+                self.push_text(indent, format!("{target} = {};", format_expr(value)));
+            }
+            TrackStatement::ForLoop {
+                variable,
+                start,
+                condition,
+                update,
+                body,
+                span_start,
+                ..
+            } => {
+                let update = match update {
+                    LoopUpdate::Increment => format!("{variable}++"),
+                    LoopUpdate::Decrement => format!("{variable}--"),
+                    LoopUpdate::Add(e) => format!("{variable} += {}", format_expr(e)),
+                    LoopUpdate::Sub(e) => format!("{variable} -= {}", format_expr(e)),
+                    LoopUpdate::Set(e) => format!("{variable} = {}", format_expr(e)),
+                };
+                self.push_text(
+                    indent,
+                    format!(
+                        "for (let {variable} = {}; {}; {update}) {{",
+                        format_expr(start),
+                        format_expr(condition),
+                    ),
+                );
+                let open = self.block_open(*span_start);
+                self.layout_block(body, open, indent + 1);
+                self.push_text(indent, "}".into());
+            }
+            TrackStatement::If { .. } => self.layout_if(stmt, indent, ""),
+            TrackStatement::Repeat {
+                count,
+                index,
+                body,
+                endings,
+                span_start,
+                ..
+            } => {
+                let index = index.as_ref().map(|i| format!(" as {i}")).unwrap_or_default();
+                self.push_text(indent, format!("repeat {count}{index} {{"));
+                let open = self.block_open(*span_start);
+                self.layout_block(body, open, indent + 1);
+                for ending in endings {
+                    let passes: Vec<String> = ending.passes.iter().map(u32::to_string).collect();
+                    self.push_text(indent, format!("}} ending {} {{", passes.join(", ")));
+                    let open = self.block_open(ending.span_start);
+                    self.layout_block(&ending.body, open, indent + 1);
+                }
+                self.push_text(indent, "}".into());
+            }
+            TrackStatement::TrackCall {
+                name,
+                transpose,
+                velocity,
+                play_duration,
+                looped,
+                args,
+                step,
+                ..
+            } => {
+                let call =
+                    format_track_call(name, transpose, velocity, play_duration, *looped, args, step);
+                self.push_text(indent, format!("{call};"));
+            }
+            TrackStatement::Comment { text, .. } => self.push_text(indent, format_comment(text)),
+            TrackStatement::NoteEvent { .. }
+            | TrackStatement::Chord { .. }
+            | TrackStatement::ChordSymbol { .. }
+            | TrackStatement::Rest { .. } => {
+                unreachable!("note-like statements are laid out as cells")
+            }
+        }
+    }
+
+    /// Lay out an `if` statement whose first line starts with `prefix`
+    /// (`} else ` for an `else if`).
+    fn layout_if(&mut self, stmt: &TrackStatement, indent: usize, prefix: &str) {
+        let TrackStatement::If {
+            condition,
+            then_body,
+            else_body,
+            ..
+        } = stmt
+        else {
+            return;
+        };
+        self.push_text(indent, format!("{prefix}if ({}) {{", format_expr(condition)));
+        let open = self.block_open(condition.span_end);
+        self.layout_block(then_body, open, indent + 1);
+        match else_body.as_slice() {
+            [] => self.push_text(indent, "}".into()),
+            [nested @ TrackStatement::If { .. }] => self.layout_if(nested, indent, "} else "),
+            _ => {
+                self.push_text(indent, "} else {".into());
+                let then_end = then_body.last().map(|s| s.span().end).or(open);
+                let open = then_end.and_then(|end| self.block_open(end));
+                self.layout_block(else_body, open, indent + 1);
+                self.push_text(indent, "}".into());
+            }
+        }
+    }
+
+    // ── Rendering ──
+
+    fn render(mut self) -> String {
+        align_cells(&mut self.lines);
+
+        let mut out = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Blank => {}
+                Line::Text {
+                    indent,
+                    text,
+                    comment,
+                } => {
+                    out.push_str(&INDENT.repeat(*indent));
+                    out.push_str(text);
+                    push_trailing_comment(&mut out, comment);
+                }
+                Line::Cells {
+                    indent,
+                    cells,
+                    comment,
+                } => {
+                    out.push_str(&INDENT.repeat(*indent));
+                    let texts: Vec<&str> = cells.iter().map(|c| c.head.as_str()).collect();
+                    out.push_str(texts.join("  ").trim_end());
+                    push_trailing_comment(&mut out, comment);
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn push_trailing_comment(out: &mut String, comment: &Option<String>) {
+    if let Some(text) = comment {
+        out.push_str("  ");
+        out.push_str(&format_comment(text));
+    }
+}
+
+/// Align runs of consecutive note lines into columns.
+///
+/// Within a run, step durations of the i-th note on each line start in the
+/// same column, and every note but the last on a line is padded so the
+/// following note lines up too. Each cell's `head` is replaced by its
+/// final, padded text.
+fn align_cells(lines: &mut [Line]) {
+    let mut i = 0;
+    while i < lines.len() {
+        let Line::Cells { indent, .. } = lines[i] else {
+            i += 1;
+            continue;
+        };
+        let mut j = i;
+        while j < lines.len() && matches!(&lines[j], Line::Cells { indent: n, .. } if *n == indent) {
+            j += 1;
+        }
+        align_run(&mut lines[i..j]);
+        i = j;
+    }
+}
+
+fn row(line: &Line) -> &[Cell] {
+    match line {
+        Line::Cells { cells, .. } => cells,
+        _ => &[],
+    }
+}
+
+fn align_run(run: &mut [Line]) {
+    let columns = run.iter().map(|l| row(l).len()).max().unwrap_or(0);
+
+    let mut head_width = vec![0; columns];
+    for line in run.iter() {
+        for (col, cell) in row(line).iter().enumerate() {
+            if cell.step.is_some() {
+                head_width[col] = head_width[col].max(width(&cell.head));
+            }
+        }
+    }
+
+    let mut cell_texts: Vec<Vec<String>> = run
+        .iter()
+        .map(|line| {
+            row(line)
+                .iter()
+                .enumerate()
+                .map(|(col, cell)| match &cell.step {
+                    Some(step) => format!("{} {step}", pad(&cell.head, head_width[col])),
+                    None => cell.head.clone(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut cell_width = vec![0; columns];
+    for texts in &cell_texts {
+        for (col, text) in texts.iter().enumerate().take(texts.len().saturating_sub(1)) {
+            cell_width[col] = cell_width[col].max(width(text));
+        }
+    }
+    for texts in &mut cell_texts {
+        let last = texts.len().saturating_sub(1);
+        for (col, text) in texts.iter_mut().enumerate().take(last) {
+            *text = pad(text, cell_width[col]);
+        }
+    }
+
+    for (line, texts) in run.iter_mut().zip(cell_texts) {
+        if let Line::Cells { cells, .. } = line {
+            for (cell, text) in cells.iter_mut().zip(texts) {
+                cell.head = text;
+                cell.step = None;
+            }
+        }
+    }
+}
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
+
+fn pad(s: &str, to: usize) -> String {
+    format!("{s}{}", " ".repeat(to.saturating_sub(width(s))))
+}
+
+// ── Statement pieces ────────────────────────────────────────
+
+/// The cell for a note, chord or rest; `None` for other statements.
+fn note_cell(stmt: &TrackStatement) -> Option<Cell> {
+    match stmt {
+        TrackStatement::NoteEvent {
+            pitch,
+            transpose,
+            velocity,
+            audible_duration,
+            step_duration,
+            ..
+        } => Some(Cell {
+            head: format!(
+                "{pitch}{}{}",
+                format_transpose(transpose),
+                format_modifiers(velocity, audible_duration)
+            ),
+            step: step_duration.as_ref().map(format_duration),
+        }),
+        TrackStatement::Chord {
+            notes,
+            audible_duration,
+            step_duration,
+            ..
+        } => {
+            let notes: Vec<String> = notes
+                .iter()
+                .map(|n| {
+                    let note = format!("{}{}", n.pitch, format_transpose(&n.transpose));
+                    match &n.audible_duration {
+                        Some(d) => format!("{note}@{}", format_duration(d)),
+                        None => note,
+                    }
+                })
+                .collect();
+            Some(Cell {
+                head: format!(
+                    "[{}]{}",
+                    notes.join(", "),
+                    format_modifiers(&None, audible_duration)
+                ),
+                step: step_duration.as_ref().map(format_duration),
+            })
+        }
+        TrackStatement::ChordSymbol {
+            symbol,
+            velocity,
+            audible_duration,
+            step_duration,
+            ..
+        } => Some(Cell {
+            head: format!(
+                "{}{}",
+                format_string(symbol),
+                format_modifiers(velocity, audible_duration)
+            ),
+            step: step_duration.as_ref().map(format_duration),
+        }),
+        TrackStatement::Rest { duration, .. } => Some(Cell {
+            head: format_duration(duration),
+            step: None,
+        }),
+        _ => None,
+    }
+}
+
+fn format_track_call(
+    name: &str,
+    transpose: &Option<Expr>,
+    velocity: &Option<f64>,
+    play_duration: &Option<DurationExpr>,
+    looped: bool,
+    args: &[Expr],
+    step: &Option<DurationExpr>,
+) -> String {
+    let args: Vec<String> = args.iter().map(format_expr).collect();
+    let mut modifiers = format_modifiers(velocity, play_duration);
+    if looped {
+        modifiers = modifiers.replacen('@', "@~", 1);
+    }
+    let mut out = format!("{name}{}{modifiers}({})", format_transpose(transpose), args.join(", "));
+    if let Some(step) = step {
+        out.push(' ');
+        out.push_str(&format_duration(step));
+    }
+    out
+}
+
+/// `+semitones` or `-semitones` after a pitch or track name.
+fn format_transpose(transpose: &Option<Expr>) -> String {
+    let Some(expr) = transpose else {
+        return String::new();
+    };
+    let (sign, amount) = match &expr.kind {
+        ExprKind::Unary {
+            op: UnaryOp::Neg,
+            operand,
+        } => ('-', operand.as_ref()),
+        _ => ('+', expr),
+    };
+    match &amount.kind {
+        ExprKind::Number(_) | ExprKind::Identifier(_) => format!("{sign}{}", format_expr(amount)),
+        _ => format!("{sign}({})", format_expr(amount)),
+    }
+}
+
+/// `*velocity@duration` modifiers. The `@` form only accepts `/N`, `N` or
+/// dots, so other fractions are written as decimal beats.
+fn format_modifiers(velocity: &Option<f64>, duration: &Option<DurationExpr>) -> String {
+    let mut out = String::new();
+    if let Some(v) = velocity {
+        out.push('*');
+        out.push_str(&format_number(*v));
+    }
+    if let Some(d) = duration {
+        out.push('@');
+        out.push_str(&match d {
+            DurationExpr::Fraction(n, m) if *n == 1.0 => format!("/{}", format_number(*m)),
+            DurationExpr::Fraction(n, m) => format_number(n / m),
+            other => format_duration(other),
+        });
+    }
+    out
+}
+
+fn format_comment(text: &str) -> String {
+    if text.is_empty() {
+        "//".into()
+    } else {
+        format!("// {text}")
+    }
+}
+
+// ── Expressions ─────────────────────────────────────────────
+
+/// Format a duration expression in its source form.
+pub fn format_duration(dur: &DurationExpr) -> String {
+    match dur {
+        DurationExpr::Inverse(n) => format!("/{}", format_number(*n)),
+        DurationExpr::Fraction(n, m) => format!("{}/{}", format_number(*n), format_number(*m)),
+        DurationExpr::Beats(n) => format_number(*n),
+        DurationExpr::Dots(count) => ".".repeat(*count),
+    }
+}
+
+/// Format a number without a trailing `.0` for whole values.
+pub fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{n}")
+    }
+}
+
+/// Format an expression in canonical source form.
+pub fn format_expr(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Number(n) => format_number(*n),
+        ExprKind::StringLit(s) => format_string(s),
+        ExprKind::RegexLit(s) => s.clone(),
+        ExprKind::Identifier(name) => name.clone(),
+        ExprKind::Array(items) => {
+            let items: Vec<String> = items.iter().map(format_expr).collect();
+            format!("[{}]", items.join(", "))
+        }
+        ExprKind::ObjectLit(props) => {
+            if props.is_empty() {
+                return "{}".into();
+            }
+            let props: Vec<String> = props
+                .iter()
+                .map(|(key, value)| format!("{}: {}", format_key(key), format_expr(value)))
+                .collect();
+            format!("{{{}}}", props.join(", "))
+        }
+        ExprKind::FunctionCall { function, args } => {
+            let args: Vec<String> = args.iter().map(format_expr).collect();
+            format!("{function}({})", args.join(", "))
+        }
+        ExprKind::PropertyAccess { property, .. } => property.clone(),
+        ExprKind::Index { target, index } => format!("{}[{}]", format_expr(target), format_expr(index)),
+        // In expressions only the `N/M` form parses.
+        ExprKind::DurationLit(DurationExpr::Inverse(n)) => format!("1/{}", format_number(*n)),
+        ExprKind::DurationLit(d) => format_duration(d),
+        ExprKind::Binary { op, lhs, rhs } => {
+            // Operators are left-associative, so an equal-precedence
+            // operation needs parentheses only on the right.
+            let lhs = format_operand(lhs, |p| p < op.precedence());
+            let rhs = format_operand(rhs, |p| p <= op.precedence());
+            format!("{lhs} {} {rhs}", op.symbol())
+        }
+        ExprKind::Unary { op, operand } => {
+            let operand = format_operand(operand, |_| true);
+            match op {
+                UnaryOp::Not => format!("!{operand}"),
+                UnaryOp::Neg => format!("-{operand}"),
+            }
+        }
+    }
+}
+
+/// Format an operand, parenthesized if it is a binary operation whose
+/// precedence satisfies `needs_parens`.
+fn format_operand(expr: &Expr, needs_parens: impl Fn(u8) -> bool) -> String {
+    match &expr.kind {
+        ExprKind::Binary { op, .. } if needs_parens(op.precedence()) => {
+            format!("({})", format_expr(expr))
+        }
+        _ => format_expr(expr),
+    }
+}
+
+/// Quote a string literal, preferring single quotes. The quote is
+/// escaped if the string contains both kinds.
+fn format_string(s: &str) -> String {
+    let quote = if s.contains('\'') && !s.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push(quote);
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+fn format_key(key: &str) -> String {
+    let is_ident = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_ident {
+        key.to_string()
+    } else {
+        format_string(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SONG: &str = r#"// Demo song
+const lead   = Oscillator({type:'square',attack:0.01});
+track.beatsPerMinute=140
+
+
+melody*90@4(lead) 8;   // intro
+drums ( )
+
+track melody(inst) {   // lead line
+    track.instrument = inst
+    track.duration = 1/4
+
+    C4 /4
+    Eb4*80@/8 /4   // accent
+    [C3@2, E3, G3]@1 /2
+    4
+    for (let i = 0; i < 2; i ++) {
+        E2 /2  E2 /4  G2 /4
+        A2 /2 A2 /4  B2 .
+    }
+}
+
+track drums() {
+    Kick /2
+    Kick /4
+}
+"#;
+
+    const FORMATTED: &str = r#"// Demo song
+const lead = Oscillator({type: 'square', attack: 0.01});
+track.beatsPerMinute = 140;
+
+melody*90@4(lead) 8;  // intro
+drums();
+
+track melody(inst) {  // lead line
+    track.instrument = inst;
+    track.duration = 1/4;
+
+    C4               /4
+    Eb4*80@/8        /4  // accent
+    [C3@2, E3, G3]@1 /2
+    4
+    for (let i = 0; i < 2; i++) {
+        E2 /2  E2 /4  G2 /4
+        A2 /2  A2 /4  B2 .
+    }
+}
+
+track drums() {
+    Kick /2
+    Kick /4
+}
+"#;
+
+    #[test]
+    fn formats_song() {
+        assert_eq!(format_source(SONG).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let once = format_source(SONG).unwrap();
+        assert_eq!(format_source(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn formatted_song_compiles_identically() {
+        let notes = |src: &str| -> Vec<(f64, String, f64, f64)> {
+            let program = crate::parse(src).unwrap();
+            crate::compiler::compile(&program)
+                .unwrap()
+                .events
+                .into_iter()
+                .filter_map(|e| match e.kind {
+                    crate::compiler::EventKind::Note {
+                        pitch,
+                        velocity,
+                        gate,
+                        ..
+                    } => Some((e.time, pitch, velocity, gate)),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(notes(SONG), notes(&format_source(SONG).unwrap()));
+    }
+
+    #[test]
+    fn formatting_keeps_property_values() {
+        let src = "track.effects=[reverb,  delay]\ntrack.mix = {wet:0.5}\nt()\ntrack t() {\ntrack.effects = [reverb]\nC4\n}\n";
+        let properties = |src: &str| -> Vec<(f64, String, String)> {
+            let program = crate::parse(src).unwrap();
+            crate::compiler::compile(&program)
+                .unwrap()
+                .events
+                .into_iter()
+                .filter_map(|e| match e.kind {
+                    crate::compiler::EventKind::SetProperty { target, value, .. } => {
+                        Some((e.time, target, value))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        let before = properties(src);
+        assert_eq!(before[0].2, "[reverb, delay]");
+        assert_eq!(before, properties(&format_source(src).unwrap()));
+    }
+
+    #[test]
+    fn step_less_note_is_not_joined_with_rest() {
+        let src = "track t() {\n    C3 /4  D3\n    E3 /4  2\n}\n";
+        let program = crate::parse(src).unwrap();
+        let out = format_source(src).unwrap();
+        assert_eq!(out, src);
+        assert_eq!(crate::parse(&out).unwrap().statements.len(), program.statements.len());
+    }
+
+    #[test]
+    fn format_program_without_source() {
+        let program = crate::parse("riff();\ntrack riff() {\n C3 /4\n D3 /2\n}\nriff();").unwrap();
+        assert_eq!(
+            format_program(&program),
+            "riff();\n\ntrack riff() {\n    C3 /4\n    D3 /2\n}\n\nriff();\n"
+        );
+    }
+
+    #[test]
+    fn string_with_both_quotes_round_trips() {
+        let src = "track.name = 'it\\'s a \"song\"';\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(out, src);
+        let program = crate::parse(&out).unwrap();
+        let Statement::Assignment { value, .. } = &program.statements[0] else {
+            panic!("expected an assignment");
+        };
+        assert!(matches!(&value.kind, ExprKind::StringLit(s) if s == "it's a \"song\""));
+    }
+
+    #[test]
+    fn keeps_comments_before_else_and_ending() {
+        let src = "track t(x) {\n    if (x) { C4 /4 } // first\n    else { D4 /4 }\n    repeat 2 { E4 /4 }\n    // before\n    ending 1 { F4 /4 }\n}\n";
+        assert_eq!(
+            format_source(src).unwrap(),
+            "track t(x) {\n    if (x) {\n        C4 /4  // first\n    } else {\n        D4 /4\n    }\n    \
+             repeat 2 {\n        E4 /4\n        // before\n    } ending 1 {\n        F4 /4\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn formats_repeat() {
+        let src = "track t() {\nrepeat 2 as i {C3 /4\nD3 /4}\nending 1{E3 /2}   ending 2 {\n  G3 /2 }\n}\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "track t() {\n    repeat 2 as i {\n        C3 /4\n        D3 /4\n    } ending 1 {\n        E3 /2\n    \
+             } ending 2 {\n        G3 /2\n    }\n}\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_if_else() {
+        let src = "track t(a, b) {\nif(a&&(b||!a)) {C3 /4}\nelse if ((a - 1) - (b - 1) == -1) { D3 /4 }\nelse {\nE3 /4\n}\n}\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "track t(a, b) {\n    if (a && (b || !a)) {\n        C3 /4\n    \
+             } else if (a - 1 - (b - 1) == -1) {\n        D3 /4\n    } else {\n        E3 /4\n    }\n}\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_degrees_and_scales() {
+        let src = "scale hijaz=[0,1,4,5,7,8,10]\ntrack t() {\ntrack.key='E hijaz'\n^1 /4\n^3+1*90 /4\n[^1,^-n]\n}\nt(^5)\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "scale hijaz = [0, 1, 4, 5, 7, 8, 10];\ntrack t() {\n    track.key = 'E hijaz';\n    \
+             ^1      /4\n    ^3+1*90 /4\n    [^1, ^-n]\n}\nt(^5);\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_chord_symbols() {
+        let src = "track t() {\ntrack.voicing='drop2'\n\"Cmaj7/E\" /2\n'F#m7'*80@/8 /4\n}\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "track t() {\n    track.voicing = 'drop2';\n    'Cmaj7/E'    /2\n    'F#m7'*80@/8 /4\n}\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_groove_decl() {
+        let src = "groove mpc={unit:1/4,timing:[0,0.1],velocity:[0,-10]}\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(out, "groove mpc = {unit: 1/4, timing: [0, 0.1], velocity: [0, -10]};\n");
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_generators() {
+        let src = "t(choose([C4,E4],[3,1]),euclid(3,8)[i-1])\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(out, "t(choose([C4, E4], [3, 1]), euclid(3, 8)[i - 1]);\n");
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_transpose() {
+        let src = "track t(n) {\nC4 + 7*90 /4\n[C4,C4 - n, C4+(n*2)]\n}\nt -12 ( 3 )\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "track t(n) {\n    C4+7*90 /4\n    [C4, C4-n, C4+(n * 2)]\n}\nt-12(3);\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_typed_params() {
+        let src = "track riff(inst,root:pitch ,  len : duration) {\nroot\n}\nriff(synth, C3, 1/2)\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "track riff(inst, root: pitch, len: duration) {\n    root\n}\nriff(synth, C3, 1/2);\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn formats_arrangement() {
+        let src = "parallel{drums()\n  // bass\n bass() 4}\nlead()&pad@~ 8( )\nat 16:parallel { a(); at 1/2 : b() }\n";
+        let out = format_source(src).unwrap();
+        assert_eq!(
+            out,
+            "parallel {\n    drums();\n    // bass\n    bass() 4;\n}\nlead() & pad@~8();\n\
+             at 16: parallel {\n    a();\n    at 1/2: b();\n}\n"
+        );
+        assert_eq!(format_source(&out).unwrap(), out);
+    }
+
+    #[test]
+    fn at_durations_stay_parseable() {
+        assert_eq!(format_modifiers(&None, &Some(DurationExpr::Fraction(1.0, 8.0))), "@/8");
+        assert_eq!(format_modifiers(&Some(64.0), &Some(DurationExpr::Fraction(3.0, 8.0))), "*64@0.375");
+    }
+
+    #[test]
+    fn strings_are_requoted() {
+        assert_eq!(format_string("square"), "'square'");
+        assert_eq!(format_string("it's"), "\"it's\"");
+        assert_eq!(format_string("a\\b"), "'a\\\\b'");
+    }
+}
@@ -0,0 +1,144 @@
+//! Feel — swing, groove templates and humanize.
+//!
+//! The compiler steps through a track on an exact grid; `Feel` moves each
+//! note as it is emitted. Swing stretches the first of every pair of
+//! `swing_unit`s, a `Groove` nudges timing and velocity per grid slot, and
+//! humanize adds seeded random jitter on top.
+
+/// A groove template: `groove mpc = {unit: 1/4, timing: [...], velocity: [...]}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Groove {
+    /// Length of one slot in beats.
+    pub unit: f64,
+    /// Timing offset per slot, as a fraction of `unit` (0.1 is a tenth
+    /// of a slot late). Repeats when the slots run out.
+    pub timing: Vec<f64>,
+    /// Velocity added per slot. Repeats when the slots run out.
+    pub velocity: Vec<f64>,
+}
+
+impl Groove {
+    /// Timing (in beats) and velocity offsets for a note written at `time`,
+    /// from the slot nearest to it.
+    pub fn offsets(&self, time: f64) -> (f64, f64) {
+        let slot = (time / self.unit).round() as i64;
+        let at = |values: &[f64]| match values.len() {
+            0 => 0.0,
+            len => values[slot.rem_euclid(len as i64) as usize],
+        };
+        (at(&self.timing) * self.unit, at(&self.velocity))
+    }
+}
+
+/// Track settings for how notes sit against the grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feel {
+    /// Share of each pair of swing units taken by the first
+    /// (`track.swing`); 0.5 is straight, 2/3 is triplet swing.
+    pub swing: f64,
+    /// `track.swingUnit` in beats, eighth notes by default.
+    pub swing_unit: f64,
+    /// `track.groove`.
+    pub groove: Option<Groove>,
+    /// Largest random timing shift in beats (`track.humanizeTiming`).
+    pub humanize_timing: f64,
+    /// Largest random velocity change (`track.humanizeVelocity`).
+    pub humanize_velocity: f64,
+}
+
+impl Default for Feel {
+    fn default() -> Self {
+        Feel {
+            swing: 0.5,
+            swing_unit: 0.5,
+            groove: None,
+            humanize_timing: 0.0,
+            humanize_velocity: 0.0,
+        }
+    }
+}
+
+impl Feel {
+    /// Where and how loud a note written at `time` with `velocity` plays.
+    /// `random` returns values in [0, 1) and is only called while
+    /// humanize is on.
+    pub fn place(&self, time: f64, velocity: f64, mut random: impl FnMut() -> f64) -> (f64, f64) {
+        let (groove_time, groove_velocity) = match &self.groove {
+            Some(groove) => groove.offsets(time),
+            None => (0.0, 0.0),
+        };
+        let mut time = self.swung(time) + groove_time;
+        let mut velocity = velocity + groove_velocity;
+
+        if self.humanize_timing > 0.0 {
+            time += self.humanize_timing * (2.0 * random() - 1.0);
+        }
+        if self.humanize_velocity > 0.0 {
+            velocity += self.humanize_velocity * (2.0 * random() - 1.0);
+        }
+        (time.max(0.0), velocity.clamp(0.0, 127.0))
+    }
+
+    /// Map grid time onto swung time. Within each pair of units the first
+    /// takes `swing` of the pair and the second the rest, so notes on the
+    /// pair boundaries stay put.
+    fn swung(&self, time: f64) -> f64 {
+        let pair = 2.0 * self.swing_unit;
+        let within = time.rem_euclid(pair);
+        let start = time - within;
+        if within < self.swing_unit {
+            start + within * 2.0 * self.swing
+        } else {
+            start + pair * self.swing + (within - self.swing_unit) * 2.0 * (1.0 - self.swing)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn never() -> f64 {
+        unreachable!("humanize is off")
+    }
+
+    #[test]
+    fn swing_delays_the_offbeat() {
+        let feel = Feel { swing: 2.0 / 3.0, ..Feel::default() };
+        let times: Vec<f64> = [0.0, 0.5, 1.0, 1.5, 1.25].iter().map(|&t| feel.place(t, 100.0, never).0).collect();
+        let expected = [0.0, 2.0 / 3.0, 1.0, 1.0 + 2.0 / 3.0, 1.0 + 1.0 / 3.0];
+        for (time, expected) in times.iter().zip(expected) {
+            assert!((time - expected).abs() < 1e-9, "{times:?}");
+        }
+        assert_eq!(Feel::default().place(0.75, 100.0, never), (0.75, 100.0));
+    }
+
+    #[test]
+    fn groove_offsets_repeat_per_slot() {
+        let feel = Feel {
+            groove: Some(Groove {
+                unit: 0.25,
+                timing: vec![0.0, 0.2],
+                velocity: vec![10.0, -20.0, 0.0],
+            }),
+            ..Feel::default()
+        };
+        assert_eq!(feel.place(0.0, 100.0, never), (0.0, 110.0));
+        assert_eq!(feel.place(0.25, 100.0, never), (0.3, 80.0));
+        assert_eq!(feel.place(0.75, 120.0, never), (0.8, 127.0));
+    }
+
+    #[test]
+    fn humanize_stays_within_its_range() {
+        let feel = Feel {
+            humanize_timing: 0.1,
+            humanize_velocity: 10.0,
+            ..Feel::default()
+        };
+        assert_eq!(feel.place(1.0, 100.0, || 0.5), (1.0, 100.0));
+        let (time, velocity) = feel.place(1.0, 100.0, || 0.0);
+        assert!((time - 0.9).abs() < 1e-9);
+        assert_eq!(velocity, 90.0);
+        assert_eq!(feel.place(0.0, 100.0, || 0.0).0, 0.0);
+    }
+}
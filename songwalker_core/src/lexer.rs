@@ -35,6 +35,46 @@ impl Lexer {
         Ok(tokens)
     }
 
+    /// Tokenize the whole input, recording lexer errors instead of stopping.
+    ///
+    /// An unexpected character is skipped; an unterminated string, regex or
+    /// invalid number discards the rest of its line. The returned token
+    /// stream always ends with `EOF`.
+    pub fn tokenize_recovering(&mut self) -> (Vec<Spanned>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            let start = self.pos;
+            match self.next_token() {
+                Ok(spanned) => {
+                    let is_eof = spanned.token == Token::EOF;
+                    match &spanned.token {
+                        Token::Newline | Token::Comment(_) => {}
+                        _ => {
+                            self.prev_significant = Some(spanned.token.clone());
+                        }
+                    }
+                    tokens.push(spanned);
+                    if is_eof {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    if let LexError::UnexpectedChar { pos, .. } = e {
+                        self.pos = pos + 1;
+                    } else {
+                        self.pos = start;
+                        while self.pos < self.chars.len() && self.chars[self.pos] != '\n' {
+                            self.pos += 1;
+                        }
+                    }
+                    errors.push(e);
+                }
+            }
+        }
+        (tokens, errors)
+    }
+
     fn peek_at(&self, offset: usize) -> Option<char> {
         self.chars.get(self.pos + offset).copied()
     }
@@ -105,7 +145,7 @@ impl Lexer {
                 Ok(self.spanned(Token::Newline, start))
             }
             '/' if self.peek_at(1) == Some('/') => self.lex_comment(start),
-            '/' if self.is_regex_context() && self.peek_at(1).map_or(false, |c| c != ' ') => {
+            '/' if self.is_regex_context() && self.peek_at(1).is_some_and(|c| c != ' ') => {
                 self.lex_regex(start)
             }
             '/' => {
@@ -132,10 +172,22 @@ impl Lexer {
                 self.advance();
                 Ok(self.spanned(Token::Comma, start))
             }
+            '=' if self.peek_at(1) == Some('=') => {
+                self.pos += 2;
+                Ok(self.spanned(Token::EqEq, start))
+            }
             '=' => {
                 self.advance();
                 Ok(self.spanned(Token::Eq, start))
             }
+            '!' if self.peek_at(1) == Some('=') => {
+                self.pos += 2;
+                Ok(self.spanned(Token::NotEq, start))
+            }
+            '!' => {
+                self.advance();
+                Ok(self.spanned(Token::Bang, start))
+            }
             '(' => {
                 self.advance();
                 Ok(self.spanned(Token::LParen, start))
@@ -160,10 +212,18 @@ impl Lexer {
                 self.advance();
                 Ok(self.spanned(Token::RBrace, start))
             }
+            '<' if self.peek_at(1) == Some('=') => {
+                self.pos += 2;
+                Ok(self.spanned(Token::LtEq, start))
+            }
             '<' => {
                 self.advance();
                 Ok(self.spanned(Token::Lt, start))
             }
+            '>' if self.peek_at(1) == Some('=') => {
+                self.pos += 2;
+                Ok(self.spanned(Token::GtEq, start))
+            }
             '>' => {
                 self.advance();
                 Ok(self.spanned(Token::Gt, start))
@@ -172,6 +232,30 @@ impl Lexer {
                 self.advance();
                 Ok(self.spanned(Token::Colon, start))
             }
+            '&' if self.peek_at(1) == Some('&') => {
+                self.pos += 2;
+                Ok(self.spanned(Token::AndAnd, start))
+            }
+            '|' if self.peek_at(1) == Some('|') => {
+                self.pos += 2;
+                Ok(self.spanned(Token::OrOr, start))
+            }
+            '%' => {
+                self.advance();
+                Ok(self.spanned(Token::Percent, start))
+            }
+            '&' => {
+                self.advance();
+                Ok(self.spanned(Token::Amp, start))
+            }
+            '~' => {
+                self.advance();
+                Ok(self.spanned(Token::Tilde, start))
+            }
+            '^' => {
+                self.advance();
+                Ok(self.spanned(Token::Caret, start))
+            }
             '+' if self.peek_at(1) == Some('+') => {
                 self.pos += 2;
                 Ok(self.spanned(Token::PlusPlus, start))
@@ -215,6 +299,7 @@ impl Lexer {
                     Some('n') => s.push('\n'),
                     Some('t') => s.push('\t'),
                     Some('\\') => s.push('\\'),
+                    Some(c @ ('\'' | '"')) => s.push(c),
                     Some(c) => {
                         s.push('\\');
                         s.push(c);
@@ -265,7 +350,7 @@ impl Lexer {
                 self.pos += 1;
             } else if ch == '.' {
                 // Only consume dot as decimal if followed by a digit
-                if self.peek_at(1).map_or(false, |c| c.is_ascii_digit()) {
+                if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
                     self.pos += 1; // consume the dot
                 } else {
                     break;
@@ -285,24 +370,38 @@ impl Lexer {
     fn lex_ident(&mut self, start: usize) -> Result<Spanned, LexError> {
         while self.pos < self.chars.len() {
             let ch = self.chars[self.pos];
-            if ch.is_ascii_alphanumeric() || ch == '_' {
+            // `#` after the first letter spells sharps: `F#4`.
+            if ch.is_ascii_alphanumeric() || ch == '_' || ch == '#' {
                 self.pos += 1;
             } else {
                 break;
             }
         }
         let text: String = self.chars[start..self.pos].iter().collect();
-        let token = match text.as_str() {
-            "track" => Token::Track,
-            "const" => Token::Const,
-            "let" => Token::Let,
-            "for" => Token::For,
-            _ => Token::Ident(text),
-        };
+        let token = keyword(&text).unwrap_or(Token::Ident(text));
         Ok(self.spanned(token, start))
     }
 }
 
+/// Reserved words, which can't be used as names.
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("track", Token::Track),
+    ("const", Token::Const),
+    ("let", Token::Let),
+    ("for", Token::For),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("parallel", Token::Parallel),
+    ("repeat", Token::Repeat),
+    ("ending", Token::Ending),
+    ("at", Token::AtKeyword),
+];
+
+/// The keyword token spelled `text`, if it is one.
+pub fn keyword(text: &str) -> Option<Token> {
+    KEYWORDS.iter().find(|(word, _)| *word == text).map(|(_, token)| token.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -436,4 +535,94 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_arrangement_tokens() {
+        let tokens = lex("at 16: a() & b()\nparallel {");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::AtKeyword,
+                Token::Number(16.0),
+                Token::Colon,
+                Token::Ident("a".into()),
+                Token::LParen,
+                Token::RParen,
+                Token::Amp,
+                Token::Ident("b".into()),
+                Token::LParen,
+                Token::RParen,
+                Token::Newline,
+                Token::Parallel,
+                Token::LBrace,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_degree_tokens() {
+        assert_eq!(
+            lex("^1 ^-2"),
+            vec![Token::Caret, Token::Number(1.0), Token::Caret, Token::Minus, Token::Number(2.0)]
+        );
+    }
+
+    #[test]
+    fn test_sharp_note_names() {
+        assert_eq!(
+            lex("F#4 'F#m7'"),
+            vec![Token::Ident("F#4".into()), Token::StringLit("F#m7".into())]
+        );
+    }
+
+    #[test]
+    fn test_condition_operators() {
+        let tokens = lex("if (!a && b == 1 || c != 2 % 3 <= d >= e)");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::If,
+                Token::LParen,
+                Token::Bang,
+                Token::Ident("a".into()),
+                Token::AndAnd,
+                Token::Ident("b".into()),
+                Token::EqEq,
+                Token::Number(1.0),
+                Token::OrOr,
+                Token::Ident("c".into()),
+                Token::NotEq,
+                Token::Number(2.0),
+                Token::Percent,
+                Token::Number(3.0),
+                Token::LtEq,
+                Token::Ident("d".into()),
+                Token::GtEq,
+                Token::Ident("e".into()),
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_recovering_skips_bad_chars() {
+        let (tokens, errors) = Lexer::new("C3 $ /4\nD3 'open\nE3").tokenize_recovering();
+        let tokens: Vec<Token> = tokens.into_iter().map(|s| s.token).collect();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], LexError::UnexpectedChar { ch: '$', pos: 3 }));
+        assert!(matches!(errors[1], LexError::UnterminatedString { .. }));
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("C3".into()),
+                Token::Slash,
+                Token::Number(4.0),
+                Token::Newline,
+                Token::Ident("D3".into()),
+                Token::Newline,
+                Token::Ident("E3".into()),
+                Token::EOF,
+            ]
+        );
+    }
 }
@@ -1,13 +1,19 @@
 pub mod ast;
+pub mod chord;
 pub mod compiler;
 pub mod dsp;
 pub mod error;
+pub mod format;
+pub mod groove;
 pub mod lexer;
+pub mod midi;
 pub mod parser;
 pub mod preset;
+pub mod scale;
+pub mod tempo;
 pub mod token;
 
-use crate::error::SongWalkerError;
+use crate::error::{Diagnostic, SongWalkerError};
 use crate::lexer::Lexer;
 use crate::parser::Parser;
 use wasm_bindgen::prelude::*;
@@ -19,6 +25,31 @@ pub fn parse(input: &str) -> Result<ast::Program, SongWalkerError> {
     Ok(parser.parse_program()?)
 }
 
+/// Parse a `.sw` source string, recovering from lexer and parser errors.
+///
+/// Always returns a (possibly partial) `Program` containing every statement
+/// that parsed, together with all errors found, in source order.
+pub fn parse_recovering(input: &str) -> (ast::Program, Vec<SongWalkerError>) {
+    let (tokens, lex_errors) = Lexer::new(input).tokenize_recovering();
+    let mut parser = Parser::new(tokens);
+    let (program, parse_errors) = parser.parse_program_recovering();
+
+    let mut errors: Vec<SongWalkerError> = lex_errors
+        .into_iter()
+        .map(SongWalkerError::from)
+        .chain(parse_errors.into_iter().map(SongWalkerError::from))
+        .collect();
+    errors.sort_by_key(|e| e.span().map_or(input.len(), |s| s.start));
+    (program, errors)
+}
+
+/// Result of `compile_song_partial`: whatever compiled, plus diagnostics.
+#[derive(serde::Serialize)]
+struct PartialCompile {
+    events: compiler::EventList,
+    diagnostics: Vec<Diagnostic>,
+}
+
 /// WASM-exposed: compile `.sw` source into a JSON event list (strict/editor mode).
 /// Errors if a note plays before track.instrument is set.
 #[wasm_bindgen]
@@ -29,23 +60,358 @@ pub fn compile_song(source: &str) -> Result<JsValue, JsValue> {
     serde_wasm_bindgen::to_value(&event_list).map_err(|e| JsValue::from_str(&format!("{e}")))
 }
 
+/// WASM-exposed: compile `.sw` source for the editor, tolerating errors.
+/// Returns `{ events, diagnostics }`: events for every statement that parsed
+/// and compiled, and a diagnostic (message + source range) for each error.
+#[wasm_bindgen]
+pub fn compile_song_partial(source: &str) -> Result<JsValue, JsValue> {
+    let (program, errors) = parse_recovering(source);
+    let (events, compile_errors) = compiler::compile_recovering(&program);
+
+    let mut diagnostics: Vec<Diagnostic> = errors
+        .iter()
+        .map(|e| Diagnostic::from_error(e, source.len()))
+        .collect();
+    diagnostics.extend(compile_errors.into_iter().map(|(message, span)| Diagnostic {
+        message,
+        start: span.start,
+        end: span.end,
+    }));
+
+    serde_wasm_bindgen::to_value(&PartialCompile {
+        events,
+        diagnostics,
+    })
+    .map_err(|e| JsValue::from_str(&format!("{e}")))
+}
+
+/// WASM-exposed: format `.sw` source canonically (editor format-on-save).
+#[wasm_bindgen]
+pub fn format_song(source: &str) -> Result<String, JsValue> {
+    format::format_source(source).map_err(|e| JsValue::from_str(&format!("{e}")))
+}
+
 /// WASM-exposed: compile and render `.sw` source to a WAV byte array.
 #[wasm_bindgen]
 pub fn render_song_wav(source: &str, sample_rate: u32) -> Result<Vec<u8>, JsValue> {
-    let program = parse(source).map_err(|e| JsValue::from_str(&format!("{e}")))?;
-    let event_list =
-        compiler::compile(&program).map_err(|e| JsValue::from_str(&e))?;
+    let event_list = compile_source(source)?;
     Ok(dsp::renderer::render_wav(&event_list, sample_rate))
 }
 
+/// WASM-exposed: compile and render `.sw` source to an audio file.
+/// `format` is `"wav"`, `"flac"` or `"raw"`; `bit_depth` is 16, 24 or 32
+/// (32-bit float, not available for FLAC). Output is stereo. `loudness`
+/// normalizes to a target in LUFS; `limit` sets a true-peak ceiling in
+/// dBTP for the master limiter.
+#[wasm_bindgen]
+pub fn render_song_audio(
+    source: &str,
+    sample_rate: u32,
+    format: &str,
+    bit_depth: u32,
+    dither: bool,
+    loudness: Option<f64>,
+    limit: Option<f64>,
+) -> Result<Vec<u8>, JsValue> {
+    use dsp::encode::{EncodeSpec, FileFormat, SampleFormat};
+
+    let format = FileFormat::from_name(format)
+        .ok_or_else(|| JsValue::from_str(&format!("unknown audio format: {format}")))?;
+    let sample_format = match bit_depth {
+        16 => SampleFormat::Int16,
+        24 => SampleFormat::Int24,
+        32 => SampleFormat::Float32,
+        _ => return Err(JsValue::from_str(&format!("unsupported bit depth: {bit_depth}"))),
+    };
+    let event_list = compile_source(source)?;
+    let engine = dsp::engine::AudioEngine::new(sample_rate as f64);
+    let renderer = || {
+        let mut renderer = engine.renderer(&event_list);
+        renderer.set_soft_clip(limit.is_none());
+        renderer
+    };
+    let spec = EncodeSpec {
+        sample_format,
+        dither,
+        limiter: limit,
+        ..EncodeSpec::new(sample_rate)
+    };
+    let gain = match loudness {
+        Some(target) => {
+            let measured = dsp::renderer::scan_loudness(&mut renderer(), spec.channels).integrated;
+            if measured.is_finite() { 10f64.powf((target - measured) / 20.0) } else { 1.0 }
+        }
+        None => 1.0,
+    };
+    let spec = EncodeSpec { gain, ..spec };
+    let mut renderer = renderer();
+    let mut out = Vec::new();
+    dsp::encode::encode(&mut renderer, &spec, format, &mut out)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(out)
+}
+
+/// WASM-exposed: a master WAV plus one sample-aligned WAV per stem, from
+/// `render_song_stems`.
+#[wasm_bindgen]
+pub struct StemExport {
+    master: Vec<u8>,
+    names: Vec<String>,
+    stems: Vec<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl StemExport {
+    pub fn master(&self) -> Vec<u8> {
+        self.master.clone()
+    }
+
+    /// Number of stems.
+    pub fn count(&self) -> usize {
+        self.stems.len()
+    }
+
+    /// Track or instrument name of a stem.
+    pub fn name(&self, index: usize) -> Option<String> {
+        self.names.get(index).cloned()
+    }
+
+    /// WAV bytes of a stem.
+    pub fn wav(&self, index: usize) -> Option<Vec<u8>> {
+        self.stems.get(index).cloned()
+    }
+}
+
+/// WASM-exposed: compile and render `.sw` source to 16-bit stereo stems.
+/// `grouping` is `"track"` (one stem per top-level track call) or
+/// `"instrument"`.
+#[wasm_bindgen]
+pub fn render_song_stems(source: &str, sample_rate: u32, grouping: &str) -> Result<StemExport, JsValue> {
+    use dsp::encode::{EncodeSpec, FileFormat};
+    use dsp::engine::StemGrouping;
+
+    let grouping = match grouping {
+        "track" => StemGrouping::Track,
+        "instrument" => StemGrouping::Instrument,
+        _ => return Err(JsValue::from_str(&format!("unknown stem grouping: {grouping}"))),
+    };
+    let event_list = compile_source(source)?;
+    let mut renderer =
+        dsp::engine::AudioEngine::new(sample_rate as f64).stem_renderer(&event_list, grouping);
+    let names = renderer.buses().to_vec();
+    let mut master = Vec::new();
+    let mut stems = vec![Vec::new(); names.len()];
+    dsp::encode::encode_stems(
+        &mut renderer,
+        &EncodeSpec::new(sample_rate),
+        FileFormat::Wav,
+        &mut master,
+        stems.iter_mut().collect(),
+    )
+    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(StemExport { master, names, stems })
+}
+
+/// WASM-exposed: compile `.sw` source and export it as a Standard MIDI File.
+/// `library_index` is the preset library's `index.json`, used to emit GM
+/// program changes for `loadPreset` instruments.
+#[wasm_bindgen]
+pub fn export_song_midi(source: &str, library_index: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let event_list = compile_source(source)?;
+    let library: Option<preset::LibraryIndex> = library_index
+        .map(|json| serde_json::from_str(&json))
+        .transpose()
+        .map_err(|e| JsValue::from_str(&format!("{e}")))?;
+    Ok(midi::export_smf(&event_list, library.as_ref()))
+}
+
+/// WASM-exposed: convert a Standard MIDI File into formatted `.sw` source.
+/// `quantize` is the grid in subdivisions per beat (4 = sixteenths).
+#[wasm_bindgen]
+pub fn import_midi(bytes: &[u8], quantize: Option<u32>) -> Result<String, JsValue> {
+    let options = midi::ImportOptions {
+        quantize,
+        ..midi::ImportOptions::default()
+    };
+    let program = midi::import_smf(bytes, &options).map_err(|e| JsValue::from_str(&e))?;
+    Ok(format::format_program(&program))
+}
+
 /// WASM-exposed: compile and render `.sw` source to mono f32 samples.
 /// Returns the raw audio buffer for AudioWorklet playback.
 #[wasm_bindgen]
 pub fn render_song_samples(source: &str, sample_rate: u32) -> Result<Vec<f32>, JsValue> {
-    let program = parse(source).map_err(|e| JsValue::from_str(&format!("{e}")))?;
-    let event_list =
-        compiler::compile(&program).map_err(|e| JsValue::from_str(&e))?;
+    let event_list = compile_source(source)?;
     let engine = dsp::engine::AudioEngine::new(sample_rate as f64);
-    let samples_f64 = engine.render(&event_list);
-    Ok(samples_f64.iter().map(|&s| s as f32).collect())
+    let mut renderer = engine.renderer(&event_list);
+    let mut samples = vec![0.0_f32; renderer.total_samples()];
+    renderer.process(&mut samples);
+    Ok(samples)
+}
+
+/// WASM-exposed: a streaming renderer for an AudioWorklet. Pulls one block
+/// at a time instead of rendering the whole song up front.
+#[wasm_bindgen]
+pub struct SongRenderer {
+    renderer: dsp::renderer::Renderer,
+}
+
+#[wasm_bindgen]
+impl SongRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str, sample_rate: u32) -> Result<SongRenderer, JsValue> {
+        let event_list = compile_source(source)?;
+        let engine = dsp::engine::AudioEngine::new(sample_rate as f64);
+        Ok(SongRenderer {
+            renderer: engine.renderer(&event_list),
+        })
+    }
+
+    /// Render the next block of mono samples; returns how many belong to
+    /// the song (0 once it has finished).
+    pub fn process(&mut self, out: &mut [f32]) -> usize {
+        self.renderer.process(out)
+    }
+
+    /// Jump to a sample offset.
+    pub fn seek(&mut self, sample: usize) {
+        self.renderer.seek(sample);
+    }
+
+    /// Current sample offset.
+    pub fn position(&self) -> usize {
+        self.renderer.position()
+    }
+
+    #[wasm_bindgen(js_name = totalSamples)]
+    pub fn total_samples(&self) -> usize {
+        self.renderer.total_samples()
+    }
+}
+
+/// WASM-exposed: a playback session for the editor's player. Holds the
+/// compiled song and transport state; positions are in beats.
+#[wasm_bindgen]
+pub struct PlaybackSession {
+    session: dsp::session::Session,
+}
+
+#[wasm_bindgen]
+impl PlaybackSession {
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str, sample_rate: u32) -> Result<PlaybackSession, JsValue> {
+        let event_list = compile_source(source)?;
+        Ok(PlaybackSession {
+            session: dsp::session::Session::new(&event_list, sample_rate as f64),
+        })
+    }
+
+    /// Hot-swap a recompiled song, keeping the playhead, loop and play state.
+    /// On a compile error the current song keeps playing.
+    pub fn load(&mut self, source: &str) -> Result<(), JsValue> {
+        let event_list = compile_source(source)?;
+        self.session.load(&event_list);
+        Ok(())
+    }
+
+    pub fn play(&mut self) {
+        self.session.play();
+    }
+
+    pub fn pause(&mut self) {
+        self.session.pause();
+    }
+
+    #[wasm_bindgen(js_name = isPlaying)]
+    pub fn is_playing(&self) -> bool {
+        self.session.is_playing()
+    }
+
+    pub fn seek(&mut self, beat: f64) {
+        self.session.seek(beat);
+    }
+
+    /// Playhead position in beats.
+    pub fn position(&self) -> f64 {
+        self.session.position()
+    }
+
+    /// Song length in beats.
+    pub fn duration(&self) -> f64 {
+        self.session.duration()
+    }
+
+    #[wasm_bindgen(js_name = setLoop)]
+    pub fn set_loop(&mut self, start_beat: f64, end_beat: f64) {
+        self.session.set_loop(start_beat, end_beat);
+    }
+
+    #[wasm_bindgen(js_name = clearLoop)]
+    pub fn clear_loop(&mut self) {
+        self.session.clear_loop();
+    }
+
+    /// Source spans of the sounding notes, flattened as
+    /// `[start0, end0, start1, end1, ...]`.
+    #[wasm_bindgen(js_name = activeNotes)]
+    pub fn active_notes(&self) -> Vec<u32> {
+        self.session
+            .active_notes()
+            .into_iter()
+            .flat_map(|(start, end)| [start as u32, end as u32])
+            .collect()
+    }
+
+    /// Render the next block of mono samples (silence while paused).
+    pub fn process(&mut self, out: &mut [f32]) {
+        self.session.process(out);
+    }
+}
+
+fn compile_source(source: &str) -> Result<compiler::EventList, JsValue> {
+    let program = parse(source).map_err(|e| JsValue::from_str(&format!("{e}")))?;
+    compiler::compile(&program).map_err(|e| JsValue::from_str(&e))
+}
+
+/// WASM-exposed: a live synth for an AudioWorklet, played from Web MIDI
+/// input. Feed raw MIDI bytes with `midi` and pull audio with `process`.
+#[wasm_bindgen]
+pub struct LiveSynth {
+    engine: dsp::live::LiveEngine,
+}
+
+#[wasm_bindgen]
+impl LiveSynth {
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: f64) -> LiveSynth {
+        LiveSynth {
+            engine: dsp::live::LiveEngine::new(sample_rate),
+        }
+    }
+
+    /// Play the instrument from `.sw` source: the `const` named
+    /// `instrument`, or else the instrument of the song's first note.
+    #[wasm_bindgen(js_name = setInstrument)]
+    pub fn set_instrument(&mut self, source: &str, instrument: Option<String>) -> Result<(), JsValue> {
+        let program = parse(source).map_err(|e| JsValue::from_str(&format!("{e}")))?;
+        let config = dsp::live::instrument_from_program(&program, instrument.as_deref())
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.engine.set_instrument(config);
+        Ok(())
+    }
+
+    /// Feed raw MIDI bytes (one or more messages, e.g. a Web MIDI event's data).
+    pub fn midi(&mut self, bytes: &[u8]) {
+        self.engine.feed(bytes);
+    }
+
+    /// Render the next block of mono samples.
+    pub fn process(&mut self, out: &mut [f32]) {
+        self.engine.process(out);
+    }
+
+    #[wasm_bindgen(js_name = activeVoices)]
+    pub fn active_voices(&self) -> usize {
+        self.engine.active_voices()
+    }
 }
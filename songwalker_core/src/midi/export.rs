@@ -0,0 +1,349 @@
+//! MIDI export — writes a compiled `EventList` as a Standard MIDI File.
+//!
+//! The file is format 1: track 0 is the conductor track holding tempo and
+//! time signature meta events (from `track.beatsPerMinute` and
+//! `track.timeSignature` assignments), followed by one MTrk per compiled
+//! track. Each track gets its own channel; tracks made only of drum names
+//! go to the GM percussion channel. When a library index is supplied,
+//! notes using a `loadPreset` instrument emit a program change from the
+//! preset's `gm_program`.
+
+use midly::num::{u4, u7, u15, u24, u28};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
+use super::{DRUM_CHANNEL, TICKS_PER_BEAT, drum_key};
+use crate::compiler::{EventKind, EventList};
+use crate::preset::LibraryIndex;
+
+/// An event placed at an absolute tick, before delta encoding.
+struct Timed<'a> {
+    tick: u64,
+    kind: TrackEventKind<'a>,
+}
+
+/// A compiled track's notes, collected in event order.
+struct TrackNotes<'a> {
+    name: &'a str,
+    /// (beat, key, velocity, gate, preset name)
+    notes: Vec<(f64, u8, u8, f64, Option<&'a str>)>,
+    drums: bool,
+}
+
+/// Export an EventList as a format 1 Standard MIDI File.
+pub fn export_smf(event_list: &EventList, library: Option<&LibraryIndex>) -> Vec<u8> {
+    let mut tracks = vec![conductor_track(event_list)];
+
+    let mut melodic_channel = 0u8;
+    for track in collect_tracks(event_list) {
+        let channel = if track.drums {
+            DRUM_CHANNEL
+        } else {
+            let channel = melodic_channel;
+            melodic_channel = (melodic_channel + 1) % 16;
+            if melodic_channel == DRUM_CHANNEL {
+                melodic_channel += 1;
+            }
+            channel
+        };
+        tracks.push(note_track(&track, channel, library));
+    }
+
+    let smf = Smf {
+        header: Header::new(Format::Parallel, Timing::Metrical(u15::new(TICKS_PER_BEAT))),
+        tracks: tracks.into_iter().map(encode_track).collect(),
+    };
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes).expect("writing to a Vec cannot fail");
+    bytes
+}
+
+fn to_ticks(beats: f64) -> u64 {
+    (beats.max(0.0) * TICKS_PER_BEAT as f64).round() as u64
+}
+
+/// Tempo and time signature changes, plus the end-of-song marker.
+fn conductor_track(event_list: &EventList) -> Vec<Timed<'static>> {
+    let mut events = Vec::new();
+    for event in &event_list.events {
+        let EventKind::SetProperty { target, value, .. } = &event.kind else {
+            continue;
+        };
+        let tick = to_ticks(event.time);
+        match target.as_str() {
+            "track.beatsPerMinute" => {
+                if let Ok(bpm) = value.parse::<f64>()
+                    && bpm > 0.0
+                {
+                    let micros = (60_000_000.0 / bpm).round() as u32;
+                    events.push(Timed {
+                        tick,
+                        kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+                            micros.min(0xFF_FFFF),
+                        ))),
+                    });
+                }
+            }
+            "track.timeSignature" => {
+                if let Some((num, den_pow)) = parse_time_signature(value) {
+                    events.push(Timed {
+                        tick,
+                        kind: TrackEventKind::Meta(MetaMessage::TimeSignature(num, den_pow, 24, 8)),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    events.push(Timed {
+        tick: to_ticks(event_list.total_beats),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    events
+}
+
+/// Parse `"3/4"` into (numerator, log2 denominator).
+fn parse_time_signature(value: &str) -> Option<(u8, u8)> {
+    let (num, den) = value.split_once('/')?;
+    let num: u8 = num.trim().parse().ok()?;
+    let den: u32 = den.trim().parse().ok()?;
+    (num > 0 && den.is_power_of_two()).then(|| (num, den.trailing_zeros() as u8))
+}
+
+/// Group note events by the track that played them, in first-use order.
+fn collect_tracks(event_list: &EventList) -> Vec<TrackNotes<'_>> {
+    let mut tracks: Vec<TrackNotes> = Vec::new();
+    for event in &event_list.events {
+        let EventKind::Note {
+            pitch,
+            midi,
+            velocity,
+            gate,
+            instrument,
+            track,
+            ..
+        } = &event.kind
+        else {
+            continue;
+        };
+        let (key, drum) = match (midi, drum_key(pitch)) {
+            (Some(m), _) => (*m, false),
+            (_, Some(k)) => (k, true),
+            _ => continue, // not representable in MIDI
+        };
+        let index = match tracks.iter().position(|t| t.name == track) {
+            Some(i) => i,
+            None => {
+                tracks.push(TrackNotes {
+                    name: track,
+                    notes: Vec::new(),
+                    drums: true,
+                });
+                tracks.len() - 1
+            }
+        };
+        let entry = &mut tracks[index];
+        entry.drums &= drum;
+        entry.notes.push((
+            event.time,
+            key,
+            velocity.round().clamp(1.0, 127.0) as u8,
+            *gate,
+            instrument.preset_ref.as_deref(),
+        ));
+    }
+    tracks
+}
+
+fn note_track<'a>(
+    track: &TrackNotes<'a>,
+    channel: u8,
+    library: Option<&LibraryIndex>,
+) -> Vec<Timed<'a>> {
+    let channel = u4::new(channel);
+    let name = if track.name.is_empty() { "song" } else { track.name };
+    let mut events = vec![Timed {
+        tick: 0,
+        kind: TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())),
+    }];
+
+    let mut program = None;
+    let mut end = 0;
+    for &(beat, key, velocity, gate, preset) in &track.notes {
+        let on = to_ticks(beat);
+        let off = on + to_ticks(gate).max(1);
+        end = end.max(off);
+
+        let gm_program = library.zip(preset).and_then(|(library, preset)| {
+            library
+                .presets
+                .iter()
+                .find(|e| e.name == preset || e.id == preset)
+                .and_then(|e| e.gm_program)
+        });
+        if let Some(gm) = gm_program
+            && !track.drums
+            && program != Some(gm)
+        {
+            program = Some(gm);
+            events.push(Timed {
+                tick: on,
+                kind: TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::ProgramChange {
+                        program: u7::new(gm.min(127)),
+                    },
+                },
+            });
+        }
+
+        events.push(Timed {
+            tick: on,
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(velocity),
+                },
+            },
+        });
+        events.push(Timed {
+            tick: off,
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                },
+            },
+        });
+    }
+    events.push(Timed {
+        tick: end,
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    events
+}
+
+/// Sort by tick (note-offs before note-ons at the same tick) and
+/// convert to delta times.
+fn encode_track(mut events: Vec<Timed<'_>>) -> Vec<TrackEvent<'_>> {
+    let order = |kind: &TrackEventKind| match kind {
+        TrackEventKind::Meta(MetaMessage::TrackName(_)) => 0,
+        TrackEventKind::Midi {
+            message: MidiMessage::NoteOff { .. },
+            ..
+        } => 1,
+        TrackEventKind::Meta(MetaMessage::EndOfTrack) => 3,
+        _ => 2,
+    };
+    events.sort_by_key(|e| (e.tick, order(&e.kind)));
+
+    let mut last = 0;
+    events
+        .into_iter()
+        .map(|e| {
+            let delta = (e.tick - last).min(0x0FFF_FFFF) as u32;
+            last = e.tick;
+            TrackEvent {
+                delta: u28::new(delta),
+                kind: e.kind,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    fn export(src: &str, library: Option<&LibraryIndex>) -> Smf<'static> {
+        let events = compile(&crate::parse(src).unwrap()).unwrap();
+        let bytes = export_smf(&events, library).leak();
+        Smf::parse(bytes).unwrap()
+    }
+
+    fn note_ons(track: &[TrackEvent]) -> Vec<(u64, u8, u8)> {
+        let mut tick = 0;
+        let mut out = Vec::new();
+        for e in track {
+            tick += e.delta.as_int() as u64;
+            if let TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, .. },
+            } = e.kind
+            {
+                out.push((tick, key.as_int(), channel.as_int()));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn one_mtrk_per_track_plus_conductor() {
+        let smf = export(
+            "track.beatsPerMinute = 150;\nlead();\ndrums();\n\
+             track lead() {\n    C4 /2\n    E4*64 /2\n}\n\
+             track drums() {\n    Kick /4\n    Snare /4\n}\n",
+            None,
+        );
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(smf.tracks.len(), 3);
+        assert!(smf.tracks[0].iter().any(|e| e.kind
+            == TrackEventKind::Meta(MetaMessage::Tempo(u24::new(400_000)))));
+
+        assert_eq!(smf.tracks[1][0].kind, TrackEventKind::Meta(MetaMessage::TrackName(b"lead")));
+        assert_eq!(note_ons(&smf.tracks[1]), [(0, 60, 0), (240, 64, 0)]);
+        // Drum-only tracks use the GM percussion channel.
+        assert_eq!(note_ons(&smf.tracks[2]), [(480, 36, 9), (600, 38, 9)]);
+    }
+
+    #[test]
+    fn writes_time_signature() {
+        let smf = export("track.timeSignature = 3/4;\nt();\ntrack t() {\n    C4 /4\n}\n", None);
+        assert!(smf.tracks[0].iter().any(|e| e.kind
+            == TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8))));
+    }
+
+    #[test]
+    fn gate_sets_note_off() {
+        let smf = export("t();\ntrack t() {\n    C4@/8 /2\n}\n", None);
+        let mut tick = 0;
+        let mut off = None;
+        for e in smf.tracks[1].iter() {
+            tick += e.delta.as_int();
+            if let TrackEventKind::Midi {
+                message: MidiMessage::NoteOff { .. },
+                ..
+            } = e.kind
+            {
+                off = Some(tick);
+            }
+        }
+        assert_eq!(off, Some(60));
+    }
+
+    #[test]
+    fn program_change_from_library() {
+        let library: LibraryIndex = serde_json::from_str(
+            r#"{"version": 1, "generatedAt": "", "presets": [
+                {"id": "bass", "name": "Finger Bass", "path": "b.json",
+                 "category": "sampler", "tags": [], "gmProgram": 33}
+            ]}"#,
+        )
+        .unwrap();
+        let src = "t();\ntrack t() {\n    track.instrument = loadPreset('Finger Bass');\n    E2 /4\n    G2 /4\n}\n";
+        let smf = export(src, Some(&library));
+        let programs: Vec<u8> = smf.tracks[1]
+            .iter()
+            .filter_map(|e| match e.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::ProgramChange { program },
+                    ..
+                } => Some(program.as_int()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(programs, [33]);
+    }
+}
@@ -0,0 +1,599 @@
+//! MIDI import — converts a Standard MIDI File into a SongWalker `Program`.
+//!
+//! Every (MIDI track, channel) pair with notes becomes a `TrackDef`. Notes
+//! keep their velocity (`*v`) and gate (`@dur`), silences become rests, and
+//! notes starting together are written with a `0` step so they sound at
+//! once. Each track's instrument is a `loadPreset` of the GM category of
+//! its first program change. All tracks are started at beat 0 by top-level
+//! calls with a `0` step. The initial tempo and time signature are song
+//! assignments; later tempo changes go into a generated `tempo` track.
+//!
+//! Use `crate::format::format_program` to print the result as source.
+
+use std::collections::HashMap;
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use super::{DRUM_CHANNEL, drum_name, midi_to_note};
+use crate::ast::*;
+use crate::lexer::keyword;
+use crate::preset::gm_category;
+
+/// Options for `import_smf`.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Quantization grid in subdivisions per beat (4 = sixteenth notes).
+    /// `None` keeps the file's exact timing.
+    pub quantize: Option<u32>,
+    /// How far notes move towards the grid, from 0.0 (not at all) to 1.0
+    /// (snapped exactly). Ignored without `quantize`.
+    pub strength: f64,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            quantize: None,
+            strength: 1.0,
+        }
+    }
+}
+
+/// A note with absolute timing in ticks.
+#[derive(Debug, Clone, Copy)]
+struct ImportedNote {
+    start: u64,
+    end: u64,
+    key: u8,
+    velocity: u8,
+}
+
+/// Notes for one (track, channel) pair.
+struct Part {
+    name: String,
+    channel: u8,
+    program: Option<u8>,
+    notes: Vec<ImportedNote>,
+}
+
+/// Convert a Standard MIDI File to a program.
+pub fn import_smf(bytes: &[u8], options: &ImportOptions) -> Result<Program, String> {
+    let smf = Smf::parse(bytes).map_err(|e| format!("Invalid MIDI file: {e}"))?;
+    let tpq = match smf.header.timing {
+        Timing::Metrical(t) if t.as_int() > 0 => t.as_int() as u64,
+        Timing::Metrical(_) => return Err("Invalid MIDI file: zero ticks per beat".into()),
+        Timing::Timecode(..) => return Err("SMPTE timecode MIDI files are not supported".into()),
+    };
+    let quantizer = Quantizer::new(tpq, options);
+
+    let mut parts: Vec<Part> = Vec::new();
+    let mut tempos: Vec<(u64, f64)> = Vec::new();
+    let mut time_signature: Option<(u8, u8)> = None;
+
+    for (index, track) in smf.tracks.iter().enumerate() {
+        let mut name = None;
+        let mut tick = 0u64;
+        let mut programs: HashMap<u8, u8> = HashMap::new();
+        // (channel, key) → stack of (start, velocity) for sounding notes
+        let mut sounding: HashMap<(u8, u8), Vec<(u64, u8)>> = HashMap::new();
+        let mut notes: Vec<(u8, ImportedNote)> = Vec::new();
+
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::TrackName(bytes)) if name.is_none() => {
+                    name = Some(String::from_utf8_lossy(bytes).into_owned());
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(micros)) if micros.as_int() > 0 => {
+                    tempos.push((tick, 60_000_000.0 / micros.as_int() as f64));
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(num, den_pow, ..))
+                    if time_signature.is_none() && den_pow < 8 =>
+                {
+                    time_signature = Some((num, den_pow));
+                }
+                TrackEventKind::Midi { channel, message } => {
+                    let channel = channel.as_int();
+                    match message {
+                        MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            sounding
+                                .entry((channel, key.as_int()))
+                                .or_default()
+                                .push((tick, vel.as_int()));
+                        }
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            let stack = sounding.entry((channel, key.as_int())).or_default();
+                            if !stack.is_empty() {
+                                // First in, first out for repeated keys.
+                                let (start, velocity) = stack.remove(0);
+                                notes.push((
+                                    channel,
+                                    ImportedNote {
+                                        start,
+                                        end: tick,
+                                        key: key.as_int(),
+                                        velocity,
+                                    },
+                                ));
+                            }
+                        }
+                        MidiMessage::ProgramChange { program } => {
+                            programs.entry(channel).or_insert(program.as_int());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Notes still sounding at the end of the track stop there.
+        for ((channel, key), stack) in sounding {
+            for (start, velocity) in stack {
+                notes.push((
+                    channel,
+                    ImportedNote {
+                        start,
+                        end: tick.max(start),
+                        key,
+                        velocity,
+                    },
+                ));
+            }
+        }
+
+        let mut channels: Vec<u8> = notes.iter().map(|(c, _)| *c).collect();
+        channels.sort_unstable();
+        channels.dedup();
+        let base = name
+            .as_deref()
+            .map(identifier)
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| format!("track{}", index + 1));
+        for &channel in &channels {
+            let name = if channels.len() > 1 {
+                format!("{base}_ch{}", channel + 1)
+            } else {
+                base.clone()
+            };
+            let mut part_notes: Vec<ImportedNote> = notes
+                .iter()
+                .filter(|(c, _)| *c == channel)
+                .map(|(_, n)| quantizer.note(*n))
+                .collect();
+            part_notes.sort_by_key(|n| (n.start, n.key));
+            parts.push(Part {
+                name,
+                channel,
+                program: programs.get(&channel).copied(),
+                notes: part_notes,
+            });
+        }
+    }
+
+    dedupe_names(&mut parts);
+    tempos.sort_by_key(|(tick, _)| *tick);
+    Ok(build_program(&parts, &tempos, time_signature, tpq))
+}
+
+// ── Quantization ────────────────────────────────────────────
+
+struct Quantizer {
+    /// Grid size in ticks (None = no quantization).
+    grid: Option<f64>,
+    strength: f64,
+}
+
+impl Quantizer {
+    fn new(tpq: u64, options: &ImportOptions) -> Self {
+        Quantizer {
+            grid: options
+                .quantize
+                .filter(|&q| q > 0)
+                .map(|q| tpq as f64 / q as f64),
+            strength: options.strength.clamp(0.0, 1.0),
+        }
+    }
+
+    fn tick(&self, tick: u64) -> u64 {
+        match self.grid {
+            Some(grid) => {
+                let t = tick as f64;
+                let snapped = (t / grid).round() * grid;
+                (t + (snapped - t) * self.strength).round() as u64
+            }
+            None => tick,
+        }
+    }
+
+    fn note(&self, note: ImportedNote) -> ImportedNote {
+        let start = self.tick(note.start);
+        let min_len = self.grid.map_or(1, |g| (g.round() as u64).max(1));
+        let end = self.tick(note.end).max(start + min_len);
+        ImportedNote { start, end, ..note }
+    }
+}
+
+// ── Program Construction ────────────────────────────────────
+
+/// Convert a MIDI name into a valid identifier.
+fn identifier(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    let out = out.trim_matches('_').to_string();
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("t_{out}")
+    } else if keyword(&out).is_some() || matches!(out.as_str(), "song" | "tempo") {
+        format!("{out}_part")
+    } else {
+        out
+    }
+}
+
+fn dedupe_names(parts: &mut [Part]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for part in parts.iter_mut() {
+        let count = seen.entry(part.name.clone()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            part.name = format!("{}_{count}", part.name);
+        }
+    }
+}
+
+/// Express a tick count as an exact duration in beats.
+fn ticks_to_duration(ticks: u64, tpq: u64) -> DurationExpr {
+    if ticks == 0 {
+        return DurationExpr::Beats(0.0);
+    }
+    let g = gcd(ticks, tpq);
+    let (num, den) = (ticks / g, tpq / g);
+    if den == 1 {
+        DurationExpr::Beats(num as f64)
+    } else if num == 1 {
+        DurationExpr::Inverse(den as f64)
+    } else {
+        DurationExpr::Fraction(num as f64, den as f64)
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn expr(kind: ExprKind) -> Expr {
+    Expr::new(kind, 0, 0)
+}
+
+fn assignment(target: &str, value: ExprKind) -> TrackStatement {
+    TrackStatement::Assignment {
+        target: target.into(),
+        value: expr(value),
+        span_start: 0,
+        span_end: 0,
+    }
+}
+
+fn rest(ticks: u64, tpq: u64) -> TrackStatement {
+    // A rest on its own line can't be written `/4`; spell it `1/4`.
+    let duration = match ticks_to_duration(ticks, tpq) {
+        DurationExpr::Inverse(den) => DurationExpr::Fraction(1.0, den),
+        other => other,
+    };
+    TrackStatement::Rest {
+        duration,
+        span_start: 0,
+        span_end: 0,
+    }
+}
+
+fn top_call(name: &str, step: Option<DurationExpr>) -> Statement {
+    Statement::TrackCall {
+        name: name.into(),
+        transpose: None,
+        velocity: None,
+        play_duration: None,
+        looped: false,
+        args: Vec::new(),
+        step,
+        span_start: 0,
+        span_end: 0,
+    }
+}
+
+fn build_program(
+    parts: &[Part],
+    tempos: &[(u64, f64)],
+    time_signature: Option<(u8, u8)>,
+    tpq: u64,
+) -> Program {
+    let mut statements = vec![Statement::Comment {
+        text: "Imported from MIDI".into(),
+        span_start: 0,
+        span_end: 0,
+    }];
+
+    let initial_bpm = tempos
+        .first()
+        .filter(|(tick, _)| *tick == 0)
+        .map_or(120.0, |(_, bpm)| *bpm);
+    statements.push(Statement::Assignment {
+        target: "track.beatsPerMinute".into(),
+        value: expr(ExprKind::Number(round_bpm(initial_bpm))),
+        span_start: 0,
+        span_end: 0,
+    });
+    if let Some((num, den_pow)) = time_signature {
+        statements.push(Statement::Assignment {
+            target: "track.timeSignature".into(),
+            value: expr(ExprKind::DurationLit(DurationExpr::Fraction(
+                num as f64,
+                (1u32 << den_pow) as f64,
+            ))),
+            span_start: 0,
+            span_end: 0,
+        });
+    }
+
+    let tempo_track = tempo_track(tempos, tpq);
+    let mut names: Vec<&str> = parts.iter().map(|p| p.name.as_str()).collect();
+    if tempo_track.is_some() {
+        names.insert(0, "tempo");
+    }
+    // Start every track at beat 0: all but the last call have a zero step.
+    for (i, name) in names.iter().enumerate() {
+        let step = (i + 1 < names.len()).then_some(DurationExpr::Beats(0.0));
+        statements.push(top_call(name, step));
+    }
+
+    if let Some(body) = tempo_track {
+        statements.push(Statement::TrackDef {
+            name: "tempo".into(),
+            params: Vec::new(),
+            body,
+            span_start: 0,
+            span_end: 0,
+        });
+    }
+    for part in parts {
+        statements.push(Statement::TrackDef {
+            name: part.name.clone(),
+            params: Vec::new(),
+            body: part_body(part, tpq),
+            span_start: 0,
+            span_end: 0,
+        });
+    }
+    Program { statements }
+}
+
+fn round_bpm(bpm: f64) -> f64 {
+    (bpm * 100.0).round() / 100.0
+}
+
+/// A track of timed tempo assignments, if the tempo changes after beat 0.
+fn tempo_track(tempos: &[(u64, f64)], tpq: u64) -> Option<Vec<TrackStatement>> {
+    if tempos.iter().all(|(tick, _)| *tick == 0) {
+        return None;
+    }
+    let mut body = Vec::new();
+    let mut cursor = 0;
+    for &(tick, bpm) in tempos.iter().filter(|(tick, _)| *tick > 0) {
+        body.push(rest(tick - cursor, tpq));
+        body.push(assignment("track.beatsPerMinute", ExprKind::Number(round_bpm(bpm))));
+        cursor = tick;
+    }
+    Some(body)
+}
+
+fn part_body(part: &Part, tpq: u64) -> Vec<TrackStatement> {
+    let drums = part.channel == DRUM_CHANNEL;
+    let mut body = Vec::new();
+    if let Some(program) = part.program.filter(|_| !drums) {
+        body.push(assignment(
+            "track.instrument",
+            ExprKind::FunctionCall {
+                function: "loadPreset".into(),
+                args: vec![expr(ExprKind::StringLit(gm_category(program).into()))],
+            },
+        ));
+    }
+
+    let notes: Vec<&ImportedNote> = part.notes.iter().filter(|n| n.key >= 12).collect();
+
+    // The most common onset interval becomes the default note length, so
+    // only notes that differ from it carry explicit durations.
+    let mut intervals: HashMap<u64, usize> = HashMap::new();
+    for pair in notes.windows(2) {
+        let ioi = pair[1].start - pair[0].start;
+        if ioi > 0 {
+            *intervals.entry(ioi).or_default() += 1;
+        }
+    }
+    let default_len = intervals
+        .into_iter()
+        .max_by_key(|&(ioi, count)| (count, std::cmp::Reverse(ioi)))
+        .map_or(tpq, |(ioi, _)| ioi);
+    body.push(assignment(
+        "track.noteLength",
+        match ticks_to_duration(default_len, tpq) {
+            DurationExpr::Beats(n) => ExprKind::Number(n),
+            DurationExpr::Inverse(n) => ExprKind::DurationLit(DurationExpr::Fraction(1.0, n)),
+            d => ExprKind::DurationLit(d),
+        },
+    ));
+
+    if let Some(first) = notes.first().filter(|n| n.start > 0) {
+        body.push(rest(first.start, tpq));
+    }
+    for (i, note) in notes.iter().enumerate() {
+        let gate = note.end - note.start;
+        // Step to the next onset; if the note ends before it, step to the
+        // end of the note and write the silence as a rest.
+        let (step, silence) = match notes.get(i + 1) {
+            Some(next) if gate < next.start - note.start => (gate, next.start - note.start - gate),
+            Some(next) => (next.start - note.start, 0),
+            None => (gate, 0),
+        };
+
+        let pitch = if drums {
+            drum_name(note.key)
+                .map(str::to_string)
+                .unwrap_or_else(|| midi_to_note(note.key))
+        } else {
+            midi_to_note(note.key)
+        };
+        body.push(TrackStatement::NoteEvent {
+            pitch,
+            transpose: None,
+            velocity: (note.velocity != 100).then_some(note.velocity as f64),
+            audible_duration: (gate != default_len).then(|| ticks_to_duration(gate, tpq)),
+            step_duration: (step != default_len).then(|| ticks_to_duration(step, tpq)),
+            span_start: 0,
+            span_end: 0,
+        });
+        if silence > 0 {
+            body.push(rest(silence, tpq));
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{EventKind, compile};
+    use crate::midi::export_smf;
+
+    /// Compiled notes as (beat, pitch, velocity, gate).
+    fn notes(program: &Program) -> Vec<(f64, String, f64, f64)> {
+        let mut notes: Vec<_> = compile(program)
+            .unwrap()
+            .events
+            .into_iter()
+            .filter_map(|e| match e.kind {
+                EventKind::Note {
+                    pitch,
+                    velocity,
+                    gate,
+                    ..
+                } => Some((e.time, pitch, velocity, gate)),
+                _ => None,
+            })
+            .collect();
+        notes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        notes
+    }
+
+    fn round_trip(src: &str, options: &ImportOptions) -> Program {
+        let events = compile(&crate::parse(src).unwrap()).unwrap();
+        import_smf(&export_smf(&events, None), options).unwrap()
+    }
+
+    #[test]
+    fn exported_song_imports_to_same_notes() {
+        let src = "track.beatsPerMinute = 90;\nlead() 0;\nbass();\n\
+                   track lead() {\n    C4*80@/8 /4\n    E4 /4\n    1/2\n    G4@1 1\n}\n\
+                   track bass() {\n    C2 /2\n    G2 /2\n}\n";
+        let program = round_trip(src, &ImportOptions::default());
+        assert_eq!(notes(&program), notes(&crate::parse(src).unwrap()));
+    }
+
+    #[test]
+    fn imported_program_formats_and_reparses() {
+        let src = "t();\ntrack t() {\n    track.instrument = loadPreset('x');\n    C4 /4\n    Eb4*90 /4\n    C4 /4\n}\n";
+        let program = round_trip(src, &ImportOptions::default());
+        let text = crate::format::format_program(&program);
+        assert!(text.contains("track.beatsPerMinute = 120;"), "{text}");
+        assert!(text.contains("track.noteLength = 1/4;"), "{text}");
+        assert!(text.contains("Eb4*90"), "{text}");
+        assert_eq!(notes(&crate::parse(&text).unwrap()), notes(&program));
+    }
+
+    #[test]
+    fn simultaneous_notes_use_zero_step() {
+        let src = "t();\ntrack t() {\n    [C4, E4, G4] /2\n    D4 /2\n}\n";
+        let program = round_trip(src, &ImportOptions::default());
+        assert_eq!(notes(&program), notes(&crate::parse(src).unwrap()));
+    }
+
+    #[test]
+    fn quantizes_to_grid() {
+        let options = ImportOptions {
+            quantize: Some(4),
+            ..ImportOptions::default()
+        };
+        let q = Quantizer::new(480, &options);
+        assert_eq!(q.tick(130), 120);
+        assert_eq!(q.tick(175), 120);
+        assert_eq!(q.tick(185), 240);
+
+        let half = Quantizer::new(
+            480,
+            &ImportOptions {
+                quantize: Some(4),
+                strength: 0.5,
+            },
+        );
+        assert_eq!(half.tick(140), 130);
+    }
+
+    #[test]
+    fn program_change_becomes_load_preset() {
+        let library: crate::preset::LibraryIndex = serde_json::from_str(
+            r#"{"version": 1, "generatedAt": "", "presets": [
+                {"id": "b", "name": "Bass", "path": "b.json", "category": "sampler", "tags": [], "gmProgram": 33}
+            ]}"#,
+        )
+        .unwrap();
+        let src = "t();\ntrack t() {\n    track.instrument = loadPreset('Bass');\n    E2 /4\n}\n";
+        let events = compile(&crate::parse(src).unwrap()).unwrap();
+        let program = import_smf(&export_smf(&events, Some(&library)), &ImportOptions::default()).unwrap();
+        let text = crate::format::format_program(&program);
+        assert!(text.contains("track.instrument = loadPreset('bass');"), "{text}");
+    }
+
+    #[test]
+    fn short_rests_stay_parseable() {
+        let src = "lead();\ntrack lead() {\n    1/4\n    C4 /4\n    1/8\n    E4 /4\n}\n";
+        let program = round_trip(src, &ImportOptions::default());
+        let source = crate::format::format_program(&program);
+        let reparsed = crate::parse(&source).unwrap_or_else(|e| panic!("{e:?}\n{source}"));
+        assert_eq!(notes(&reparsed), notes(&crate::parse(src).unwrap()));
+    }
+
+    #[test]
+    fn identifiers_are_sanitized() {
+        assert_eq!(identifier("Lead Synth #2"), "lead_synth_2");
+        assert_eq!(identifier("808"), "t_808");
+        assert_eq!(identifier("Track"), "track_part");
+        assert_eq!(identifier("Repeat"), "repeat_part");
+    }
+
+    #[test]
+    fn keyword_track_names_round_trip() {
+        let names = ["At", "Parallel", "Repeat", "Ending", "If", "Else"];
+        let src = "t();\ntrack t() {\n    C4 /4\n    D4 /4\n    E4 /4\n    F4 /4\n    G4 /4\n    A4 /4\n}\n";
+        let mut events = compile(&crate::parse(src).unwrap()).unwrap();
+        let tracks = events.events.iter_mut().filter_map(|e| match &mut e.kind {
+            EventKind::Note { track, .. } => Some(track),
+            _ => None,
+        });
+        for (track, name) in tracks.zip(names) {
+            *track = name.to_string();
+        }
+
+        let program = import_smf(&export_smf(&events, None), &ImportOptions::default()).unwrap();
+        let source = crate::format::format_program(&program);
+        let reparsed = crate::parse(&source).unwrap_or_else(|e| panic!("{e:?}\n{source}"));
+        assert_eq!(notes(&reparsed).len(), 6);
+        for name in ["at_part", "parallel_part", "repeat_part", "ending_part", "if_part", "else_part"] {
+            assert!(source.contains(&format!("track {name}()")), "{source}");
+        }
+    }
+}
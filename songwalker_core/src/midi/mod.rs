@@ -0,0 +1,71 @@
+//! Standard MIDI File support.
+//!
+//! - `export` — write a compiled `EventList` as a format 1 SMF.
+//! - `import` — convert an SMF into an `ast::Program`.
+
+pub mod export;
+pub mod import;
+
+pub use export::export_smf;
+pub use import::{ImportOptions, import_smf};
+
+/// Ticks per quarter note (one SongWalker beat) in exported files.
+pub const TICKS_PER_BEAT: u16 = 480;
+
+/// The General MIDI percussion channel (channel 10, zero-based).
+pub const DRUM_CHANNEL: u8 = 9;
+
+/// GM percussion keys for the drum names the editor recognises.
+const DRUM_KEYS: &[(&str, u8)] = &[
+    ("Kick", 36),
+    ("Rimshot", 37),
+    ("Snare", 38),
+    ("Clap", 39),
+    ("ClosedHiHat", 42),
+    ("HiHat", 42),
+    ("Tom", 45),
+    ("OpenHiHat", 46),
+    ("Crash", 49),
+    ("Ride", 51),
+];
+
+/// Map a drum name (`Kick`, `HiHat`, ...) to its GM percussion key.
+pub fn drum_key(name: &str) -> Option<u8> {
+    DRUM_KEYS.iter().find(|(n, _)| *n == name).map(|(_, k)| *k)
+}
+
+/// Map a GM percussion key back to a drum name.
+pub fn drum_name(key: u8) -> Option<&'static str> {
+    DRUM_KEYS.iter().find(|(_, k)| *k == key).map(|(n, _)| *n)
+}
+
+/// Convert a MIDI note number to a note name. Accidentals are always
+/// written as flats (`Db4`), so every key has a single spelling.
+pub fn midi_to_note(midi: u8) -> String {
+    const NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
+    let octave = midi as i32 / 12 - 1;
+    format!("{}{octave}", NAMES[midi as usize % 12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::engine::note_to_midi;
+
+    #[test]
+    fn note_names_round_trip() {
+        for midi in 12..=127u8 {
+            assert_eq!(note_to_midi(&midi_to_note(midi)), Some(midi as i32));
+        }
+        assert_eq!(midi_to_note(60), "C4");
+        assert_eq!(midi_to_note(61), "Db4");
+    }
+
+    #[test]
+    fn drum_names_round_trip() {
+        assert_eq!(drum_key("Kick"), Some(36));
+        assert_eq!(drum_name(36), Some("Kick"));
+        assert_eq!(drum_name(42), Some("ClosedHiHat"));
+        assert_eq!(drum_key("Cowbell"), None);
+    }
+}
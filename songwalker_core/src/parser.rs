@@ -1,15 +1,21 @@
 use crate::ast::*;
 use crate::error::ParseError;
-use crate::token::{token_to_string, Spanned, Token};
+use crate::token::{Spanned, Token};
 
 pub struct Parser {
     tokens: Vec<Spanned>,
     pos: usize,
+    /// Errors recorded while recovering from malformed statements.
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Spanned>) -> Self {
-        Parser { tokens, pos: 0 }
+        Parser {
+            tokens,
+            pos: 0,
+            errors: Vec::new(),
+        }
     }
 
     // ── Helpers ──────────────────────────────────────────────
@@ -31,6 +37,11 @@ impl Parser {
         self.tokens[self.pos].span
     }
 
+    /// End offset of the most recently consumed token.
+    fn prev_end(&self) -> usize {
+        self.tokens[self.pos.saturating_sub(1)].span.end
+    }
+
     fn advance(&mut self) -> Spanned {
         let s = self.tokens[self.pos].clone();
         self.pos += 1;
@@ -101,8 +112,8 @@ impl Parser {
         }
     }
 
-    /// Skip newlines and return any comments found.
-    fn skip_newlines_collecting_comments(&mut self) -> Vec<String> {
+    /// Skip newlines and return any comments found as `(text, start, end)`.
+    fn skip_newlines_collecting_comments(&mut self) -> Vec<(String, usize, usize)> {
         let mut comments = Vec::new();
         loop {
             match self.peek() {
@@ -110,8 +121,8 @@ impl Parser {
                     self.advance();
                 }
                 Token::Comment(text) => {
-                    comments.push(text);
-                    self.advance();
+                    let span = self.advance().span;
+                    comments.push((text, span.start, span.end));
                 }
                 _ => break,
             }
@@ -119,31 +130,122 @@ impl Parser {
         comments
     }
 
+    /// Look past newlines and comments for `token`, which continues the
+    /// block just closed (`else`, `ending`). If found, stop at it and return
+    /// the comments passed; otherwise rewind.
+    fn continues_with(&mut self, token: &Token) -> Option<Vec<TrackStatement>> {
+        let saved = self.pos;
+        let comments = self.skip_newlines_collecting_comments();
+        if !self.check(token) {
+            self.pos = saved;
+            return None;
+        }
+        Some(
+            comments
+                .into_iter()
+                .map(|(text, span_start, span_end)| TrackStatement::Comment {
+                    text,
+                    span_start,
+                    span_end,
+                })
+                .collect(),
+        )
+    }
+
     /// Skip an optional semicolon and/or newlines.
     fn skip_terminator(&mut self) {
         self.eat(&Token::Semicolon);
         self.skip_newlines();
     }
 
+    /// Skip tokens after a parse error up to the next statement boundary.
+    ///
+    /// Stops after a newline or `;` at the current nesting level, or after a
+    /// `{ ... }` block opened while skipping has been closed. Inside a block
+    /// (`in_block`), a `}` closing that block is left for the caller.
+    fn synchronize(&mut self, in_block: bool) {
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek() {
+                Token::Newline | Token::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => {
+                    if !in_block {
+                        // Stray closing brace at the top level.
+                        self.advance();
+                    }
+                    return;
+                }
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
     // ── Program ──────────────────────────────────────────────
 
+    /// Parse the whole program, failing on the first syntax error.
     pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        let (program, errors) = self.parse_program_recovering();
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(program),
+        }
+    }
+
+    /// Parse the whole program, recovering from syntax errors.
+    ///
+    /// Malformed statements are skipped up to the next newline, `;` or
+    /// closing brace, so the returned `Program` holds every statement that
+    /// did parse (including track definitions whose bodies contain errors).
+    /// Errors are returned in source order.
+    pub fn parse_program_recovering(&mut self) -> (Program, Vec<ParseError>) {
         let mut statements = Vec::new();
         self.skip_newlines();
 
         while !self.is_at_end() {
             // Collect any comments as statements
             let comments = self.skip_newlines_collecting_comments();
-            for c in comments {
-                statements.push(Statement::Comment(c));
+            for (text, span_start, span_end) in comments {
+                statements.push(Statement::Comment {
+                    text,
+                    span_start,
+                    span_end,
+                });
             }
             if self.is_at_end() {
                 break;
             }
-            statements.push(self.parse_statement()?);
-            self.skip_terminator();
+            match self.parse_statement() {
+                Ok(stmt) => {
+                    statements.push(stmt);
+                    self.skip_terminator();
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(false);
+                }
+            }
+        }
+        (Program { statements }, std::mem::take(&mut self.errors))
+    }
+
+    /// Expect the `}` closing a block. A missing brace (end of input) is
+    /// recorded rather than returned, so the partial block is kept.
+    fn expect_block_end(&mut self) {
+        if let Err(e) = self.expect(&Token::RBrace) {
+            self.errors.push(e);
         }
-        Ok(Program { statements })
     }
 
     // ── Top-Level Statement ─────────────────────────────────
@@ -151,8 +253,12 @@ impl Parser {
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
         match self.peek() {
             Token::Comment(text) => {
-                self.advance();
-                Ok(Statement::Comment(text))
+                let span = self.advance().span;
+                Ok(Statement::Comment {
+                    text,
+                    span_start: span.start,
+                    span_end: span.end,
+                })
             }
             Token::Track => {
                 // Distinguish `track name(...)` from `track.prop = ...`
@@ -163,18 +269,141 @@ impl Parser {
                 }
             }
             Token::Const => self.parse_const_decl(),
-            Token::Ident(_) => self.parse_ident_statement(false),
+            Token::Ident(word) if word == "scale" && matches!(self.peek_at(1), Token::Ident(_)) => {
+                self.parse_scale_decl()
+            }
+            Token::Ident(word) if word == "groove" && matches!(self.peek_at(1), Token::Ident(_)) => {
+                self.parse_groove_decl()
+            }
+            Token::Parallel | Token::AtKeyword => self.parse_arrangement(),
+            Token::Ident(_) => {
+                let stmt = self.parse_ident_statement(false)?;
+                if matches!(stmt, Statement::TrackCall { .. }) && self.check(&Token::Amp) {
+                    self.parse_join(stmt)
+                } else {
+                    Ok(stmt)
+                }
+            }
+            _ => Err(ParseError::UnexpectedToken {
+                expected: "statement (track, const, parallel, at, identifier, or comment)".into(),
+                found: self.peek(),
+                span: self.span(),
+            }),
+        }
+    }
+
+    // ── Arrangement ─────────────────────────────────────────
+
+    /// Parse a statement that places tracks on the timeline: a track call
+    /// (optionally `&`-joined), a `parallel` block or an `at` statement.
+    fn parse_arrangement(&mut self) -> Result<Statement, ParseError> {
+        match self.peek() {
+            Token::Parallel => self.parse_parallel_block(),
+            Token::AtKeyword => self.parse_at(),
+            Token::Ident(_) => {
+                let call = self.parse_call()?;
+                if self.check(&Token::Amp) {
+                    self.parse_join(call)
+                } else {
+                    Ok(call)
+                }
+            }
             _ => Err(ParseError::UnexpectedToken {
-                expected: "statement (track, const, identifier, or comment)".into(),
+                expected: "track call, parallel block, or at".into(),
                 found: self.peek(),
                 span: self.span(),
             }),
         }
     }
 
+    /// Parse a top-level track call, rejecting assignments.
+    fn parse_call(&mut self) -> Result<Statement, ParseError> {
+        if matches!(self.peek_at(1), Token::Dot | Token::Eq) {
+            self.advance();
+            return Err(ParseError::UnexpectedToken {
+                expected: "track call".into(),
+                found: self.peek(),
+                span: self.span(),
+            });
+        }
+        self.parse_ident_statement(false)
+    }
+
+    /// `a() & b() & c()` — `first` has already been parsed.
+    fn parse_join(&mut self, first: Statement) -> Result<Statement, ParseError> {
+        let span_start = first.span().start;
+        let mut body = vec![first];
+        while self.eat(&Token::Amp) {
+            self.skip_newlines();
+            body.push(self.parse_call()?);
+        }
+        Ok(Statement::Parallel {
+            body,
+            joined: true,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    /// `parallel { a(); b(); }`
+    fn parse_parallel_block(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
+        self.expect(&Token::Parallel)?;
+        self.expect(&Token::LBrace)?;
+        let mut body = Vec::new();
+        self.skip_newlines();
+
+        while !self.check(&Token::RBrace) && !self.is_at_end() {
+            let comments = self.skip_newlines_collecting_comments();
+            for (text, span_start, span_end) in comments {
+                body.push(Statement::Comment {
+                    text,
+                    span_start,
+                    span_end,
+                });
+            }
+            if self.check(&Token::RBrace) || self.is_at_end() {
+                break;
+            }
+            match self.parse_arrangement() {
+                Ok(stmt) => {
+                    body.push(stmt);
+                    self.skip_terminator();
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(true);
+                }
+            }
+        }
+        self.expect_block_end();
+        Ok(Statement::Parallel {
+            body,
+            joined: false,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    /// `at 16: chorus()`
+    fn parse_at(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
+        self.expect(&Token::AtKeyword)?;
+        let beat = self.parse_duration_expr()?;
+        self.expect(&Token::Colon)?;
+        let body = self.parse_arrangement()?;
+        Ok(Statement::At {
+            beat,
+            body: Box::new(body),
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
     // ── Track Definition ────────────────────────────────────
 
     fn parse_track_def(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
         self.expect(&Token::Track)?;
         let name = self.expect_ident()?;
         self.expect(&Token::LParen)?;
@@ -182,21 +411,46 @@ impl Parser {
         self.expect(&Token::RParen)?;
         self.expect(&Token::LBrace)?;
         let body = self.parse_track_body()?;
-        self.expect(&Token::RBrace)?;
-        Ok(Statement::TrackDef { name, params, body })
+        self.expect_block_end();
+        Ok(Statement::TrackDef {
+            name,
+            params,
+            body,
+            span_start,
+            span_end: self.prev_end(),
+        })
     }
 
-    fn parse_param_list(&mut self) -> Result<Vec<String>, ParseError> {
+    fn parse_param_list(&mut self) -> Result<Vec<Param>, ParseError> {
         let mut params = Vec::new();
         if !self.check(&Token::RParen) {
-            params.push(self.expect_ident()?);
+            params.push(self.parse_param()?);
             while self.eat(&Token::Comma) {
-                params.push(self.expect_ident()?);
+                params.push(self.parse_param()?);
             }
         }
         Ok(params)
     }
 
+    /// `name` or `name: type`
+    fn parse_param(&mut self) -> Result<Param, ParseError> {
+        let name = self.expect_ident()?;
+        if !self.eat(&Token::Colon) {
+            return Ok(Param::new(name));
+        }
+        let span = self.span();
+        let type_name = self.expect_ident()?;
+        match ParamType::from_name(&type_name) {
+            Some(ty) => Ok(Param { name, ty: Some(ty) }),
+            None => Err(ParseError::UnexpectedToken {
+                expected: "parameter type (instrument, number, duration, string, pitch, or array)"
+                    .into(),
+                found: Token::Ident(type_name),
+                span,
+            }),
+        }
+    }
+
     // ── Track Body ──────────────────────────────────────────
 
     fn parse_track_body(&mut self) -> Result<Vec<TrackStatement>, ParseError> {
@@ -205,16 +459,28 @@ impl Parser {
 
         while !self.check(&Token::RBrace) && !self.is_at_end() {
             let comments = self.skip_newlines_collecting_comments();
-            for c in comments {
-                stmts.push(TrackStatement::Comment(c));
+            for (text, span_start, span_end) in comments {
+                stmts.push(TrackStatement::Comment {
+                    text,
+                    span_start,
+                    span_end,
+                });
             }
             if self.check(&Token::RBrace) || self.is_at_end() {
                 break;
             }
-            stmts.push(self.parse_track_statement()?);
-            // Consume optional semicolons and newlines between statements
-            self.eat(&Token::Semicolon);
-            self.skip_newlines();
+            match self.parse_track_statement() {
+                Ok(stmt) => {
+                    stmts.push(stmt);
+                    // Consume optional semicolons and newlines between statements
+                    self.eat(&Token::Semicolon);
+                    self.skip_newlines();
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(true);
+                }
+            }
         }
         Ok(stmts)
     }
@@ -222,37 +488,54 @@ impl Parser {
     fn parse_track_statement(&mut self) -> Result<TrackStatement, ParseError> {
         match self.peek() {
             Token::Comment(text) => {
-                self.advance();
-                Ok(TrackStatement::Comment(text))
+                let span = self.advance().span;
+                Ok(TrackStatement::Comment {
+                    text,
+                    span_start: span.start,
+                    span_end: span.end,
+                })
             }
             Token::LBracket => self.parse_chord(),
+            Token::StringLit(_) => self.parse_chord_symbol(),
+            Token::Caret => self.parse_degree_note(),
             Token::Number(_) => {
                 // Standalone number = rest
-                let dur = self.parse_duration_expr()?;
-                Ok(TrackStatement::Rest(dur))
+                self.parse_rest()
             }
             Token::Track => {
                 // `track.property = value`
                 self.parse_track_body_assignment()
             }
             Token::For => self.parse_for_loop(),
+            Token::Repeat => self.parse_repeat(),
+            Token::If => self.parse_if(),
             Token::Ident(_) => self.parse_ident_statement_in_track(),
             Token::Dot => {
                 // Dot shorthand as a rest: `.` or `..`
-                let dur = self.parse_duration_expr()?;
-                Ok(TrackStatement::Rest(dur))
+                self.parse_rest()
             }
             _ => Err(ParseError::UnexpectedToken {
-                expected: "track statement (note, chord, rest, assignment, or for loop)".into(),
+                expected: "track statement (note, chord, rest, assignment, for, repeat, or if)".into(),
                 found: self.peek(),
                 span: self.span(),
             }),
         }
     }
 
+    fn parse_rest(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
+        let duration = self.parse_duration_expr()?;
+        Ok(TrackStatement::Rest {
+            duration,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
     // ── Ident-leading statement (note event or track call) ──
 
     fn parse_ident_statement(&mut self, _in_track: bool) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
         let name = self.expect_ident()?;
 
         // Check for assignment: `name.prop = value` or `name = value`
@@ -262,7 +545,12 @@ impl Parser {
             let target = self.parse_dotted_ident_rest(name)?;
             self.expect(&Token::Eq)?;
             let value = self.parse_expr()?;
-            return Ok(Statement::Assignment { target, value });
+            return Ok(Statement::Assignment {
+                target,
+                value,
+                span_start,
+                span_end: self.prev_end(),
+            });
         }
         if self.check(&Token::Eq) {
             self.advance();
@@ -270,11 +558,14 @@ impl Parser {
             return Ok(Statement::Assignment {
                 target: name,
                 value,
+                span_start,
+                span_end: self.prev_end(),
             });
         }
 
-        // Parse optional modifiers: *vel @dur
-        let (velocity, play_duration) = self.parse_modifiers()?;
+        // Parse optional transpose and modifiers: +semitones *vel @dur
+        let transpose = self.parse_transpose()?;
+        let (velocity, play_duration, looped) = self.parse_modifiers()?;
 
         if self.check(&Token::LParen) {
             // Track call
@@ -284,10 +575,14 @@ impl Parser {
             let step = self.try_parse_duration()?;
             Ok(Statement::TrackCall {
                 name,
+                transpose,
                 velocity,
                 play_duration,
+                looped,
                 args,
                 step,
+                span_start,
+                span_end: self.prev_end(),
             })
         } else {
             Err(ParseError::UnexpectedToken {
@@ -309,7 +604,12 @@ impl Parser {
             let target = self.parse_dotted_ident_rest(name)?;
             self.expect(&Token::Eq)?;
             let value = self.parse_expr()?;
-            return Ok(TrackStatement::Assignment { target, value });
+            return Ok(TrackStatement::Assignment {
+                target,
+                value,
+                span_start: start_span,
+                span_end: self.prev_end(),
+            });
         }
         if self.check(&Token::Eq) {
             self.advance();
@@ -317,11 +617,14 @@ impl Parser {
             return Ok(TrackStatement::Assignment {
                 target: name,
                 value,
+                span_start: start_span,
+                span_end: self.prev_end(),
             });
         }
 
-        // Parse optional modifiers: *vel @dur
-        let (velocity, play_duration) = self.parse_modifiers()?;
+        // Parse optional transpose and modifiers: +semitones *vel @dur
+        let transpose = self.parse_transpose()?;
+        let (velocity, play_duration, looped) = self.parse_modifiers()?;
 
         if self.check(&Token::LParen) {
             // Track call inside a track
@@ -331,17 +634,28 @@ impl Parser {
             let step = self.try_parse_duration()?;
             Ok(TrackStatement::TrackCall {
                 name,
+                transpose,
                 velocity,
                 play_duration,
+                looped,
                 args,
                 step,
+                span_start: start_span,
+                span_end: self.prev_end(),
+            })
+        } else if looped {
+            Err(ParseError::UnexpectedToken {
+                expected: "( for a looped track call".into(),
+                found: self.peek(),
+                span: self.span(),
             })
         } else {
             // Note event: pitch was `name`, parse optional step duration
             let step = self.try_parse_duration()?;
-            let end_span = self.tokens[self.pos.saturating_sub(1)].span.end;
+            let end_span = self.prev_end();
             Ok(TrackStatement::NoteEvent {
                 pitch: name,
+                transpose,
                 velocity,
                 audible_duration: play_duration,
                 step_duration: step,
@@ -364,29 +678,128 @@ impl Parser {
     // ── Assignment starting with `track` keyword ────────────
 
     fn parse_assignment_starting_with_track(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
         self.expect(&Token::Track)?; // consume `track`
         let target = self.parse_dotted_ident_rest("track".to_string())?;
         self.expect(&Token::Eq)?;
         let value = self.parse_expr()?;
-        Ok(Statement::Assignment { target, value })
+        Ok(Statement::Assignment {
+            target,
+            value,
+            span_start,
+            span_end: self.prev_end(),
+        })
     }
 
     fn parse_track_body_assignment(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
         self.expect(&Token::Track)?;
         let target = self.parse_dotted_ident_rest("track".to_string())?;
         self.expect(&Token::Eq)?;
         let value = self.parse_expr()?;
-        Ok(TrackStatement::Assignment { target, value })
+        Ok(TrackStatement::Assignment {
+            target,
+            value,
+            span_start,
+            span_end: self.prev_end(),
+        })
     }
 
     // ── Const Declaration ───────────────────────────────────
 
     fn parse_const_decl(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
         self.expect(&Token::Const)?;
         let name = self.expect_ident()?;
         self.expect(&Token::Eq)?;
         let value = self.parse_expr()?;
-        Ok(Statement::ConstDecl { name, value })
+        Ok(Statement::ConstDecl {
+            name,
+            value,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    /// `scale name = [steps];` — `scale` is only a keyword here.
+    fn parse_scale_decl(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
+        self.advance(); // consume `scale`
+        let name = self.expect_ident()?;
+        self.expect(&Token::Eq)?;
+        let value = self.parse_expr()?;
+        Ok(Statement::ScaleDecl {
+            name,
+            value,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    /// `groove name = {unit: 1/4, timing: [...], velocity: [...]};` —
+    /// `groove` is only a keyword here.
+    fn parse_groove_decl(&mut self) -> Result<Statement, ParseError> {
+        let span_start = self.span().start;
+        self.advance(); // consume `groove`
+        let name = self.expect_ident()?;
+        self.expect(&Token::Eq)?;
+        let value = self.parse_expr()?;
+        Ok(Statement::GrooveDecl {
+            name,
+            value,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    // ── Scale Degrees ───────────────────────────────────────
+
+    /// `^3`, `^-1` or `^name`, returned as written for the compiler to
+    /// resolve against `track.key`.
+    fn parse_degree(&mut self) -> Result<String, ParseError> {
+        self.expect(&Token::Caret)?;
+        let sign = if self.eat(&Token::Minus) { "-" } else { "" };
+        match self.peek() {
+            Token::Number(n) if n.fract() == 0.0 => {
+                self.advance();
+                Ok(format!("^{sign}{n}"))
+            }
+            Token::Ident(name) => {
+                self.advance();
+                Ok(format!("^{sign}{name}"))
+            }
+            other => Err(ParseError::UnexpectedToken {
+                expected: "scale degree (a whole number or a name)".into(),
+                found: other,
+                span: self.span(),
+            }),
+        }
+    }
+
+    /// A degree note: `^3+1*90 /4`.
+    fn parse_degree_note(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
+        let pitch = self.parse_degree()?;
+        let transpose = self.parse_transpose()?;
+        let tilde = self.span();
+        let (velocity, audible_duration, looped) = self.parse_modifiers()?;
+        if looped {
+            return Err(ParseError::UnexpectedToken {
+                expected: "note duration (only track calls can loop)".into(),
+                found: Token::Tilde,
+                span: tilde,
+            });
+        }
+        let step_duration = self.try_parse_duration()?;
+        Ok(TrackStatement::NoteEvent {
+            pitch,
+            transpose,
+            velocity,
+            audible_duration,
+            step_duration,
+            span_start,
+            span_end: self.prev_end(),
+        })
     }
 
     // ── Chord ───────────────────────────────────────────────
@@ -405,9 +818,17 @@ impl Parser {
         self.expect(&Token::RBracket)?;
 
         // Parse optional modifiers on the whole chord
-        let (_, audible_duration) = self.parse_modifiers()?;
+        let tilde = self.span();
+        let (_, audible_duration, looped) = self.parse_modifiers()?;
+        if looped {
+            return Err(ParseError::UnexpectedToken {
+                expected: "chord duration (only track calls can loop)".into(),
+                found: Token::Tilde,
+                span: tilde,
+            });
+        }
         let step_duration = self.try_parse_duration()?;
-        let end_span = self.tokens[self.pos.saturating_sub(1)].span.end;
+        let end_span = self.prev_end();
 
         Ok(TrackStatement::Chord {
             notes,
@@ -418,8 +839,40 @@ impl Parser {
         })
     }
 
+    /// A chord symbol: `'Cmaj7/E'*90@/8 /2`.
+    fn parse_chord_symbol(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
+        let Token::StringLit(symbol) = self.advance().token else {
+            unreachable!("parse_chord_symbol called without a string");
+        };
+        let tilde = self.span();
+        let (velocity, audible_duration, looped) = self.parse_modifiers()?;
+        if looped {
+            return Err(ParseError::UnexpectedToken {
+                expected: "chord duration (only track calls can loop)".into(),
+                found: Token::Tilde,
+                span: tilde,
+            });
+        }
+        let step_duration = self.try_parse_duration()?;
+        Ok(TrackStatement::ChordSymbol {
+            symbol,
+            velocity,
+            audible_duration,
+            step_duration,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
     fn parse_chord_note(&mut self) -> Result<ChordNote, ParseError> {
-        let pitch = self.expect_ident()?;
+        let span_start = self.span().start;
+        let pitch = if self.check(&Token::Caret) {
+            self.parse_degree()?
+        } else {
+            self.expect_ident()?
+        };
+        let transpose = self.parse_transpose()?;
         let audible_duration = if self.eat(&Token::At) {
             Some(self.parse_duration_expr()?)
         } else {
@@ -427,57 +880,314 @@ impl Parser {
         };
         Ok(ChordNote {
             pitch,
+            transpose,
             audible_duration,
+            span_start,
+            span_end: self.prev_end(),
         })
     }
 
     // ── For Loop ────────────────────────────────────────────
 
     fn parse_for_loop(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
         self.expect(&Token::For)?;
         self.expect(&Token::LParen)?;
 
-        // Collect three parts separated by semicolons (as opaque strings)
-        let init = self.collect_tokens_until(&Token::Semicolon)?;
+        self.eat(&Token::Let);
+        let variable = self.expect_ident()?;
+        self.expect(&Token::Eq)?;
+        let start = self.parse_condition(0)?;
         self.expect(&Token::Semicolon)?;
-        let condition = self.collect_tokens_until(&Token::Semicolon)?;
+        let condition = self.parse_condition(0)?;
         self.expect(&Token::Semicolon)?;
-        let update = self.collect_tokens_until(&Token::RParen)?;
+        let update = self.parse_loop_update(&variable)?;
         self.expect(&Token::RParen)?;
 
         self.skip_newlines();
         self.expect(&Token::LBrace)?;
         let body = self.parse_track_body()?;
-        self.expect(&Token::RBrace)?;
+        self.expect_block_end();
 
         Ok(TrackStatement::ForLoop {
-            init,
+            variable,
+            start,
             condition,
             update,
             body,
+            span_start,
+            span_end: self.prev_end(),
         })
     }
 
-    fn collect_tokens_until(&mut self, sentinel: &Token) -> Result<String, ParseError> {
-        let mut parts = Vec::new();
-        while !self.check(sentinel) && !self.is_at_end() {
-            let s = self.advance();
-            parts.push(token_to_string(&s.token));
+    /// `i++`, `i--`, `i += n`, `i -= n` or `i = n`, for the loop variable.
+    fn parse_loop_update(&mut self, variable: &str) -> Result<LoopUpdate, ParseError> {
+        let span = self.span();
+        match self.peek() {
+            Token::Ident(name) if name == variable => {
+                self.advance();
+            }
+            found => {
+                return Err(ParseError::UnexpectedToken {
+                    expected: format!("loop variable '{variable}'"),
+                    found,
+                    span,
+                });
+            }
+        }
+        match self.peek() {
+            Token::PlusPlus => {
+                self.advance();
+                Ok(LoopUpdate::Increment)
+            }
+            Token::MinusMinus => {
+                self.advance();
+                Ok(LoopUpdate::Decrement)
+            }
+            Token::Plus => {
+                self.advance();
+                self.expect(&Token::Eq)?;
+                Ok(LoopUpdate::Add(self.parse_condition(0)?))
+            }
+            Token::Minus => {
+                self.advance();
+                self.expect(&Token::Eq)?;
+                Ok(LoopUpdate::Sub(self.parse_condition(0)?))
+            }
+            Token::Eq => {
+                self.advance();
+                Ok(LoopUpdate::Set(self.parse_condition(0)?))
+            }
+            found => Err(ParseError::UnexpectedToken {
+                expected: "++, --, +=, -= or =".into(),
+                found,
+                span: self.span(),
+            }),
+        }
+    }
+
+    // ── Conditionals ────────────────────────────────────────
+
+    /// `if (cond) { ... } else if (cond) { ... } else { ... }`
+    fn parse_if(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
+        self.expect(&Token::If)?;
+        self.expect(&Token::LParen)?;
+        let condition = self.parse_condition(0)?;
+        self.expect(&Token::RParen)?;
+        self.skip_newlines();
+        self.expect(&Token::LBrace)?;
+        let mut then_body = self.parse_track_body()?;
+        self.expect_block_end();
+
+        // `else` may start on a later line; comments before it stay with
+        // the block they follow.
+        let else_body = if let Some(comments) = self.continues_with(&Token::Else) {
+            then_body.extend(comments);
+            self.advance();
+            self.skip_newlines();
+            if self.check(&Token::If) {
+                vec![self.parse_if()?]
+            } else {
+                self.expect(&Token::LBrace)?;
+                let body = self.parse_track_body()?;
+                self.expect_block_end();
+                body
+            }
+        } else {
+            Vec::new()
+        };
+
+        Ok(TrackStatement::If {
+            condition,
+            then_body,
+            else_body,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    /// Parse a condition whose operators bind tighter than `min_precedence`.
+    fn parse_condition(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let span_start = self.span().start;
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = binary_op(&self.peek())
+            && op.precedence() > min_precedence
+        {
+            self.advance();
+            let rhs = self.parse_condition(op.precedence())?;
+            lhs = Expr::new(
+                ExprKind::Binary {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+                span_start,
+                self.prev_end(),
+            );
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let span_start = self.span().start;
+        let op = match self.peek() {
+            Token::Bang => UnaryOp::Not,
+            Token::Minus => UnaryOp::Neg,
+            Token::LParen => {
+                self.advance();
+                let inner = self.parse_condition(0)?;
+                self.expect(&Token::RParen)?;
+                return Ok(inner);
+            }
+            _ => return self.parse_expr(),
+        };
+        self.advance();
+        let operand = self.parse_unary()?;
+        Ok(Expr::new(
+            ExprKind::Unary {
+                op,
+                operand: Box::new(operand),
+            },
+            span_start,
+            self.prev_end(),
+        ))
+    }
+
+    // ── Repeat ──────────────────────────────────────────────
+
+    /// `repeat N [as name] { body }` followed by any `ending K, ... { body }`.
+    fn parse_repeat(&mut self) -> Result<TrackStatement, ParseError> {
+        let span_start = self.span().start;
+        self.expect(&Token::Repeat)?;
+        let count = self.expect_pass_number("repeat count (a whole number of at least 1)")?;
+        let index = if self.peek() == Token::Ident("as".into()) {
+            self.advance();
+            Some(self.expect_ident()?)
+        } else {
+            None
+        };
+
+        self.skip_newlines();
+        self.expect(&Token::LBrace)?;
+        let mut body = self.parse_track_body()?;
+        self.expect_block_end();
+
+        let mut endings: Vec<Ending> = Vec::new();
+        // An ending may start on a later line; comments before it stay
+        // with the block they follow.
+        while let Some(comments) = self.continues_with(&Token::Ending) {
+            match endings.last_mut() {
+                Some(ending) => ending.body.extend(comments),
+                None => body.extend(comments),
+            }
+            let ending_start = self.span().start;
+            self.advance();
+            let mut passes = vec![self.expect_pass_number("ending pass number")?];
+            while self.eat(&Token::Comma) {
+                passes.push(self.expect_pass_number("ending pass number")?);
+            }
+            self.skip_newlines();
+            self.expect(&Token::LBrace)?;
+            let body = self.parse_track_body()?;
+            self.expect_block_end();
+            endings.push(Ending {
+                passes,
+                body,
+                span_start: ending_start,
+                span_end: self.prev_end(),
+            });
+        }
+
+        Ok(TrackStatement::Repeat {
+            count,
+            index,
+            body,
+            endings,
+            span_start,
+            span_end: self.prev_end(),
+        })
+    }
+
+    /// Expect a whole number of at least 1.
+    fn expect_pass_number(&mut self, expected: &str) -> Result<u32, ParseError> {
+        let span = self.span();
+        let n = self.expect_number()?;
+        if n >= 1.0 && n.fract() == 0.0 && n <= u32::MAX as f64 {
+            Ok(n as u32)
+        } else {
+            Err(ParseError::UnexpectedToken {
+                expected: expected.into(),
+                found: Token::Number(n),
+                span,
+            })
         }
-        Ok(parts.join(" "))
     }
 
     // ── Modifiers ───────────────────────────────────────────
 
-    /// Parse optional `*velocity` and `@duration` modifiers.
-    fn parse_modifiers(&mut self) -> Result<(Option<f64>, Option<DurationExpr>), ParseError> {
+    /// Parse an optional `+semitones` or `-semitones` after a pitch or
+    /// track name: a whole number, a name, or a parenthesized expression.
+    /// A `-` amount is returned negated.
+    fn parse_transpose(&mut self) -> Result<Option<Expr>, ParseError> {
+        let span_start = self.span().start;
+        let negate = match self.peek() {
+            Token::Plus => false,
+            Token::Minus => true,
+            _ => return Ok(None),
+        };
+        self.advance();
+        let amount_start = self.span().start;
+        let amount = match self.peek() {
+            Token::Number(n) => {
+                self.advance();
+                Expr::new(ExprKind::Number(n), amount_start, self.prev_end())
+            }
+            Token::Ident(name) => {
+                self.advance();
+                Expr::new(ExprKind::Identifier(name), amount_start, self.prev_end())
+            }
+            Token::LParen => {
+                self.advance();
+                let inner = self.parse_condition(0)?;
+                self.expect(&Token::RParen)?;
+                inner
+            }
+            other => {
+                return Err(ParseError::UnexpectedToken {
+                    expected: "semitones (a number, a name, or a parenthesized expression)".into(),
+                    found: other,
+                    span: self.span(),
+                });
+            }
+        };
+        if !negate {
+            return Ok(Some(amount));
+        }
+        Ok(Some(Expr::new(
+            ExprKind::Unary {
+                op: UnaryOp::Neg,
+                operand: Box::new(amount),
+            },
+            span_start,
+            self.prev_end(),
+        )))
+    }
+
+    /// Parse optional `*velocity` and `@duration` modifiers. `@~duration`
+    /// (looped) is returned as the third element.
+    fn parse_modifiers(
+        &mut self,
+    ) -> Result<(Option<f64>, Option<DurationExpr>, bool), ParseError> {
         let velocity = if self.eat(&Token::Star) {
             Some(self.expect_number()?)
         } else {
             None
         };
 
+        let mut looped = false;
         let duration = if self.eat(&Token::At) {
+            looped = self.eat(&Token::Tilde);
             // After @, parse a simple duration (no greedy fractions).
             // `@1/4` is uncommon; use `@/4` for inverse or `@4` for beats.
             Some(self.parse_simple_duration()?)
@@ -485,7 +1195,7 @@ impl Parser {
             None
         };
 
-        Ok((velocity, duration))
+        Ok((velocity, duration, looped))
     }
 
     /// Parse a simple duration: `/N` or `N` (no fraction form).
@@ -582,6 +1292,28 @@ impl Parser {
     }
 
     fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        let span_start = self.span().start;
+        let kind = self.parse_expr_kind()?;
+        let mut expr = Expr::new(kind, span_start, self.prev_end());
+        // `list[i]` — only with the bracket right after the list, so a
+        // chord can follow an expression on the same line.
+        while self.check(&Token::LBracket) && self.span().start == self.prev_end() {
+            self.advance();
+            let index = self.parse_condition(0)?;
+            self.expect(&Token::RBracket)?;
+            expr = Expr::new(
+                ExprKind::Index {
+                    target: Box::new(expr),
+                    index: Box::new(index),
+                },
+                span_start,
+                self.prev_end(),
+            );
+        }
+        Ok(expr)
+    }
+
+    fn parse_expr_kind(&mut self) -> Result<ExprKind, ParseError> {
         match self.peek() {
             Token::Number(n) => {
                 self.advance();
@@ -591,22 +1323,31 @@ impl Parser {
                     self.advance();
                     if let Token::Number(m) = self.peek() {
                         self.advance();
-                        Ok(Expr::DurationLit(DurationExpr::Fraction(n, m)))
+                        Ok(ExprKind::DurationLit(DurationExpr::Fraction(n, m)))
                     } else {
                         self.pos = saved;
-                        Ok(Expr::Number(n))
+                        Ok(ExprKind::Number(n))
                     }
                 } else {
-                    Ok(Expr::Number(n))
+                    Ok(ExprKind::Number(n))
                 }
             }
+            Token::Minus if matches!(self.peek_at(1), Token::Number(_)) => {
+                // Negative number, e.g. `track.transpose = -12`
+                self.advance();
+                let Token::Number(n) = self.advance().token else {
+                    unreachable!()
+                };
+                Ok(ExprKind::Number(-n))
+            }
             Token::StringLit(s) => {
                 self.advance();
-                Ok(Expr::StringLit(s))
+                Ok(ExprKind::StringLit(s))
             }
+            Token::Caret => Ok(ExprKind::Identifier(self.parse_degree()?)),
             Token::RegexLit(s) => {
                 self.advance();
-                Ok(Expr::RegexLit(s))
+                Ok(ExprKind::RegexLit(s))
             }
             Token::Ident(name) => {
                 self.advance();
@@ -615,18 +1356,18 @@ impl Parser {
                     self.advance(); // consume (
                     let args = self.parse_call_args()?;
                     self.expect(&Token::RParen)?;
-                    Ok(Expr::FunctionCall {
+                    Ok(ExprKind::FunctionCall {
                         function: name,
                         args,
                     })
                 } else if self.check(&Token::Dot) {
                     let target = self.parse_dotted_ident_rest(name.clone())?;
-                    Ok(Expr::PropertyAccess {
+                    Ok(ExprKind::PropertyAccess {
                         object: name,
                         property: target,
                     })
                 } else {
-                    Ok(Expr::Identifier(name))
+                    Ok(ExprKind::Identifier(name))
                 }
             }
             Token::LBracket => self.parse_array_expr(),
@@ -639,7 +1380,7 @@ impl Parser {
         }
     }
 
-    fn parse_array_expr(&mut self) -> Result<Expr, ParseError> {
+    fn parse_array_expr(&mut self) -> Result<ExprKind, ParseError> {
         self.expect(&Token::LBracket)?;
         let mut items = Vec::new();
         if !self.check(&Token::RBracket) {
@@ -649,10 +1390,10 @@ impl Parser {
             }
         }
         self.expect(&Token::RBracket)?;
-        Ok(Expr::Array(items))
+        Ok(ExprKind::Array(items))
     }
 
-    fn parse_object_expr(&mut self) -> Result<Expr, ParseError> {
+    fn parse_object_expr(&mut self) -> Result<ExprKind, ParseError> {
         self.expect(&Token::LBrace)?;
         let mut props = Vec::new();
         if !self.check(&Token::RBrace) {
@@ -665,7 +1406,7 @@ impl Parser {
             }
         }
         self.expect(&Token::RBrace)?;
-        Ok(Expr::ObjectLit(props))
+        Ok(ExprKind::ObjectLit(props))
     }
 
     fn parse_obj_prop(&mut self) -> Result<(String, Expr), ParseError> {
@@ -688,6 +1429,26 @@ impl Parser {
     }
 }
 
+/// The binary operator a token stands for in a condition.
+fn binary_op(token: &Token) -> Option<BinaryOp> {
+    Some(match token {
+        Token::OrOr => BinaryOp::Or,
+        Token::AndAnd => BinaryOp::And,
+        Token::EqEq => BinaryOp::Eq,
+        Token::NotEq => BinaryOp::Ne,
+        Token::Lt => BinaryOp::Lt,
+        Token::LtEq => BinaryOp::Le,
+        Token::Gt => BinaryOp::Gt,
+        Token::GtEq => BinaryOp::Ge,
+        Token::Plus => BinaryOp::Add,
+        Token::Minus => BinaryOp::Sub,
+        Token::Star => BinaryOp::Mul,
+        Token::Slash => BinaryOp::Div,
+        Token::Percent => BinaryOp::Rem,
+        _ => return None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -713,9 +1474,11 @@ track riff(inst) {
 
         assert_eq!(program.statements.len(), 1);
         match &program.statements[0] {
-            Statement::TrackDef { name, params, body } => {
+            Statement::TrackDef {
+                name, params, body, ..
+            } => {
                 assert_eq!(name, "riff");
-                assert_eq!(params, &["inst"]);
+                assert_eq!(params, &[Param::new("inst")]);
                 // Filter out comments
                 let notes: Vec<_> = body
                     .iter()
@@ -780,6 +1543,7 @@ track t() {
                 play_duration,
                 args,
                 step,
+                ..
             } => {
                 assert_eq!(name, "drums");
                 assert_eq!(*velocity, Some(96.0));
@@ -795,10 +1559,10 @@ track t() {
     fn test_parse_const_decl() {
         let program = parse(r#"const lead = loadPreset("Guitar");"#).unwrap();
         match &program.statements[0] {
-            Statement::ConstDecl { name, value } => {
+            Statement::ConstDecl { name, value, .. } => {
                 assert_eq!(name, "lead");
-                match value {
-                    Expr::FunctionCall { function, args } => {
+                match &value.kind {
+                    ExprKind::FunctionCall { function, args } => {
                         assert_eq!(function, "loadPreset");
                         assert_eq!(args.len(), 1);
                     }
@@ -813,10 +1577,10 @@ track t() {
     fn test_parse_assignment() {
         let program = parse("track.beatsPerMinute = 160;").unwrap();
         match &program.statements[0] {
-            Statement::Assignment { target, value } => {
+            Statement::Assignment { target, value, .. } => {
                 assert_eq!(target, "track.beatsPerMinute");
-                match value {
-                    Expr::Number(n) => assert_eq!(*n, 160.0),
+                match &value.kind {
+                    ExprKind::Number(n) => assert_eq!(*n, 160.0),
                     other => panic!("Expected Number, got {other:?}"),
                 }
             }
@@ -873,7 +1637,7 @@ track t() {
         match &program.statements[0] {
             Statement::TrackDef { body, .. } => {
                 assert!(matches!(&body[0], TrackStatement::NoteEvent { pitch, .. } if pitch == "C3"));
-                assert!(matches!(&body[1], TrackStatement::Rest(DurationExpr::Beats(n)) if *n == 4.0));
+                assert!(matches!(&body[1], TrackStatement::Rest { duration: DurationExpr::Beats(n), .. } if *n == 4.0));
                 assert!(matches!(&body[2], TrackStatement::NoteEvent { pitch, .. } if pitch == "D3"));
             }
             other => panic!("Expected TrackDef, got {other:?}"),
@@ -897,14 +1661,17 @@ track t() {
         match &program.statements[0] {
             Statement::TrackDef { body, .. } => match &body[0] {
                 TrackStatement::ForLoop {
-                    init,
+                    variable,
+                    start,
                     condition,
                     update,
                     body,
+                    ..
                 } => {
-                    assert!(init.contains("let"));
-                    assert!(condition.contains("<"));
-                    assert!(update.contains("++"));
+                    assert_eq!(variable, "i");
+                    assert!(matches!(start.kind, ExprKind::Number(n) if n == 0.0));
+                    assert!(matches!(&condition.kind, ExprKind::Binary { op: BinaryOp::Lt, .. }));
+                    assert!(matches!(update, LoopUpdate::Increment));
                     let notes: Vec<_> = body
                         .iter()
                         .filter(|s| matches!(s, TrackStatement::NoteEvent { .. }))
@@ -917,6 +1684,378 @@ track t() {
         }
     }
 
+    #[test]
+    fn test_parse_for_loop_errors() {
+        let src = "track t() {\n    for (let i = 0; i < ; i++) { C4 }\n}";
+        match &parse_recovering(src).1[..] {
+            [ParseError::UnexpectedToken { found: Token::Semicolon, span, .. }, ..] => {
+                assert_eq!(span.start, src.find("; i++").unwrap());
+            }
+            other => panic!("Expected an error at the second ';', got {other:?}"),
+        }
+
+        let src = "track t() {\n    for (let i = 0; i < 1; j++) { C4 }\n}";
+        match &parse_recovering(src).1[..] {
+            [ParseError::UnexpectedToken { expected, span, .. }, ..] => {
+                assert_eq!(expected, "loop variable 'i'");
+                assert_eq!(span.start, src.find("j++").unwrap());
+            }
+            other => panic!("Expected an error at 'j', got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_if_else_chain() {
+        let program = parse(
+            r#"
+track t(fill) {
+    if (fill) {
+        C3 /4
+    }
+    else if (pass % 2 == 0 && !fill) { D3 /4 } else {
+        E3 /4
+    }
+    if (x) { F3 /4 }
+    G3 /4
+}
+"#,
+        )
+        .unwrap();
+
+        let Statement::TrackDef { body, .. } = &program.statements[0] else {
+            panic!("Expected TrackDef");
+        };
+        match &body[0] {
+            TrackStatement::If {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                assert!(matches!(&condition.kind, ExprKind::Identifier(n) if n == "fill"));
+                assert_eq!(then_body.len(), 1);
+                match &else_body[..] {
+                    [TrackStatement::If {
+                        condition,
+                        else_body,
+                        ..
+                    }] => {
+                        // `&&` binds looser than `==`, which binds looser than `%`.
+                        let ExprKind::Binary { op, lhs, rhs } = &condition.kind else {
+                            panic!("Expected Binary, got {condition:?}");
+                        };
+                        assert_eq!(*op, BinaryOp::And);
+                        assert!(matches!(&lhs.kind, ExprKind::Binary { op: BinaryOp::Eq, lhs, .. }
+                            if matches!(lhs.kind, ExprKind::Binary { op: BinaryOp::Rem, .. })));
+                        assert!(matches!(&rhs.kind, ExprKind::Unary { op: UnaryOp::Not, .. }));
+                        assert_eq!(else_body.len(), 1);
+                    }
+                    other => panic!("Expected else if, got {other:?}"),
+                }
+            }
+            other => panic!("Expected If, got {other:?}"),
+        }
+        assert!(matches!(&body[1], TrackStatement::If { else_body, .. } if else_body.is_empty()));
+        assert!(matches!(&body[2], TrackStatement::NoteEvent { pitch, .. } if pitch == "G3"));
+    }
+
+    #[test]
+    fn test_parse_repeat_with_endings() {
+        let program = parse(
+            r#"
+track t() {
+    repeat 4 as pass {
+        C3 /4
+    } ending 1, 2 {
+        D3 /4
+    }
+
+    ending 3 { E3 /4 }
+    F3 /4
+}
+"#,
+        )
+        .unwrap();
+
+        match &program.statements[0] {
+            Statement::TrackDef { body, .. } => {
+                match &body[0] {
+                    TrackStatement::Repeat {
+                        count,
+                        index,
+                        body,
+                        endings,
+                        ..
+                    } => {
+                        assert_eq!(*count, 4);
+                        assert_eq!(index.as_deref(), Some("pass"));
+                        assert_eq!(body.len(), 1);
+                        assert_eq!(endings.len(), 2);
+                        assert_eq!(endings[0].passes, [1, 2]);
+                        assert_eq!(endings[1].passes, [3]);
+                    }
+                    other => panic!("Expected Repeat, got {other:?}"),
+                }
+                assert!(matches!(&body[1], TrackStatement::NoteEvent { pitch, .. } if pitch == "F3"));
+            }
+            other => panic!("Expected TrackDef, got {other:?}"),
+        }
+
+        assert!(parse("track t() {\n    repeat 0 { C3 }\n}").is_err());
+        assert!(parse("track t() {\n    repeat 2 { C3 } ending 1.5 { D3 }\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_comments_before_else_and_ending() {
+        let program = parse(
+            r#"
+track t(x) {
+    if (x) { C4 /4 } // first
+    // second
+    else { D4 /4 }
+    repeat 2 { E4 /4 }
+    // before ending
+    ending 1 { F4 /4 } // after ending
+    ending 2 { G4 /4 }
+}
+"#,
+        )
+        .unwrap();
+
+        let Statement::TrackDef { body, .. } = &program.statements[0] else {
+            panic!("Expected TrackDef");
+        };
+        let comments = |stmts: &[TrackStatement]| -> Vec<String> {
+            stmts
+                .iter()
+                .filter_map(|s| match s {
+                    TrackStatement::Comment { text, .. } => Some(text.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        match &body[0] {
+            TrackStatement::If { then_body, else_body, .. } => {
+                assert_eq!(comments(then_body), ["first", "second"]);
+                assert_eq!(else_body.len(), 1);
+            }
+            other => panic!("Expected If, got {other:?}"),
+        }
+        match &body[1] {
+            TrackStatement::Repeat { body, endings, .. } => {
+                assert_eq!(comments(body), ["before ending"]);
+                assert_eq!(endings.len(), 2);
+                assert_eq!(comments(&endings[0].body), ["after ending"]);
+            }
+            other => panic!("Expected Repeat, got {other:?}"),
+        }
+        assert_eq!(body.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_looped_call() {
+        let program = parse("track t() {\n    beat@~16()\n}\nbeat*90@~/2(); beat@2();").unwrap();
+        match &program.statements[0] {
+            Statement::TrackDef { body, .. } => assert!(matches!(
+                &body[0],
+                TrackStatement::TrackCall { looped: true, play_duration: Some(DurationExpr::Beats(n)), .. } if *n == 16.0
+            )),
+            other => panic!("Expected TrackDef, got {other:?}"),
+        }
+        assert!(matches!(&program.statements[1], Statement::TrackCall { looped: true, velocity: Some(_), .. }));
+        assert!(matches!(&program.statements[2], Statement::TrackCall { looped: false, .. }));
+
+        // Only track calls can loop.
+        assert!(parse("track t() {\n    C4@~2 /4\n}").is_err());
+        assert!(parse("track t() {\n    [C4, E4]@~2 /4\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_transpose() {
+        let program = parse("track t() {\n    C4+7*90 /4\n    [C4, root-(n * 2)]\n}\nt-12@4();").unwrap();
+        let Statement::TrackDef { body, .. } = &program.statements[0] else {
+            panic!("Expected TrackDef");
+        };
+        match &body[0] {
+            TrackStatement::NoteEvent {
+                transpose: Some(t),
+                velocity: Some(v),
+                ..
+            } => {
+                assert!(matches!(t.kind, ExprKind::Number(n) if n == 7.0));
+                assert_eq!(*v, 90.0);
+            }
+            other => panic!("Expected NoteEvent, got {other:?}"),
+        }
+        match &body[1] {
+            TrackStatement::Chord { notes, .. } => {
+                assert!(notes[0].transpose.is_none());
+                let Some(Expr {
+                    kind: ExprKind::Unary { op: UnaryOp::Neg, operand },
+                    ..
+                }) = &notes[1].transpose
+                else {
+                    panic!("Expected a negated interval, got {:?}", notes[1].transpose);
+                };
+                assert!(matches!(operand.kind, ExprKind::Binary { op: BinaryOp::Mul, .. }));
+            }
+            other => panic!("Expected Chord, got {other:?}"),
+        }
+        assert!(matches!(
+            &program.statements[1],
+            Statement::TrackCall { transpose: Some(_), play_duration: Some(_), .. }
+        ));
+
+        assert!(parse("track t() {\n    C4+ /4\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_degrees_and_scales() {
+        let program = parse("scale hijaz = [0, 1, 4];\ntrack t() {\n    ^3+1*90 /4\n    [^1, ^-n]\n}\nscale(^2);").unwrap();
+        assert!(matches!(&program.statements[0], Statement::ScaleDecl { name, .. } if name == "hijaz"));
+        let Statement::TrackDef { body, .. } = &program.statements[1] else {
+            panic!("Expected TrackDef");
+        };
+        assert!(matches!(
+            &body[0],
+            TrackStatement::NoteEvent { pitch, transpose: Some(_), velocity: Some(_), .. } if pitch == "^3"
+        ));
+        match &body[1] {
+            TrackStatement::Chord { notes, .. } => {
+                let pitches: Vec<&str> = notes.iter().map(|n| n.pitch.as_str()).collect();
+                assert_eq!(pitches, ["^1", "^-n"]);
+            }
+            other => panic!("Expected Chord, got {other:?}"),
+        }
+        // `scale` is only a keyword before a name.
+        match &program.statements[2] {
+            Statement::TrackCall { name, args, .. } => {
+                assert_eq!(name, "scale");
+                assert!(matches!(&args[0].kind, ExprKind::Identifier(d) if d == "^2"));
+            }
+            other => panic!("Expected TrackCall, got {other:?}"),
+        }
+
+        assert!(parse("track t() {\n    ^1.5\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_chord_symbols() {
+        let program = parse("track t() {\n    'Cmaj7/E' /2\n    'F#m7'*80@/8 /4\n    F#4\n}").unwrap();
+        let Statement::TrackDef { body, .. } = &program.statements[0] else {
+            panic!("Expected TrackDef");
+        };
+        assert!(matches!(
+            &body[0],
+            TrackStatement::ChordSymbol { symbol, velocity: None, step_duration: Some(_), .. } if symbol == "Cmaj7/E"
+        ));
+        assert!(matches!(
+            &body[1],
+            TrackStatement::ChordSymbol { symbol, velocity: Some(_), audible_duration: Some(_), .. } if symbol == "F#m7"
+        ));
+        assert!(matches!(&body[2], TrackStatement::NoteEvent { pitch, .. } if pitch == "F#4"));
+
+        assert!(parse("track t() {\n    'C'@~4\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_groove_decl() {
+        let program = parse("groove mpc = {unit: 1/4, timing: [0, 0.1]};\ngroove(2);").unwrap();
+        match &program.statements[0] {
+            Statement::GrooveDecl { name, value, .. } => {
+                assert_eq!(name, "mpc");
+                assert!(matches!(&value.kind, ExprKind::ObjectLit(pairs) if pairs.len() == 2));
+            }
+            other => panic!("Expected GrooveDecl, got {other:?}"),
+        }
+        assert!(matches!(&program.statements[1], Statement::TrackCall { name, .. } if name == "groove"));
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let program = parse("t(hits[i - 1], euclid(3, 8)[2]);\ntrack t() {\n    track.transpose = n [C4, E4]\n}").unwrap();
+        match &program.statements[0] {
+            Statement::TrackCall { args, .. } => {
+                assert!(matches!(&args[0].kind, ExprKind::Index { index, .. }
+                    if matches!(index.kind, ExprKind::Binary { .. })));
+                assert!(matches!(&args[1].kind, ExprKind::Index { target, .. }
+                    if matches!(target.kind, ExprKind::FunctionCall { .. })));
+            }
+            other => panic!("Expected TrackCall, got {other:?}"),
+        }
+        // A spaced bracket starts a chord instead.
+        let Statement::TrackDef { body, .. } = &program.statements[1] else {
+            panic!("Expected TrackDef");
+        };
+        assert!(matches!(&body[1], TrackStatement::Chord { .. }));
+    }
+
+    #[test]
+    fn test_parse_typed_params() {
+        let program = parse("track riff(inst, root: pitch, len: duration) {\n    root\n}").unwrap();
+        match &program.statements[0] {
+            Statement::TrackDef { params, .. } => assert_eq!(
+                params,
+                &[
+                    Param::new("inst"),
+                    Param { name: "root".into(), ty: Some(ParamType::Pitch) },
+                    Param { name: "len".into(), ty: Some(ParamType::Duration) },
+                ]
+            ),
+            other => panic!("Expected TrackDef, got {other:?}"),
+        }
+
+        assert!(parse("track riff(root: chord) {\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_parallel_and_at() {
+        let program = parse(
+            r#"
+parallel {
+    drums();
+    // bass line
+    bass() 4
+}
+lead() & pad()
+at 16: chorus();
+"#,
+        )
+        .unwrap();
+
+        match &program.statements[0] {
+            Statement::Parallel { body, joined, .. } => {
+                assert!(!joined);
+                assert_eq!(body.len(), 3);
+                assert!(matches!(&body[1], Statement::Comment { .. }));
+                assert!(matches!(&body[2], Statement::TrackCall { name, step: Some(_), .. } if name == "bass"));
+            }
+            other => panic!("Expected Parallel, got {other:?}"),
+        }
+        match &program.statements[1] {
+            Statement::Parallel { body, joined, .. } => {
+                assert!(joined);
+                assert!(matches!(&body[1], Statement::TrackCall { name, .. } if name == "pad"));
+            }
+            other => panic!("Expected Parallel, got {other:?}"),
+        }
+        match &program.statements[2] {
+            Statement::At { beat, body, .. } => {
+                assert!(matches!(beat, DurationExpr::Beats(n) if *n == 16.0));
+                assert!(matches!(&**body, Statement::TrackCall { name, .. } if name == "chorus"));
+            }
+            other => panic!("Expected At, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parallel_rejects_non_calls() {
+        assert!(parse("parallel { track.noteLength = 1/4; }").is_err());
+        assert!(parse("parallel { const x = 1; }").is_err());
+        assert!(parse("at 4: x = 1;").is_err());
+        assert!(parse("a() & b = 1;").is_err());
+    }
+
     #[test]
     fn test_parse_fraction_duration() {
         let program = parse(
@@ -930,10 +2069,10 @@ track t() {
 
         match &program.statements[0] {
             Statement::TrackDef { body, .. } => match &body[0] {
-                TrackStatement::Assignment { target, value } => {
+                TrackStatement::Assignment { target, value, .. } => {
                     assert_eq!(target, "track.duration");
-                    match value {
-                        Expr::DurationLit(DurationExpr::Fraction(n, m)) => {
+                    match &value.kind {
+                        ExprKind::DurationLit(DurationExpr::Fraction(n, m)) => {
                             assert_eq!(*n, 1.0);
                             assert_eq!(*m, 4.0);
                         }
@@ -1002,8 +2141,173 @@ track riff(instrument) {
         let non_comment: Vec<_> = program
             .statements
             .iter()
-            .filter(|s| !matches!(s, Statement::Comment(_)))
+            .filter(|s| !matches!(s, Statement::Comment { .. }))
             .collect();
         assert_eq!(non_comment.len(), 5);
     }
+
+    fn parse_recovering(input: &str) -> (Program, Vec<ParseError>) {
+        let tokens = Lexer::new(input).tokenize().unwrap();
+        Parser::new(tokens).parse_program_recovering()
+    }
+
+    #[test]
+    fn test_recover_bad_line_in_track_body() {
+        let (program, errors) = parse_recovering(
+            r#"
+track riff() {
+    C3 /4
+    D3 / )
+    E3 /4
+}
+riff();
+"#,
+        );
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.statements.len(), 2);
+        match &program.statements[0] {
+            Statement::TrackDef { body, .. } => {
+                let pitches: Vec<_> = body
+                    .iter()
+                    .filter_map(|s| match s {
+                        TrackStatement::NoteEvent { pitch, .. } => Some(pitch.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(pitches, ["C3", "E3"]);
+            }
+            other => panic!("Expected TrackDef, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_recover_bad_top_level_statement() {
+        let (program, errors) = parse_recovering(
+            r#"
+track.beatsPerMinute = ;
+riff();
+= 3
+track riff() {
+    C3 /4
+}
+"#,
+        );
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&program.statements[0], Statement::TrackCall { name, .. } if name == "riff"));
+        assert!(matches!(&program.statements[1], Statement::TrackDef { name, .. } if name == "riff"));
+    }
+
+    #[test]
+    fn test_recover_skips_broken_track_header() {
+        let (program, errors) = parse_recovering(
+            r#"
+track broken( {
+    C3 /4
+}
+track ok() {
+    D3 /4
+}
+"#,
+        );
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.statements.len(), 1);
+        assert!(matches!(&program.statements[0], Statement::TrackDef { name, .. } if name == "ok"));
+    }
+
+    #[test]
+    fn test_recover_missing_closing_brace_keeps_track() {
+        let (program, errors) = parse_recovering(
+            r#"
+track riff() {
+    C3 /4
+    for (let i = 0; i < 2; i ++) {
+        D3 /8
+"#,
+        );
+
+        assert_eq!(errors.len(), 2);
+        match &program.statements[0] {
+            Statement::TrackDef { body, .. } => {
+                assert!(matches!(&body[0], TrackStatement::NoteEvent { pitch, .. } if pitch == "C3"));
+                assert!(matches!(&body[1], TrackStatement::ForLoop { body, .. } if body.len() == 1));
+            }
+            other => panic!("Expected TrackDef, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_program_reports_first_error() {
+        let err = parse("riff(;\n= 3").unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("pos 5"), "unexpected error: {msg}");
+    }
+
+    #[test]
+    fn test_spans_on_statements_and_exprs() {
+        let src = "const lead = Oscillator({type: 'square'});\nriff(lead) 4;\ntrack riff(inst) {\n    track.instrument = inst;\n    5\n}";
+        let program = parse(src).unwrap();
+        let text = |start: usize, end: usize| &src[start..end];
+
+        match &program.statements[0] {
+            Statement::ConstDecl {
+                value,
+                span_start,
+                span_end,
+                ..
+            } => {
+                assert_eq!(text(*span_start, *span_end), "const lead = Oscillator({type: 'square'})");
+                assert_eq!(text(value.span_start, value.span_end), "Oscillator({type: 'square'})");
+                match &value.kind {
+                    ExprKind::FunctionCall { args, .. } => {
+                        assert_eq!(text(args[0].span_start, args[0].span_end), "{type: 'square'}");
+                    }
+                    other => panic!("Expected FunctionCall, got {other:?}"),
+                }
+            }
+            other => panic!("Expected ConstDecl, got {other:?}"),
+        }
+        match &program.statements[1] {
+            Statement::TrackCall {
+                args,
+                span_start,
+                span_end,
+                ..
+            } => {
+                assert_eq!(text(*span_start, *span_end), "riff(lead) 4");
+                assert_eq!(text(args[0].span_start, args[0].span_end), "lead");
+            }
+            other => panic!("Expected TrackCall, got {other:?}"),
+        }
+        match &program.statements[2] {
+            Statement::TrackDef {
+                body,
+                span_start,
+                span_end,
+                ..
+            } => {
+                assert!(text(*span_start, *span_end).starts_with("track riff(inst) {"));
+                assert!(text(*span_start, *span_end).ends_with('}'));
+                match &body[0] {
+                    TrackStatement::Assignment {
+                        span_start,
+                        span_end,
+                        ..
+                    } => assert_eq!(text(*span_start, *span_end), "track.instrument = inst"),
+                    other => panic!("Expected Assignment, got {other:?}"),
+                }
+                match &body[1] {
+                    TrackStatement::Rest {
+                        span_start,
+                        span_end,
+                        ..
+                    } => assert_eq!(text(*span_start, *span_end), "5"),
+                    other => panic!("Expected Rest, got {other:?}"),
+                }
+            }
+            other => panic!("Expected TrackDef, got {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,157 @@
+//! Scales and keys — resolve scale degrees to pitches.
+//!
+//! A `Key` is a tonic plus a scale, written `'D dorian'` or
+//! `'Bb3 harmonic minor'` in `track.key`. Degree notes (`^1`, `^5`) count
+//! up the scale from the tonic, continuing into the next octave past the
+//! last degree.
+
+use std::collections::HashMap;
+
+use crate::dsp::engine::note_to_midi;
+
+/// Built-in scales as semitone steps above the tonic.
+pub const SCALES: &[(&str, &[i32])] = &[
+    ("major", &[0, 2, 4, 5, 7, 9, 11]),
+    ("ionian", &[0, 2, 4, 5, 7, 9, 11]),
+    ("dorian", &[0, 2, 3, 5, 7, 9, 10]),
+    ("phrygian", &[0, 1, 3, 5, 7, 8, 10]),
+    ("lydian", &[0, 2, 4, 6, 7, 9, 11]),
+    ("mixolydian", &[0, 2, 4, 5, 7, 9, 10]),
+    ("minor", &[0, 2, 3, 5, 7, 8, 10]),
+    ("aeolian", &[0, 2, 3, 5, 7, 8, 10]),
+    ("locrian", &[0, 1, 3, 5, 6, 8, 10]),
+    ("harmonic minor", &[0, 2, 3, 5, 7, 8, 11]),
+    ("melodic minor", &[0, 2, 3, 5, 7, 9, 11]),
+    ("pentatonic", &[0, 2, 4, 7, 9]),
+    ("major pentatonic", &[0, 2, 4, 7, 9]),
+    ("minor pentatonic", &[0, 3, 5, 7, 10]),
+    ("blues", &[0, 3, 5, 6, 7, 10]),
+    ("whole tone", &[0, 2, 4, 6, 8, 10]),
+    ("chromatic", &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+];
+
+/// Octave of a key's tonic when `track.key` doesn't give one.
+const DEFAULT_OCTAVE: i32 = 4;
+
+/// Look up a built-in scale by name, ignoring case.
+pub fn builtin_scale(name: &str) -> Option<&'static [i32]> {
+    let name = name.to_lowercase();
+    SCALES.iter().find(|(n, _)| *n == name).map(|(_, steps)| *steps)
+}
+
+/// Whether `steps` form a scale: starting at 0 and rising within one
+/// octave.
+pub fn is_scale(steps: &[i32]) -> bool {
+    steps.first() == Some(&0)
+        && steps.windows(2).all(|w| w[0] < w[1])
+        && steps.iter().all(|&s| s < 12)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    /// MIDI key of degree 1.
+    pub tonic: i32,
+    /// Semitone steps above the tonic, starting at 0.
+    pub steps: Vec<i32>,
+}
+
+impl Key {
+    /// Parse `'D dorian'`, `'Bb3 harmonic minor'` or just `'G'` (major).
+    /// `scales` holds the song's own scales, which shadow built-in ones.
+    pub fn parse(text: &str, scales: &HashMap<String, Vec<i32>>) -> Result<Key, String> {
+        let mut words = text.split_whitespace();
+        let tonic_name = words.next().unwrap_or_default();
+        let tonic = note_to_midi(tonic_name)
+            .or_else(|| note_to_midi(&format!("{tonic_name}{DEFAULT_OCTAVE}")))
+            .ok_or_else(|| {
+                format!("Unknown key '{text}'. Expected a tonic and a scale, e.g. 'D dorian'.")
+            })?;
+
+        let scale = words.collect::<Vec<_>>().join(" ");
+        let steps = if scale.is_empty() {
+            builtin_scale("major").map(<[i32]>::to_vec)
+        } else {
+            scales.get(&scale).cloned().or_else(|| builtin_scale(&scale).map(<[i32]>::to_vec))
+        };
+        match steps {
+            Some(steps) => Ok(Key { tonic, steps }),
+            None => Err(format!("Unknown scale '{scale}'.")),
+        }
+    }
+
+    /// MIDI key of a 1-based degree. Degrees past the end of the scale
+    /// continue into higher octaves; 0 and below go down.
+    pub fn degree(&self, degree: i32) -> i32 {
+        let len = self.steps.len() as i32;
+        let index = degree - 1;
+        self.tonic + 12 * index.div_euclid(len) + self.steps[index.rem_euclid(len) as usize]
+    }
+
+    /// Move `midi` by `steps` scale degrees. A pitch outside the scale
+    /// keeps its distance above the scale tone below it.
+    pub fn shift(&self, midi: i32, steps: i32) -> i32 {
+        let len = self.steps.len() as i32;
+        let above_tonic = midi - self.tonic;
+        let within = above_tonic.rem_euclid(12);
+        let index = self.steps.iter().rposition(|&s| s <= within).unwrap_or(0);
+        let degree = above_tonic.div_euclid(12) * len + index as i32 + 1;
+        self.degree(degree + steps) + within - self.steps[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        Key::parse(text, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn parses_keys() {
+        let d_dorian = key("D dorian");
+        assert_eq!(d_dorian.tonic, 62);
+        assert_eq!(d_dorian.steps, [0, 2, 3, 5, 7, 9, 10]);
+        assert_eq!(key("Bb3 Harmonic Minor").tonic, 58);
+        assert_eq!(key("G").steps, builtin_scale("major").unwrap());
+
+        let scales = HashMap::from([("hijaz".to_string(), vec![0, 1, 4, 5, 7, 8, 10])]);
+        assert_eq!(Key::parse("E hijaz", &scales).unwrap().steps[2], 4);
+
+        assert_eq!(
+            Key::parse("H major", &scales).unwrap_err(),
+            "Unknown key 'H major'. Expected a tonic and a scale, e.g. 'D dorian'."
+        );
+        assert_eq!(Key::parse("C bebop", &scales).unwrap_err(), "Unknown scale 'bebop'.");
+    }
+
+    #[test]
+    fn degrees_wrap_into_neighbouring_octaves() {
+        let c = key("C major");
+        let degrees: Vec<i32> = (-1..=9).map(|d| c.degree(d)).collect();
+        assert_eq!(degrees, [57, 59, 60, 62, 64, 65, 67, 69, 71, 72, 74]);
+
+        let pentatonic = key("A3 minor pentatonic");
+        assert_eq!(pentatonic.degree(6), 69);
+    }
+
+    #[test]
+    fn shift_stays_in_key() {
+        let c = key("C major");
+        // E4 up a third is G4; B4 up one step is C5.
+        assert_eq!(c.shift(64, 2), 67);
+        assert_eq!(c.shift(71, 1), 72);
+        assert_eq!(c.shift(60, -1), 59);
+        // F#4 sits a semitone above F4, so it lands a semitone above G4.
+        assert_eq!(c.shift(66, 1), 68);
+    }
+
+    #[test]
+    fn validates_scales() {
+        assert!(is_scale(&[0, 2, 4, 7, 9]));
+        assert!(!is_scale(&[]));
+        assert!(!is_scale(&[2, 4]));
+        assert!(!is_scale(&[0, 4, 2]));
+        assert!(!is_scale(&[0, 12]));
+    }
+}
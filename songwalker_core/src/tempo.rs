@@ -0,0 +1,138 @@
+//! Tempo map — converts between beats and seconds across tempo changes.
+//!
+//! Built from the `track.beatsPerMinute` assignments in a compiled
+//! `EventList`. Each assignment takes effect at its beat and holds until
+//! the next one; before the first, the default tempo applies.
+
+use crate::compiler::{EventKind, EventList};
+
+/// Tempo used when a song never sets `track.beatsPerMinute`.
+pub const DEFAULT_BPM: f64 = 120.0;
+
+/// A tempo segment starting at `beat`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    pub beat: f64,
+    pub bpm: f64,
+    /// Seconds from the start of the song to `beat`.
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// Segments sorted by beat; the first always starts at beat 0.
+    changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    /// A map with one tempo throughout.
+    pub fn constant(bpm: f64) -> Self {
+        TempoMap {
+            changes: vec![TempoChange {
+                beat: 0.0,
+                bpm,
+                seconds: 0.0,
+            }],
+        }
+    }
+
+    /// Collect the tempo changes in an EventList. Invalid or non-positive
+    /// values are ignored; of several changes on one beat the last wins.
+    pub fn from_events(event_list: &EventList, default_bpm: f64) -> Self {
+        let mut points: Vec<(f64, f64)> = event_list
+            .events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::SetProperty { target, value, .. } if target == "track.beatsPerMinute" => {
+                    value.parse::<f64>().ok().filter(|bpm| *bpm > 0.0).map(|bpm| (e.time, bpm))
+                }
+                _ => None,
+            })
+            .collect();
+        // Stable sort keeps source order within a beat.
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut map = TempoMap::constant(default_bpm);
+        for (beat, bpm) in points {
+            let beat = beat.max(0.0);
+            let last = *map.changes.last().unwrap();
+            if beat <= last.beat {
+                map.changes.last_mut().unwrap().bpm = bpm;
+            } else if bpm != last.bpm {
+                map.changes.push(TempoChange {
+                    beat,
+                    bpm,
+                    seconds: last.seconds + (beat - last.beat) * 60.0 / last.bpm,
+                });
+            }
+        }
+        map
+    }
+
+    pub fn changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    /// The tempo in effect at a beat.
+    pub fn bpm_at(&self, beat: f64) -> f64 {
+        self.segment_at_beat(beat).bpm
+    }
+
+    /// Seconds from the start of the song to a beat.
+    pub fn seconds_at(&self, beat: f64) -> f64 {
+        let seg = self.segment_at_beat(beat);
+        seg.seconds + (beat - seg.beat) * 60.0 / seg.bpm
+    }
+
+    /// The beat reached after `seconds`.
+    pub fn beat_at(&self, seconds: f64) -> f64 {
+        let i = self.changes.partition_point(|c| c.seconds <= seconds);
+        let seg = &self.changes[i.saturating_sub(1)];
+        seg.beat + (seconds - seg.seconds) * seg.bpm / 60.0
+    }
+
+    fn segment_at_beat(&self, beat: f64) -> &TempoChange {
+        let i = self.changes.partition_point(|c| c.beat <= beat);
+        &self.changes[i.saturating_sub(1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(source: &str) -> TempoMap {
+        let events = crate::compiler::compile(&crate::parse(source).unwrap()).unwrap();
+        TempoMap::from_events(&events, DEFAULT_BPM)
+    }
+
+    #[test]
+    fn constant_tempo() {
+        let m = map("track.beatsPerMinute = 90;\n");
+        assert_eq!(m.changes().len(), 1);
+        assert_eq!(m.bpm_at(10.0), 90.0);
+        assert!((m.seconds_at(3.0) - 2.0).abs() < 1e-9);
+        assert!((m.beat_at(2.0) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tempo_change_mid_song() {
+        // 4 beats at 120 (2s), then 60 BPM.
+        let m = map(
+            "t();\ntrack t() {\n    C4 4\n    track.beatsPerMinute = 60;\n    C4 4\n}\n",
+        );
+        assert_eq!(m.changes().len(), 2);
+        assert_eq!(m.bpm_at(3.9), 120.0);
+        assert_eq!(m.bpm_at(4.0), 60.0);
+        assert!((m.seconds_at(4.0) - 2.0).abs() < 1e-9);
+        assert!((m.seconds_at(6.0) - 4.0).abs() < 1e-9);
+        assert!((m.beat_at(1.0) - 2.0).abs() < 1e-9);
+        assert!((m.beat_at(5.0) - 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn defaults_without_assignments() {
+        let m = map("");
+        assert_eq!(m, TempoMap::constant(DEFAULT_BPM));
+    }
+}
@@ -11,6 +11,12 @@ pub enum Token {
     Const,
     Let,
     For,
+    If,
+    Else,
+    Parallel,
+    Repeat,
+    Ending,
+    AtKeyword, // at
 
     // Punctuation
     Star,       // *
@@ -20,6 +26,9 @@ pub enum Token {
     Semicolon,  // ;
     Comma,      // ,
     Eq,         // =
+    EqEq,       // ==
+    NotEq,      // !=
+    Bang,       // !
     LParen,     // (
     RParen,     // )
     LBracket,   // [
@@ -28,11 +37,19 @@ pub enum Token {
     RBrace,     // }
     Lt,         // <
     Gt,         // >
+    LtEq,       // <=
+    GtEq,       // >=
+    AndAnd,     // &&
+    OrOr,       // ||
+    Percent,    // %
     Plus,       // +
     Minus,      // -
     PlusPlus,   // ++
     MinusMinus, // --
     Colon,      // :
+    Amp,        // &
+    Tilde,      // ~
+    Caret,      // ^
 
     // Structural
     Newline,
@@ -69,6 +86,12 @@ pub fn token_to_string(token: &Token) -> String {
         Token::Const => "const".into(),
         Token::Let => "let".into(),
         Token::For => "for".into(),
+        Token::If => "if".into(),
+        Token::Else => "else".into(),
+        Token::Parallel => "parallel".into(),
+        Token::Repeat => "repeat".into(),
+        Token::Ending => "ending".into(),
+        Token::AtKeyword => "at".into(),
         Token::Star => "*".into(),
         Token::At => "@".into(),
         Token::Slash => "/".into(),
@@ -76,6 +99,9 @@ pub fn token_to_string(token: &Token) -> String {
         Token::Semicolon => ";".into(),
         Token::Comma => ",".into(),
         Token::Eq => "=".into(),
+        Token::EqEq => "==".into(),
+        Token::NotEq => "!=".into(),
+        Token::Bang => "!".into(),
         Token::LParen => "(".into(),
         Token::RParen => ")".into(),
         Token::LBracket => "[".into(),
@@ -84,11 +110,19 @@ pub fn token_to_string(token: &Token) -> String {
         Token::RBrace => "}".into(),
         Token::Lt => "<".into(),
         Token::Gt => ">".into(),
+        Token::LtEq => "<=".into(),
+        Token::GtEq => ">=".into(),
+        Token::AndAnd => "&&".into(),
+        Token::OrOr => "||".into(),
+        Token::Percent => "%".into(),
         Token::Plus => "+".into(),
         Token::Minus => "-".into(),
         Token::PlusPlus => "++".into(),
         Token::MinusMinus => "--".into(),
         Token::Colon => ":".into(),
+        Token::Amp => "&".into(),
+        Token::Tilde => "~".into(),
+        Token::Caret => "^".into(),
         Token::Newline => "\n".into(),
         Token::Comment(s) => format!("// {s}"),
         Token::EOF => "".into(),
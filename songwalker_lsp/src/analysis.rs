@@ -0,0 +1,751 @@
+//! Document analysis for the language server.
+//!
+//! Everything here works on a single `.sw` document and returns `lsp_types`
+//! values; `main.rs` only moves them over the wire. Core spans are char
+//! offsets, LSP positions are (line, UTF-16 column) — `Document` converts
+//! between the two.
+
+use std::collections::HashMap;
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol,
+    Documentation, Hover, HoverContents, InsertTextFormat, MarkupContent, MarkupKind, Position,
+    Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+    SymbolKind,
+};
+use songwalker_core::ast::{Param, Program, Statement};
+use songwalker_core::compiler::{self, EventKind, EventList, InstrumentConfig};
+use songwalker_core::dsp::engine::{midi_to_frequency, note_to_midi};
+use songwalker_core::error::{self, SongWalkerError};
+use songwalker_core::lexer::Lexer;
+use songwalker_core::preset::{LibraryIndex, gm_category_display};
+use songwalker_core::token::{Span, Spanned, Token};
+
+// ── Document ────────────────────────────────────────────────
+
+/// A parsed and compiled `.sw` document.
+pub struct Document {
+    chars: Vec<char>,
+    /// Char offset of the start of each line.
+    line_starts: Vec<usize>,
+    tokens: Vec<Spanned>,
+    program: Program,
+    parse_errors: Vec<SongWalkerError>,
+    events: EventList,
+    compile_errors: Vec<(String, Span)>,
+    consts: HashMap<String, InstrumentConfig>,
+}
+
+/// A `track` or `const` definition found in the source.
+struct Definition {
+    name: String,
+    kind: DefKind,
+    /// Span of the defined name.
+    name_span: Span,
+    /// Span of the whole statement.
+    span: Span,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DefKind {
+    Track,
+    Const,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(i, _)| i + 1),
+        );
+
+        let (tokens, _) = Lexer::new(text).tokenize_recovering();
+        let (program, parse_errors) = songwalker_core::parse_recovering(text);
+        let (events, compile_errors) = compiler::compile_recovering(&program);
+        let consts = compiler::resolve_consts(&program);
+
+        Document {
+            chars,
+            line_starts,
+            tokens,
+            program,
+            parse_errors,
+            events,
+            compile_errors,
+            consts,
+        }
+    }
+
+    // ── Positions ──
+
+    /// Convert a char offset to an LSP position.
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.chars.len());
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let character: usize = self.chars[self.line_starts[line]..offset]
+            .iter()
+            .map(|c| c.len_utf16())
+            .sum();
+        Position::new(line as u32, character as u32)
+    }
+
+    /// Convert an LSP position to a char offset (clamped to the line end).
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.chars.len();
+        };
+        let mut units = 0;
+        let mut offset = start;
+        while offset < self.chars.len()
+            && self.chars[offset] != '\n'
+            && units < position.character as usize
+        {
+            units += self.chars[offset].len_utf16();
+            offset += 1;
+        }
+        offset
+    }
+
+    pub fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    /// The token under `offset` (a cursor right after a token also counts).
+    fn token_at(&self, offset: usize) -> Option<&Spanned> {
+        let mut candidates = self
+            .tokens
+            .iter()
+            .filter(|t| !matches!(t.token, Token::Newline | Token::EOF));
+        candidates
+            .clone()
+            .find(|t| t.span.start <= offset && offset < t.span.end)
+            .or_else(|| candidates.find(|t| t.span.end == offset))
+    }
+
+    fn definitions(&self) -> Vec<Definition> {
+        let mut defs = Vec::new();
+        for pair in self.tokens.windows(2) {
+            let kind = match pair[0].token {
+                Token::Track => DefKind::Track,
+                Token::Const => DefKind::Const,
+                _ => continue,
+            };
+            if let Token::Ident(name) = &pair[1].token {
+                let span = self
+                    .program
+                    .statements
+                    .iter()
+                    .map(Statement::span)
+                    .find(|s| s.start == pair[0].span.start)
+                    .unwrap_or(pair[1].span);
+                defs.push(Definition {
+                    name: name.clone(),
+                    kind,
+                    name_span: pair[1].span,
+                    span,
+                });
+            }
+        }
+        defs
+    }
+
+    fn track_params(&self, name: &str) -> Option<&[Param]> {
+        self.program.statements.iter().find_map(|stmt| match stmt {
+            Statement::TrackDef {
+                name: n, params, ..
+            } if n == name => Some(params.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Parameters of the track definition enclosing `offset`.
+    fn enclosing_params(&self, offset: usize) -> &[Param] {
+        self.program
+            .statements
+            .iter()
+            .find_map(|stmt| match stmt {
+                Statement::TrackDef {
+                    params,
+                    span_start,
+                    span_end,
+                    ..
+                } if *span_start <= offset && offset <= *span_end => Some(params.as_slice()),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    // ── Diagnostics ──
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let parse = self.parse_errors.iter().map(|e| {
+            let d = error::Diagnostic::from_error(e, self.chars.len());
+            (d.message, Span {
+                start: d.start,
+                end: d.end,
+            })
+        });
+        let compile = self.compile_errors.iter().cloned();
+        parse
+            .chain(compile)
+            .map(|(message, span)| Diagnostic {
+                range: self.range(span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("songwalker".into()),
+                message,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    // ── Completion ──
+
+    pub fn completion(&self, offset: usize, library: Option<&LibraryIndex>) -> Vec<CompletionItem> {
+        if self.in_preset_name(offset) {
+            return library.map(preset_completions).unwrap_or_default();
+        }
+
+        let mut items = builtin_completions();
+        for def in self.definitions() {
+            match def.kind {
+                DefKind::Track => {
+                    let params = param_list(self.track_params(&def.name).unwrap_or(&[]));
+                    items.push(CompletionItem {
+                        label: def.name.clone(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        detail: Some(format!("track {}({params})", def.name)),
+                        insert_text: Some(format!("{}($0)", def.name)),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
+                        ..Default::default()
+                    });
+                }
+                DefKind::Const => items.push(CompletionItem {
+                    label: def.name.clone(),
+                    kind: Some(CompletionItemKind::CONSTANT),
+                    detail: self.consts.get(&def.name).map(instrument_summary),
+                    ..Default::default()
+                }),
+            }
+        }
+        for param in self.enclosing_params(offset) {
+            items.push(CompletionItem {
+                label: param.name.clone(),
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(match param.ty {
+                    Some(ty) => format!("{} parameter", ty.name()),
+                    None => "track parameter".into(),
+                }),
+                ..Default::default()
+            });
+        }
+        items
+    }
+
+    /// Whether `offset` is inside the string argument of `loadPreset(`.
+    fn in_preset_name(&self, offset: usize) -> bool {
+        let line_start = self.line_starts[self.position(offset).line as usize];
+        let before: String = self.chars[line_start..offset.min(self.chars.len())]
+            .iter()
+            .collect();
+        let Some(call) = before.rfind("loadPreset(") else {
+            return false;
+        };
+        let arg = before[call + "loadPreset(".len()..].trim_start();
+        let mut chars = arg.chars();
+        match chars.next() {
+            Some(quote @ ('"' | '\'')) => !chars.any(|c| c == quote),
+            _ => false,
+        }
+    }
+
+    // ── Hover ──
+
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        let token = self.token_at(offset)?;
+        let Token::Ident(name) = &token.token else {
+            return None;
+        };
+
+        let value = if let Some(params) = self.track_params(name) {
+            format!("```songwalker\ntrack {name}({})\n```", param_list(params))
+        } else if let Some(config) = self.consts.get(name) {
+            format!("`const {name}`\n\n{}", describe_instrument(config))
+        } else if let Some(midi) = note_to_midi(name) {
+            self.describe_note(name, midi, token.span)
+        } else {
+            return None;
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(self.range(token.span)),
+        })
+    }
+
+    /// MIDI number, frequency and (if the note is played) its instrument.
+    fn describe_note(&self, pitch: &str, midi: i32, span: Span) -> String {
+        let mut tuning = 440.0;
+        let mut instrument = None;
+        for event in &self.events.events {
+            match &event.kind {
+                EventKind::SetProperty { target, value, .. } if target == "track.tuningPitch" => {
+                    tuning = value.parse().unwrap_or(tuning);
+                }
+                // Every note of a statement shares its instrument, so the
+                // span alone finds it, even for transposed notes.
+                EventKind::Note {
+                    instrument: inst,
+                    source_start,
+                    source_end,
+                    ..
+                } if *source_start <= span.start && span.end <= *source_end => {
+                    instrument = Some(inst);
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        let freq = midi_to_frequency(midi, tuning);
+        let mut out = format!("**{pitch}** — MIDI {midi}, {freq:.2} Hz");
+        if let Some(config) = instrument {
+            out.push_str("\n\n");
+            out.push_str(&describe_instrument(config));
+        }
+        out
+    }
+
+    // ── Navigation ──
+
+    /// Range of the track or const definition named under `offset`.
+    pub fn definition(&self, offset: usize) -> Option<Range> {
+        let Token::Ident(name) = &self.token_at(offset)?.token else {
+            return None;
+        };
+        self.definitions()
+            .into_iter()
+            .find(|d| &d.name == name)
+            .map(|d| self.range(d.name_span))
+    }
+
+    #[allow(deprecated)] // `DocumentSymbol::deprecated` must still be set
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol> {
+        self.definitions()
+            .into_iter()
+            .map(|def| {
+                let (kind, detail) = match def.kind {
+                    DefKind::Track => (
+                        SymbolKind::FUNCTION,
+                        self.track_params(&def.name)
+                            .map(|p| format!("({})", param_list(p))),
+                    ),
+                    DefKind::Const => (
+                        SymbolKind::CONSTANT,
+                        self.consts.get(&def.name).map(instrument_summary),
+                    ),
+                };
+                DocumentSymbol {
+                    name: def.name,
+                    detail,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range: self.range(def.span),
+                    selection_range: self.range(def.name_span),
+                    children: None,
+                }
+            })
+            .collect()
+    }
+
+    // ── Semantic Tokens ──
+
+    /// Semantic tokens, delta-encoded against `legend()`.
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken> {
+        let defs = self.definitions();
+        let is_def = |kind: DefKind, name: &str| defs.iter().any(|d| d.kind == kind && d.name == name);
+
+        let mut out = Vec::new();
+        let mut prev = Position::new(0, 0);
+        for (i, spanned) in self.tokens.iter().enumerate() {
+            let next = self.tokens.get(i + 1).map(|t| &t.token);
+            let after_dot = i > 0 && self.tokens[i - 1].token == Token::Dot;
+            let (token_type, modifiers) = match &spanned.token {
+                Token::Track
+                | Token::Const
+                | Token::Let
+                | Token::For
+                | Token::If
+                | Token::Else
+                | Token::Repeat
+                | Token::Ending
+                | Token::Parallel
+                | Token::AtKeyword => (KEYWORD, 0),
+                Token::Comment(_) => (COMMENT, 0),
+                Token::StringLit(_) => (STRING, 0),
+                Token::Number(_) => (NUMBER, 0),
+                Token::RegexLit(_) => (REGEXP, 0),
+                Token::Star | Token::At | Token::Amp | Token::Tilde | Token::Caret => (OPERATOR, 0),
+                Token::Ident(_) if after_dot => (PROPERTY, 0),
+                Token::Ident(name) if is_def(DefKind::Track, name) => (FUNCTION, 0),
+                Token::Ident(name) if is_def(DefKind::Const, name) => (VARIABLE, READONLY),
+                Token::Ident(name)
+                    if self
+                        .enclosing_params(spanned.span.start)
+                        .iter()
+                        .any(|p| p.name == *name) =>
+                {
+                    (PARAMETER, 0)
+                }
+                Token::Ident(_) if next == Some(&Token::LParen) => (FUNCTION, 0),
+                Token::Ident(name) if note_to_midi(name).is_some() => (NOTE, 0),
+                Token::Ident(_) => (VARIABLE, 0),
+                _ => continue,
+            };
+
+            let start = self.position(spanned.span.start);
+            let end = self.position(spanned.span.end);
+            if end.line != start.line {
+                continue; // tokens never span lines in practice
+            }
+            let delta_line = start.line - prev.line;
+            let delta_start = if delta_line == 0 {
+                start.character - prev.character
+            } else {
+                start.character
+            };
+            out.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: end.character - start.character,
+                token_type,
+                token_modifiers_bitset: modifiers,
+            });
+            prev = start;
+        }
+        out
+    }
+}
+
+// Indices into `legend()`.
+const KEYWORD: u32 = 0;
+const COMMENT: u32 = 1;
+const STRING: u32 = 2;
+const NUMBER: u32 = 3;
+const REGEXP: u32 = 4;
+const OPERATOR: u32 = 5;
+const FUNCTION: u32 = 6;
+const VARIABLE: u32 = 7;
+const PROPERTY: u32 = 8;
+const PARAMETER: u32 = 9;
+/// Note names (`C4`, `Eb3`) are reported as enum members.
+const NOTE: u32 = 10;
+/// Modifier bit for `const` references.
+const READONLY: u32 = 1;
+
+/// The semantic token legend advertised to the client.
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::COMMENT,
+            SemanticTokenType::STRING,
+            SemanticTokenType::NUMBER,
+            SemanticTokenType::REGEXP,
+            SemanticTokenType::OPERATOR,
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::ENUM_MEMBER,
+        ],
+        token_modifiers: vec![SemanticTokenModifier::READONLY],
+    }
+}
+
+// ── Completion Items ────────────────────────────────────────
+
+fn snippet(label: &str, kind: CompletionItemKind, insert: &str, detail: &str) -> CompletionItem {
+    CompletionItem {
+        label: label.into(),
+        kind: Some(kind),
+        detail: Some(detail.into()),
+        insert_text: Some(insert.into()),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        ..Default::default()
+    }
+}
+
+/// Keywords, built-in instruments and track properties (mirrors the web editor).
+fn builtin_completions() -> Vec<CompletionItem> {
+    use CompletionItemKind as K;
+    vec![
+        snippet("track", K::KEYWORD, "track ${1:name}(${2:params}) {\n\t$0\n}", "Define a new track"),
+        snippet("const", K::KEYWORD, "const ${1:name} = ${0};", "Declare a constant"),
+        snippet("scale", K::KEYWORD, "scale ${1:name} = [${2:0, 2, 3, 5, 7, 8, 10}];", "Declare a scale"),
+        snippet(
+            "groove",
+            K::KEYWORD,
+            "groove ${1:name} = {unit: ${2:1/4}, timing: [${3:0, 0.1}], velocity: [${4:0, -10}]};",
+            "Declare a groove template",
+        ),
+        snippet(
+            "for",
+            K::KEYWORD,
+            "for (let ${1:i} = 0; ${1:i} < ${2:count}; ${1:i}++) {\n\t$0\n}",
+            "For loop",
+        ),
+        snippet("repeat", K::KEYWORD, "repeat ${1:2} {\n\t$0\n}", "Repeat a passage"),
+        snippet("if", K::KEYWORD, "if (${1:condition}) {\n\t$0\n}", "Conditional"),
+        snippet(
+            "parallel",
+            K::KEYWORD,
+            "parallel {\n\t${1:track}();\n\t$0\n}",
+            "Start track calls together",
+        ),
+        snippet("at", K::KEYWORD, "at ${1:beat}: ${0}", "Place a statement at a beat from the enclosing block start"),
+        snippet("loadPreset", K::FUNCTION, "loadPreset(\"${0}\")", "Load an instrument preset by name"),
+        snippet("random", K::FUNCTION, "random(${1:min}, ${2:max})", "Seeded random number"),
+        snippet("choose", K::FUNCTION, "choose([${1:C4, E4, G4}])", "Pick a random item, optionally weighted"),
+        snippet("shuffle", K::FUNCTION, "shuffle([${1:C4, E4, G4}])", "The list in seeded random order"),
+        snippet("euclid", K::FUNCTION, "euclid(${1:3}, ${2:8})", "Euclidean rhythm as a list of 1s and 0s"),
+        snippet(
+            "Oscillator",
+            K::FUNCTION,
+            "Oscillator({type: '${1|sine,square,sawtooth,triangle|}'})",
+            "Create an oscillator instrument",
+        ),
+        snippet("track.beatsPerMinute", K::PROPERTY, "track.beatsPerMinute = ${1:120};", "Set the tempo in BPM"),
+        snippet("track.noteLength", K::PROPERTY, "track.noteLength = ${1:1/4};", "Set the default note length"),
+        snippet("track.key", K::PROPERTY, "track.key = '${1:D dorian}';", "Set the key for degree notes"),
+        snippet(
+            "track.diatonicTranspose",
+            K::PROPERTY,
+            "track.diatonicTranspose = ${1:2};",
+            "Transpose by scale steps",
+        ),
+        snippet("track.transpose", K::PROPERTY, "track.transpose = ${1:12};", "Transpose by semitones"),
+        snippet(
+            "track.voicing",
+            K::PROPERTY,
+            "track.voicing = '${1|close,drop2,spread|}';",
+            "How chord symbols are voiced",
+        ),
+        snippet("track.inversion", K::PROPERTY, "track.inversion = ${1:1};", "Invert chord symbols"),
+        snippet("track.chordOctave", K::PROPERTY, "track.chordOctave = ${1:3};", "Octave of chord-symbol roots"),
+        snippet(
+            "track.arpeggio",
+            K::PROPERTY,
+            "track.arpeggio = '${1|up,down,updown,random,none|}';",
+            "Arpeggiate chord symbols across the step",
+        ),
+        snippet("track.swing", K::PROPERTY, "track.swing = ${1:0.6};", "Swing every second eighth note"),
+        snippet("track.swingUnit", K::PROPERTY, "track.swingUnit = ${1:1/4};", "Note length that swings"),
+        snippet("track.groove", K::PROPERTY, "track.groove = '${1:name}';", "Apply a groove template"),
+        snippet(
+            "track.humanizeTiming",
+            K::PROPERTY,
+            "track.humanizeTiming = ${1:0.02};",
+            "Random timing shift in beats",
+        ),
+        snippet(
+            "track.humanizeVelocity",
+            K::PROPERTY,
+            "track.humanizeVelocity = ${1:8};",
+            "Random velocity change",
+        ),
+        snippet("track.instrument", K::PROPERTY, "track.instrument = ${1:inst};", "Set the track instrument"),
+        snippet("track.tuningPitch", K::PROPERTY, "track.tuningPitch = ${1:440};", "Set the A4 frequency in Hz"),
+        snippet(
+            "song.endMode",
+            K::PROPERTY,
+            "song.endMode = '${1|tail,release,gate|}';",
+            "How the song end is determined",
+        ),
+        snippet("song.seed", K::PROPERTY, "song.seed = ${1:1};", "Seed for random choices and humanize"),
+    ]
+}
+
+fn preset_completions(library: &LibraryIndex) -> Vec<CompletionItem> {
+    library
+        .presets
+        .iter()
+        .map(|entry| {
+            let category = entry
+                .gm_program
+                .map(gm_category_display)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{:?}", entry.category));
+            CompletionItem {
+                label: entry.name.clone(),
+                kind: Some(CompletionItemKind::VALUE),
+                detail: Some(category),
+                documentation: (!entry.tags.is_empty())
+                    .then(|| Documentation::String(entry.tags.join(", "))),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// A track's parameter list as written, e.g. `root: pitch, len`.
+fn param_list(params: &[Param]) -> String {
+    params.iter().map(Param::to_string).collect::<Vec<_>>().join(", ")
+}
+
+// ── Instrument Descriptions ─────────────────────────────────
+
+/// One-line summary, e.g. `square oscillator` or `preset "Piano"`.
+fn instrument_summary(config: &InstrumentConfig) -> String {
+    match &config.preset_ref {
+        Some(name) => format!("preset \"{name}\""),
+        None => format!("{} oscillator", config.waveform),
+    }
+}
+
+/// Markdown description of a resolved instrument.
+fn describe_instrument(config: &InstrumentConfig) -> String {
+    let mut out = format!("Instrument: {}", instrument_summary(config));
+    let fields = [
+        ("attack", config.attack, "s"),
+        ("decay", config.decay, "s"),
+        ("sustain", config.sustain, ""),
+        ("release", config.release, "s"),
+        ("detune", config.detune, " cents"),
+        ("mixer", config.mixer, ""),
+    ];
+    for (name, value, unit) in fields {
+        if let Some(v) = value {
+            out.push_str(&format!("\n- {name}: {v}{unit}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SONG: &str = "const lead = Oscillator({type: 'square', attack: 0.01});\n\
+                        riff(lead);\n\
+                        \n\
+                        track riff(inst) {\n    \
+                            track.instrument = inst;\n    \
+                            A4 /4\n\
+                        }\n";
+
+    fn hover_text(doc: &Document, offset: usize) -> String {
+        match doc.hover(offset).unwrap().contents {
+            HoverContents::Markup(m) => m.value,
+            other => panic!("unexpected hover {other:?}"),
+        }
+    }
+
+    #[test]
+    fn positions_round_trip_with_utf16() {
+        let doc = Document::new("// é𝄞\nC4");
+        assert_eq!(doc.position(5), Position::new(0, 6));
+        assert_eq!(doc.position(6), Position::new(1, 0));
+        assert_eq!(doc.offset(Position::new(0, 6)), 5);
+        assert_eq!(doc.offset(Position::new(1, 1)), 7);
+    }
+
+    #[test]
+    fn reports_parse_and_compile_errors() {
+        let doc = Document::new("track a(x) {\n    C4 / )\n}\na(nothing);\n");
+        let diags = doc.diagnostics();
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].range.start.line, 1);
+        assert_eq!(diags[1].range.start, Position::new(3, 0));
+        assert!(diags[1].message.contains("nothing"));
+    }
+
+    #[test]
+    fn compile_errors_point_at_the_failing_statement() {
+        let doc = Document::new("riff();\ntrack riff() {\n    C4 /4\n    track.instrument = nothing;\n}\n");
+        let diags = doc.diagnostics();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].range.start, Position::new(3, 4));
+    }
+
+    #[test]
+    fn completes_tracks_consts_and_params() {
+        let doc = Document::new(SONG);
+        let offset = SONG.find("A4").unwrap();
+        let labels: Vec<String> = doc.completion(offset, None).into_iter().map(|c| c.label).collect();
+        for expected in ["riff", "lead", "inst", "track", "loadPreset"] {
+            assert!(labels.iter().any(|l| l == expected), "missing {expected}");
+        }
+    }
+
+    #[test]
+    fn completes_preset_names_inside_load_preset() {
+        let library: LibraryIndex = serde_json::from_str(
+            r#"{"version": 1, "generatedAt": "", "presets": [
+                {"id": "p", "name": "Grand Piano", "path": "p.json", "category": "sampler",
+                 "tags": ["keys"], "gmProgram": 0}
+            ]}"#,
+        )
+        .unwrap();
+        let src = "const p = loadPreset('Gr";
+        let doc = Document::new(src);
+        let items = doc.completion(src.chars().count(), Some(&library));
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "Grand Piano");
+        assert_eq!(items[0].detail.as_deref(), Some("Piano"));
+    }
+
+    #[test]
+    fn hover_shows_note_frequency_and_instrument() {
+        let doc = Document::new(SONG);
+        let text = hover_text(&doc, SONG.find("A4").unwrap());
+        assert!(text.contains("MIDI 69, 440.00 Hz"), "{text}");
+        assert!(text.contains("square oscillator"), "{text}");
+        assert!(text.contains("attack: 0.01s"), "{text}");
+    }
+
+    #[test]
+    fn hover_shows_const_instrument() {
+        let doc = Document::new(SONG);
+        let text = hover_text(&doc, SONG.find("(lead)").unwrap() + 1);
+        assert!(text.contains("const lead"));
+        assert!(text.contains("square oscillator"));
+    }
+
+    #[test]
+    fn goes_to_track_definition() {
+        let doc = Document::new(SONG);
+        let call = SONG.find("riff(lead)").unwrap();
+        let range = doc.definition(call).unwrap();
+        assert_eq!(range, Range::new(Position::new(3, 6), Position::new(3, 10)));
+    }
+
+    #[test]
+    fn lists_document_symbols() {
+        let doc = Document::new(SONG);
+        let symbols = doc.document_symbols();
+        let names: Vec<_> = symbols.iter().map(|s| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(
+            names,
+            [("lead", SymbolKind::CONSTANT), ("riff", SymbolKind::FUNCTION)]
+        );
+        assert_eq!(symbols[1].range.end.line, 6);
+    }
+
+    #[test]
+    fn classifies_semantic_tokens() {
+        let doc = Document::new("track a(x) {\n    C4 /4 // hi\n}");
+        let types: Vec<u32> = doc.semantic_tokens().iter().map(|t| t.token_type).collect();
+        assert_eq!(types, [KEYWORD, FUNCTION, PARAMETER, NOTE, NUMBER, COMMENT]);
+        let comment = doc.semantic_tokens()[5];
+        assert_eq!((comment.delta_line, comment.delta_start, comment.length), (0, 2, 5));
+    }
+}
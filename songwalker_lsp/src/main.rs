@@ -0,0 +1,273 @@
+//! SongWalker language server — diagnostics, completion, hover,
+//! go-to-definition, document symbols, semantic tokens and formatting for
+//! `.sw` files over stdio.
+//!
+//! Usage:
+//!   songwalker_lsp [--library <index.json>]
+//!
+//! The preset library index (used to complete `loadPreset("...")` names) can
+//! also be passed as the `libraryIndex` initialization option.
+
+mod analysis;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::process;
+
+use analysis::Document;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest,
+    Request as _, SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DocumentFormattingParams,
+    DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    HoverParams, HoverProviderCapability, InitializeParams, Location, OneOf, Position,
+    PublishDiagnosticsParams, Range, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Uri,
+};
+use songwalker_core::format;
+use songwalker_core::preset::LibraryIndex;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("songwalker_lsp: {e}");
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), BoxError> {
+    let args: Vec<String> = std::env::args().collect();
+    let library_arg = args
+        .iter()
+        .position(|a| a == "--library")
+        .and_then(|i| args.get(i + 1))
+        .cloned();
+
+    let (connection, io_threads) = Connection::stdio();
+    let init = connection.initialize(serde_json::to_value(capabilities())?)?;
+    let init: InitializeParams = serde_json::from_value(init)?;
+
+    let library_path = library_arg.or_else(|| {
+        init.initialization_options
+            .as_ref()?
+            .get("libraryIndex")?
+            .as_str()
+            .map(str::to_string)
+    });
+    let library = library_path.and_then(|path| match load_library(&path) {
+        Ok(index) => Some(index),
+        Err(e) => {
+            eprintln!("songwalker_lsp: cannot load library index '{path}': {e}");
+            None
+        }
+    });
+
+    let mut server = Server {
+        connection: &connection,
+        documents: HashMap::new(),
+        library,
+    };
+    server.main_loop()?;
+
+    // The writer thread exits once every sender is gone.
+    drop(server);
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn load_library(path: &str) -> Result<LibraryIndex, BoxError> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["\"".into(), "'".into(), ".".into()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: analysis::legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    }
+}
+
+// ── Server ──────────────────────────────────────────────────
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Uri, (String, Document)>,
+    library: Option<LibraryIndex>,
+}
+
+impl Server<'_> {
+    fn main_loop(&mut self) -> Result<(), BoxError> {
+        for msg in &self.connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    let id = req.id.clone();
+                    let response = match self.handle_request(req) {
+                        Ok(result) => Response::new_ok(id, result),
+                        Err(e) => Response::new_err(
+                            id,
+                            lsp_server::ErrorCode::InvalidParams as i32,
+                            e.to_string(),
+                        ),
+                    };
+                    self.connection.sender.send(response.into())?;
+                }
+                Message::Notification(not) => self.handle_notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, req: Request) -> Result<serde_json::Value, BoxError> {
+        let result = match req.method.as_str() {
+            Completion::METHOD => {
+                let (_, p) = extract::<CompletionParams>(req)?;
+                let pos = p.text_document_position;
+                self.with_document(&pos.text_document.uri, |doc| {
+                    let items = doc.completion(doc.offset(pos.position), self.library.as_ref());
+                    serde_json::to_value(CompletionResponse::Array(items))
+                })
+            }
+            HoverRequest::METHOD => {
+                let (_, p) = extract::<HoverParams>(req)?;
+                let pos = p.text_document_position_params;
+                self.with_document(&pos.text_document.uri, |doc| {
+                    serde_json::to_value(doc.hover(doc.offset(pos.position)))
+                })
+            }
+            GotoDefinition::METHOD => {
+                let (_, p) = extract::<GotoDefinitionParams>(req)?;
+                let pos = p.text_document_position_params;
+                let uri = pos.text_document.uri;
+                self.with_document(&uri, |doc| {
+                    let location = doc.definition(doc.offset(pos.position)).map(|range| {
+                        GotoDefinitionResponse::Scalar(Location {
+                            uri: uri.clone(),
+                            range,
+                        })
+                    });
+                    serde_json::to_value(location)
+                })
+            }
+            DocumentSymbolRequest::METHOD => {
+                let (_, p) = extract::<DocumentSymbolParams>(req)?;
+                self.with_document(&p.text_document.uri, |doc| {
+                    serde_json::to_value(DocumentSymbolResponse::Nested(doc.document_symbols()))
+                })
+            }
+            SemanticTokensFullRequest::METHOD => {
+                let (_, p) = extract::<SemanticTokensParams>(req)?;
+                self.with_document(&p.text_document.uri, |doc| {
+                    serde_json::to_value(SemanticTokensResult::Tokens(SemanticTokens {
+                        result_id: None,
+                        data: doc.semantic_tokens(),
+                    }))
+                })
+            }
+            Formatting::METHOD => {
+                let (_, p) = extract::<DocumentFormattingParams>(req)?;
+                let edits = self.documents.get(&p.text_document.uri).and_then(|(text, doc)| {
+                    // Sources with syntax errors are left alone.
+                    let formatted = format::format_source(text).ok()?;
+                    let end = doc.position(text.chars().count());
+                    Some(vec![TextEdit {
+                        range: Range::new(Position::new(0, 0), end),
+                        new_text: formatted,
+                    }])
+                });
+                serde_json::to_value(edits)
+            }
+            _ => Ok(serde_json::Value::Null),
+        };
+        Ok(result?)
+    }
+
+    /// Run `f` on an open document; unknown documents yield `null`.
+    fn with_document(
+        &self,
+        uri: &Uri,
+        f: impl FnOnce(&Document) -> serde_json::Result<serde_json::Value>,
+    ) -> serde_json::Result<serde_json::Value> {
+        match self.documents.get(uri) {
+            Some((_, doc)) => f(doc),
+            None => Ok(serde_json::Value::Null),
+        }
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<(), BoxError> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: lsp_types::DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                self.update(p.text_document.uri, p.text_document.text)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                // Full sync: the last change holds the whole document.
+                if let Some(change) = p.content_changes.into_iter().last() {
+                    self.update(p.text_document.uri, change.text)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: lsp_types::DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                self.documents.remove(&p.text_document.uri);
+                self.publish(p.text_document.uri, Vec::new())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Re-analyse a document and publish its diagnostics.
+    fn update(&mut self, uri: Uri, text: String) -> Result<(), BoxError> {
+        let doc = Document::new(&text);
+        let diagnostics = doc.diagnostics();
+        self.documents.insert(uri.clone(), (text, doc));
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&self, uri: Uri, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<(), BoxError> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let not = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(not.into())?;
+        Ok(())
+    }
+}
+
+fn extract<P: serde::de::DeserializeOwned>(req: Request) -> Result<(RequestId, P), BoxError> {
+    let method = req.method.clone();
+    req.extract(&method).map_err(|e| format!("{e:?}").into())
+}
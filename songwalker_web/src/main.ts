@@ -1,4 +1,4 @@
-import init, { compile_song } from './wasm/songwalker_core.js';
+import init, { compile_song, format_song } from './wasm/songwalker_core.js';
 import { SongPlayer } from './player.js';
 import { PresetLoader } from './preset-loader.js';
 import { PresetBrowser } from './preset-browser.js';
@@ -18,6 +18,7 @@ interface NoteEvent {
     kind: {
         Note: {
             pitch: string;
+            midi: number | null;
             velocity: number;
             gate: number;
             source_start: number;
@@ -154,6 +155,24 @@ async function saveFile(source: string): Promise<void> {
     }
 }
 
+/** Format the editor contents in place (undoable) and return the result. */
+function formatEditor(editor: monaco.editor.IStandaloneCodeEditor): string {
+    const source = editor.getValue();
+    let formatted: string;
+    try {
+        formatted = format_song(source);
+    } catch {
+        return source;
+    }
+    if (formatted !== source) {
+        const model = editor.getModel()!;
+        editor.pushUndoStop();
+        editor.executeEdits('songwalker.format', [{ range: model.getFullModelRange(), text: formatted }]);
+        editor.pushUndoStop();
+    }
+    return formatted;
+}
+
 // ── Error location markers ───────────────────────────────
 
 /** Parse `[start:end]` from an error message and underline the range in the editor. */
@@ -658,7 +677,7 @@ async function main() {
         }
     });
 
-    saveBtn.addEventListener('click', () => saveFile(editor.getValue()));
+    saveBtn.addEventListener('click', () => saveFile(formatEditor(editor)));
 
     exportBtn.addEventListener('click', exportWav);
 
@@ -679,6 +698,14 @@ async function main() {
         run: () => compileAndPlay(),
     });
 
+    // Format on save (Ctrl+S); sources with syntax errors are left untouched
+    editor.addAction({
+        id: 'songwalker.formatAndSave',
+        label: 'Format and Save',
+        keybindings: [monaco.KeyMod.CtrlCmd | monaco.KeyCode.KeyS],
+        run: () => saveSource(formatEditor(editor)),
+    });
+
     // Escape to exit fullscreen
     editor.addAction({
         id: 'songwalker.exitFullscreen',
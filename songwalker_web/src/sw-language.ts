@@ -35,7 +35,7 @@ export const monarchTokens: monaco.languages.IMonarchLanguage = {
     defaultToken: '',
     ignoreCase: false,
 
-    keywords: ['track', 'const', 'let', 'for'],
+    keywords: ['track', 'const', 'let', 'for', 'if', 'else', 'repeat', 'ending', 'as', 'parallel', 'at'],
 
     notes: [
         'C', 'D', 'E', 'F', 'G', 'A', 'B',
@@ -46,7 +46,10 @@ export const monarchTokens: monaco.languages.IMonarchLanguage = {
         'OpenHiHat', 'ClosedHiHat', 'Clap', 'Rimshot',
     ],
 
-    operators: ['=', '+', '-', '*', '@', '/', '.', '<', '>'],
+    operators: [
+        '=', '+', '-', '*', '@', '/', '.', '<', '>', '&', '~', '^',
+        '==', '!=', '<=', '>=', '&&', '||', '!', '%',
+    ],
 
     symbols: /[=><!~?:&|+\-*\/\^%@.]+/,
 
@@ -58,6 +61,9 @@ export const monarchTokens: monaco.languages.IMonarchLanguage = {
             // Note names: C3, Eb4, F#5, etc.
             [/[A-G][b#]?\d+/, 'variable.note'],
 
+            // Scale degrees: ^1, ^-2, ^step
+            [/\^-?\w+/, 'variable.note'],
+
             // Drum names
             [/\b(Kick|Snare|HiHat|Crash|Ride|Tom|OpenHiHat|ClosedHiHat|Clap|Rimshot)\b/, 'variable.drum'],
 
@@ -145,6 +151,20 @@ export const completionItems: monaco.languages.CompletionItem[] = [
         insertTextRules: 4,
         detail: 'Declare a constant',
     },
+    {
+        label: 'scale',
+        kind: 1,
+        insertText: 'scale ${1:name} = [${2:0, 2, 3, 5, 7, 8, 10}];',
+        insertTextRules: 4,
+        detail: 'Declare a scale for track.key',
+    },
+    {
+        label: 'groove',
+        kind: 1,
+        insertText: 'groove ${1:name} = {unit: ${2:1/4}, timing: [${3:0, 0.1}], velocity: [${4:0, -10}]};',
+        insertTextRules: 4,
+        detail: 'Declare a groove template for track.groove',
+    },
     {
         label: 'for',
         kind: 1,
@@ -152,6 +172,37 @@ export const completionItems: monaco.languages.CompletionItem[] = [
         insertTextRules: 4,
         detail: 'For loop',
     },
+    {
+        label: 'repeat',
+        kind: 1,
+        insertText: 'repeat ${1:2} {\n\t$0\n}',
+        insertTextRules: 4,
+        detail: 'Repeat a passage',
+        documentation: 'Follow with `ending 1 { }`, `ending 2 { }` for first and second endings. `repeat 4 as pass` numbers the passes from 1.',
+    },
+    {
+        label: 'if',
+        kind: 1,
+        insertText: 'if (${1:condition}) {\n\t$0\n}',
+        insertTextRules: 4,
+        detail: 'Conditional',
+        documentation: 'Conditions can use track parameters and repeat pass numbers, e.g. `if (fill && pass == 4)`.',
+    },
+    {
+        label: 'parallel',
+        kind: 1,
+        insertText: 'parallel {\n\t${1:track}();\n\t$0\n}',
+        insertTextRules: 4,
+        detail: 'Start track calls together',
+        documentation: 'Every member starts at the same beat; the block lasts as long as its longest member.',
+    },
+    {
+        label: 'at',
+        kind: 1,
+        insertText: 'at ${1:beat}: ${0}',
+        insertTextRules: 4,
+        detail: 'Place a statement at an absolute beat',
+    },
     {
         label: 'loadPreset',
         kind: 3, // Function
@@ -160,6 +211,34 @@ export const completionItems: monaco.languages.CompletionItem[] = [
         detail: 'Load an instrument preset by name',
         documentation: 'Loads a preset from the catalog. Preset assets are preloaded at compile time.',
     },
+    {
+        label: 'random',
+        kind: 3, // Function
+        insertText: 'random(${1:min}, ${2:max})',
+        insertTextRules: 4,
+        detail: 'Random number from min up to max, seeded by song.seed',
+    },
+    {
+        label: 'choose',
+        kind: 3, // Function
+        insertText: 'choose([${1:C4, E4, G4}])',
+        insertTextRules: 4,
+        detail: 'Pick a random item; a second list gives weights',
+    },
+    {
+        label: 'shuffle',
+        kind: 3, // Function
+        insertText: 'shuffle([${1:C4, E4, G4}])',
+        insertTextRules: 4,
+        detail: 'The list in random order, seeded by song.seed',
+    },
+    {
+        label: 'euclid',
+        kind: 3, // Function
+        insertText: 'euclid(${1:3}, ${2:8})',
+        insertTextRules: 4,
+        detail: 'Euclidean rhythm: hits spread evenly over steps, as 1s and 0s',
+    },
     {
         label: 'Oscillator',
         kind: 3, // Function
@@ -189,6 +268,90 @@ export const completionItems: monaco.languages.CompletionItem[] = [
         insertTextRules: 4,
         detail: 'Set the default note length',
     },
+    {
+        label: 'track.key',
+        kind: 9,
+        insertText: "track.key = '${1:D dorian}';",
+        insertTextRules: 4,
+        detail: 'Set the key for scale-degree notes (^1, ^3, ...)',
+    },
+    {
+        label: 'track.diatonicTranspose',
+        kind: 9,
+        insertText: 'track.diatonicTranspose = ${1:2};',
+        insertTextRules: 4,
+        detail: 'Transpose by scale steps within the key',
+    },
+    {
+        label: 'track.transpose',
+        kind: 9,
+        insertText: 'track.transpose = ${1:12};',
+        insertTextRules: 4,
+        detail: 'Transpose by semitones',
+    },
+    {
+        label: 'track.swing',
+        kind: 9,
+        insertText: 'track.swing = ${1:0.6};',
+        insertTextRules: 4,
+        detail: 'Swing: share of each pair of eighth notes taken by the first (0.5 = straight)',
+    },
+    {
+        label: 'track.swingUnit',
+        kind: 9,
+        insertText: 'track.swingUnit = ${1:1/4};',
+        insertTextRules: 4,
+        detail: 'Note length that swings (default 1/2 beat)',
+    },
+    {
+        label: 'track.groove',
+        kind: 9,
+        insertText: "track.groove = '${1:name}';",
+        insertTextRules: 4,
+        detail: 'Apply a groove template',
+    },
+    {
+        label: 'track.humanizeTiming',
+        kind: 9,
+        insertText: 'track.humanizeTiming = ${1:0.02};',
+        insertTextRules: 4,
+        detail: 'Random timing shift of up to this many beats (seeded by song.seed)',
+    },
+    {
+        label: 'track.humanizeVelocity',
+        kind: 9,
+        insertText: 'track.humanizeVelocity = ${1:8};',
+        insertTextRules: 4,
+        detail: 'Random velocity change of up to this much (seeded by song.seed)',
+    },
+    {
+        label: 'track.voicing',
+        kind: 9,
+        insertText: "track.voicing = '${1|close,drop2,spread|}';",
+        insertTextRules: 4,
+        detail: "How chord symbols such as 'Cmaj7/E' are voiced",
+    },
+    {
+        label: 'track.inversion',
+        kind: 9,
+        insertText: 'track.inversion = ${1:1};',
+        insertTextRules: 4,
+        detail: 'Invert chord symbols',
+    },
+    {
+        label: 'track.chordOctave',
+        kind: 9,
+        insertText: 'track.chordOctave = ${1:3};',
+        insertTextRules: 4,
+        detail: 'Octave of chord-symbol roots (default 4)',
+    },
+    {
+        label: 'track.arpeggio',
+        kind: 9,
+        insertText: "track.arpeggio = '${1|up,down,updown,random,none|}';",
+        insertTextRules: 4,
+        detail: 'Arpeggiate chord symbols across the step',
+    },
     {
         label: 'track.instrument',
         kind: 9,
@@ -211,4 +374,11 @@ export const completionItems: monaco.languages.CompletionItem[] = [
         detail: "Set song end mode: 'gate', 'release', or 'tail'",
         documentation: "Controls output length. 'gate' = hard cut at last note-off, 'release' = wait for envelope release, 'tail' = wait for effects tail (default).",
     },
+    {
+        label: 'song.seed',
+        kind: 9,
+        insertText: 'song.seed = ${1:1};',
+        insertTextRules: 4,
+        detail: 'Seed for random choices such as random arpeggios and humanize',
+    },
 ] as any;